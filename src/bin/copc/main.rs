@@ -0,0 +1,631 @@
+//! The `copc` command line tool.
+
+use copc_rs::{
+    convert, ept_to_copc, validate_path, BoundsSelection, ConvertOptions, CopcReader, CrsOverride,
+    CsvOptions, EptOptions, LodSelection, PlyFormat, PlyOptions, PointAttribute, PotreeOptions,
+    RasterFormat, RasterOptions, RasterProduct, StatisticsOptions, TileFormat, TilesOptions,
+    VoxelKey,
+};
+use las::{Bounds, Vector};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+type CliResult<T> = Result<T, Box<dyn Error>>;
+
+const USAGE: &str = "\
+Usage: copc <command> [options]
+
+Commands:
+  info <file> [--json]
+      Header, COPC info, CRS and hierarchy statistics
+  query <file> <output> [--bounds B] [--level L | --levels MIN,MAX | --resolution R]
+        [--columns C] [--precision N] [--ascii]
+      Write the points of a query to a .las, .laz, .copc.laz, .ply, .xyz, .csv or .tsv file,
+      the bounds are minx,miny,maxx,maxy or minx,miny,minz,maxx,maxy,maxz, the columns
+      of a .ply, .csv or .tsv file are attribute names like x,y,z,intensity,gps_time
+  convert <input> <output> [--epsg CODE] [--fallback-epsg CODE] [--min-node-size N]
+          [--max-node-size N] [--max-depth N] [--page-size N] [--seed N]
+      Convert a LAS/LAZ file to COPC
+  validate <file> [--json]
+      Check a file against the COPC 1.0 spec, exits with 1 if it is invalid
+  potree <file> <directory> [--name NAME] [--step-size N]
+      Write the octree to a Potree 2.0 dataset
+  tiles <file> <directory> [--glb]
+      Write the octree to a 3D Tiles tileset of .pnts or .glb tiles
+  raster <file> <output> [--cell-size C] [--product P] [--bounds B] [--nodata V] [--all-levels]
+      Write a raster of the points to a .tif GeoTIFF or a raw float32 file with a world file,
+      the products are dsm, dtm, min-z, density and intensity, the bounds are minx,miny,maxx,maxy,
+      only the levels with a spacing down to the cell size are read without --all-levels
+  ept <file> <directory> [--step-size N]
+      Write the octree to an Entwine Point Tile dataset
+  from-ept <directory> <output>
+      Convert an Entwine Point Tile dataset of LAZ nodes to COPC
+  stats <file> [--bounds B] [--attributes A] [--bins N] [--json]
+      Minimum, maximum, mean and histogram of attributes like z,intensity,classification,
+      the bounds are like for query
+  tree <file> [--max-level N] [--dot | --json]
+      Print the hierarchy, or the nodes grouped by hierarchy page as a Graphviz graph,
+      or the nodes with bounds and compression ratio, the levels and the pages as JSON";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(args) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run(args: Vec<String>) -> CliResult<ExitCode> {
+    let Some((command, args)) = args.split_first() else {
+        println!("{USAGE}");
+        return Ok(ExitCode::from(2));
+    };
+    match command.as_str() {
+        "info" => info(Args::parse(args, 1, &[], &["json"])?),
+        "query" => query(Args::parse(
+            args,
+            2,
+            &[
+                "bounds",
+                "level",
+                "levels",
+                "resolution",
+                "columns",
+                "precision",
+            ],
+            &["ascii"],
+        )?),
+        "convert" => convert_cmd(Args::parse(
+            args,
+            2,
+            &[
+                "epsg",
+                "fallback-epsg",
+                "min-node-size",
+                "max-node-size",
+                "max-depth",
+                "page-size",
+                "seed",
+            ],
+            &[],
+        )?),
+        "validate" => validate(Args::parse(args, 1, &[], &["json"])?),
+        "potree" => potree(Args::parse(args, 2, &["name", "step-size"], &[])?),
+        "tiles" => tiles(Args::parse(args, 2, &[], &["glb"])?),
+        "raster" => raster(Args::parse(
+            args,
+            2,
+            &["cell-size", "product", "bounds", "nodata"],
+            &["all-levels"],
+        )?),
+        "ept" => ept(Args::parse(args, 2, &["step-size"], &[])?),
+        "from-ept" => from_ept(Args::parse(args, 2, &[], &[])?),
+        "stats" => stats(Args::parse(
+            args,
+            1,
+            &["bounds", "attributes", "bins"],
+            &["json"],
+        )?),
+        "tree" => tree(Args::parse(args, 1, &["max-level"], &["dot", "json"])?),
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            Ok(ExitCode::SUCCESS)
+        }
+        _ => Err(format!("unknown command `{command}`\n\n{USAGE}").into()),
+    }
+}
+
+/// Positional arguments, `--name value` options and `--name` flags of a command
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+    flags: Vec<String>,
+}
+
+impl Args {
+    fn parse(
+        args: &[String],
+        positional: usize,
+        options: &[&str],
+        flags: &[&str],
+    ) -> CliResult<Self> {
+        let mut parsed = Args {
+            positional: vec![],
+            options: HashMap::new(),
+            flags: vec![],
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if options.contains(&name) => {
+                    let value = args.next().ok_or(format!("--{name} needs a value"))?;
+                    parsed.options.insert(name.to_string(), value.clone());
+                }
+                Some(name) if flags.contains(&name) => parsed.flags.push(name.to_string()),
+                Some(name) => return Err(format!("unknown option --{name}").into()),
+                None => parsed.positional.push(arg.clone()),
+            }
+        }
+        if parsed.positional.len() != positional {
+            return Err(format!(
+                "expected {positional} file arguments, got {}\n\n{USAGE}",
+                parsed.positional.len()
+            )
+            .into());
+        }
+        Ok(parsed)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|f| f == name)
+    }
+
+    fn value<T: std::str::FromStr>(&self, name: &str) -> CliResult<Option<T>> {
+        self.options
+            .get(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("invalid value `{value}` for --{name}").into())
+            })
+            .transpose()
+    }
+
+    fn values<T: std::str::FromStr>(&self, name: &str) -> CliResult<Option<Vec<T>>> {
+        self.options
+            .get(name)
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|v| v.trim().parse())
+                    .collect::<Result<Vec<T>, _>>()
+                    .map_err(|_| format!("invalid value `{value}` for --{name}").into())
+            })
+            .transpose()
+    }
+}
+
+fn info(args: Args) -> CliResult<ExitCode> {
+    let path = &args.positional[0];
+    let reader = CopcReader::from_path(path)?;
+    let header = reader.header();
+    let copc_info = reader.copc_info();
+    let bounds = header.bounds();
+    let levels = reader.level_summaries();
+
+    if args.flag("json") {
+        print!("{}", reader.info_json()?);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    println!("File:              {path}");
+    println!("LAS version:       {}", header.version());
+    println!("Point format:      {}", header.point_format().to_u8()?);
+    println!("Points:            {}", header.number_of_points());
+    println!(
+        "Bounds:            ({}, {}, {}) - ({}, {}, {})",
+        bounds.min.x, bounds.min.y, bounds.min.z, bounds.max.x, bounds.max.y, bounds.max.z
+    );
+    let t = header.transforms();
+    println!(
+        "Scale:             {} {} {}",
+        t.x.scale, t.y.scale, t.z.scale
+    );
+    println!(
+        "Offset:            {} {} {}",
+        t.x.offset, t.y.offset, t.z.offset
+    );
+    println!(
+        "Octree center:     {} {} {}",
+        copc_info.center.x, copc_info.center.y, copc_info.center.z
+    );
+    println!("Octree halfsize:   {}", copc_info.halfsize);
+    println!("Spacing:           {}", copc_info.spacing);
+    println!(
+        "Gps time:          {} - {}",
+        copc_info.gpstime_minimum, copc_info.gpstime_maximum
+    );
+    match reader.crs() {
+        Some(crs) => {
+            let epsg = crs
+                .epsg()
+                .map_or("unknown EPSG code".to_string(), |c| format!("EPSG:{c}"));
+            let unit = crs
+                .horizontal_unit()
+                .map_or("unknown unit".to_string(), |u| u.name.clone());
+            println!("CRS:               {epsg}, {unit}");
+            if let Some(datum) = crs.vertical_datum() {
+                println!("Vertical datum:    {datum}");
+            }
+        }
+        None => println!("CRS:               none"),
+    }
+    println!("Nodes:             {}", reader.hierarchy_entries().len());
+    println!("Hierarchy pages:   {}", reader.hierarchy_pages().len());
+    println!("Level     Nodes      Points       Bytes");
+    for level in &levels {
+        println!(
+            "{:>5} {:>9} {:>11} {:>11}",
+            level.level, level.nodes, level.points, level.bytes
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The `--bounds` of a query, minx,miny,maxx,maxy in the z range of the octree
+/// or minx,miny,minz,maxx,maxy,maxz
+fn bounds_selection(
+    args: &Args,
+    reader: &CopcReader<impl Read + Seek>,
+) -> CliResult<BoundsSelection> {
+    Ok(match args.values::<f64>("bounds")?.as_deref() {
+        None => BoundsSelection::All,
+        Some(&[min_x, min_y, max_x, max_y]) => BoundsSelection::Within(Bounds {
+            min: Vector {
+                x: min_x,
+                y: min_y,
+                z: reader.copc_info().center.z - reader.copc_info().halfsize,
+            },
+            max: Vector {
+                x: max_x,
+                y: max_y,
+                z: reader.copc_info().center.z + reader.copc_info().halfsize,
+            },
+        }),
+        Some(&[min_x, min_y, min_z, max_x, max_y, max_z]) => BoundsSelection::Within(Bounds {
+            min: Vector {
+                x: min_x,
+                y: min_y,
+                z: min_z,
+            },
+            max: Vector {
+                x: max_x,
+                y: max_y,
+                z: max_z,
+            },
+        }),
+        Some(_) => return Err("--bounds needs 4 or 6 values".into()),
+    })
+}
+
+fn query(args: Args) -> CliResult<ExitCode> {
+    let mut reader = CopcReader::from_path(&args.positional[0])?;
+    let output = Path::new(&args.positional[1]);
+
+    let bounds = bounds_selection(&args, &reader)?;
+    let lod = match (
+        args.value::<i32>("level")?,
+        args.values::<i32>("levels")?,
+        args.value::<f64>("resolution")?,
+    ) {
+        (None, None, None) => LodSelection::All,
+        (Some(level), None, None) => LodSelection::Level(level),
+        (None, Some(levels), None) => match levels.as_slice() {
+            &[min, max] => LodSelection::LevelMinMax(min, max),
+            _ => return Err("--levels needs 2 values".into()),
+        },
+        (None, None, Some(resolution)) => LodSelection::Resolution(resolution),
+        _ => return Err("only one of --level, --levels and --resolution can be used".into()),
+    };
+
+    let columns = args.options.get("columns").map(|columns| {
+        columns
+            .split(',')
+            .map(|c| c.trim().parse().unwrap_or_else(|e| match e {}))
+            .collect()
+    });
+
+    let extension = output
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let is_copc = output
+        .to_str()
+        .is_some_and(|p| p.to_lowercase().ends_with(".copc.laz"));
+    let count = match extension.as_str() {
+        "laz" if is_copc => {
+            reader
+                .export_copc(output, lod, bounds, &ConvertOptions::default())?
+                .points_written
+        }
+        "las" | "laz" => reader.export_las(output, lod, bounds)?.points_written,
+        "ply" => {
+            let options = PlyOptions {
+                format: if args.flag("ascii") {
+                    PlyFormat::Ascii
+                } else {
+                    PlyFormat::BinaryLittleEndian
+                },
+                attributes: columns,
+                ..Default::default()
+            };
+            reader.export_ply(output, lod, bounds, &options)?.points_written
+        }
+        "xyz" | "txt" | "csv" | "tsv" => {
+            let mut options = CsvOptions {
+                columns,
+                precision: args.value("precision")?,
+                ..Default::default()
+            };
+            match extension.as_str() {
+                "csv" => (),
+                "tsv" => options.delimiter = '\t',
+                _ => {
+                    options.delimiter = ' ';
+                    options.header = false;
+                    options.columns.get_or_insert_with(|| {
+                        vec![PointAttribute::X, PointAttribute::Y, PointAttribute::Z]
+                    });
+                }
+            }
+            reader.export_csv(output, lod, bounds, &options)?.points_written
+        }
+        _ => {
+            return Err(format!(
+                "unknown output format `{extension}`, use .las, .laz, .copc.laz, .ply, .xyz, .csv or .tsv"
+            )
+            .into())
+        }
+    };
+    println!("Wrote {count} points to {}", output.display());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn convert_cmd(args: Args) -> CliResult<ExitCode> {
+    let mut options = ConvertOptions {
+        crs: args.value("epsg")?.map(CrsOverride::Epsg),
+        fallback_crs: args.value("fallback-epsg")?.map(CrsOverride::Epsg),
+        max_depth: args.value("max-depth")?,
+        hierarchy_page_size: args.value("page-size")?,
+        seed: args.value("seed")?,
+        ..Default::default()
+    };
+    if let Some(size) = args.value("min-node-size")? {
+        options.min_node_size = size;
+    }
+    if let Some(size) = args.value("max-node-size")? {
+        options.max_node_size = size;
+    }
+
+    let summary = convert(&args.positional[0], &args.positional[1], &options)?;
+    println!("Points read:       {}", summary.points_read);
+    println!("Points written:    {}", summary.points_written);
+    if summary.read_errors > 0 {
+        println!("Read errors:       {}", summary.read_errors);
+    }
+    if summary.rejected.total() > 0 {
+        println!("Rejected points:   {}", summary.rejected.total());
+    }
+    println!("Nodes:             {}", summary.node_count);
+    println!("Depth:             {}", summary.depth);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn potree(args: Args) -> CliResult<ExitCode> {
+    let mut reader = CopcReader::from_path(&args.positional[0])?;
+    let mut options = PotreeOptions {
+        name: args.value("name")?,
+        ..Default::default()
+    };
+    if let Some(step_size) = args.value("step-size")? {
+        options.hierarchy_step_size = step_size;
+    }
+
+    let summary = reader.export_potree(&args.positional[1], &options)?;
+    println!("Points:            {}", summary.points_written);
+    println!("Nodes:             {}", summary.nodes_written);
+    println!("Depth:             {}", summary.depth);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn tiles(args: Args) -> CliResult<ExitCode> {
+    let mut reader = CopcReader::from_path(&args.positional[0])?;
+    let options = TilesOptions {
+        format: if args.flag("glb") {
+            TileFormat::Glb
+        } else {
+            TileFormat::Pnts
+        },
+    };
+
+    let summary = reader.export_tiles(&args.positional[1], &options)?;
+    println!("Points:            {}", summary.points_written);
+    println!("Tiles:             {}", summary.tiles_written);
+    println!("Depth:             {}", summary.depth);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn raster(args: Args) -> CliResult<ExitCode> {
+    let mut reader = CopcReader::from_path(&args.positional[0])?;
+    let output = Path::new(&args.positional[1]);
+    let mut options = RasterOptions {
+        format: match output.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("tif") || e.eq_ignore_ascii_case("tiff") => {
+                RasterFormat::GeoTiff
+            }
+            _ => RasterFormat::Raw,
+        },
+        ..Default::default()
+    };
+    if let Some(cell_size) = args.value("cell-size")? {
+        options.cell_size = cell_size;
+    }
+    if let Some(nodata) = args.value("nodata")? {
+        options.nodata = nodata;
+    }
+    if args.flag("all-levels") {
+        options.lod = Some(LodSelection::All);
+    }
+    if let Some(product) = args.options.get("product") {
+        options.product = match product.as_str() {
+            "dsm" => RasterProduct::Dsm,
+            "dtm" => RasterProduct::Dtm,
+            "min-z" => RasterProduct::MinZ,
+            "density" => RasterProduct::Density,
+            "intensity" => RasterProduct::Intensity,
+            _ => return Err(format!("unknown raster product `{product}`").into()),
+        };
+    }
+    match args.values::<f64>("bounds")?.as_deref() {
+        None => (),
+        Some(&[min_x, min_y, max_x, max_y]) => {
+            let z = reader.header().bounds();
+            options.bounds = Some(Bounds {
+                min: Vector {
+                    x: min_x,
+                    y: min_y,
+                    z: z.min.z,
+                },
+                max: Vector {
+                    x: max_x,
+                    y: max_y,
+                    z: z.max.z,
+                },
+            });
+        }
+        Some(_) => return Err("--bounds needs 4 values".into()),
+    }
+
+    let summary = reader.export_raster(output, &options)?;
+    println!("Size:              {} x {}", summary.width, summary.height);
+    println!("Points:            {}", summary.points_used);
+    println!("Cells with data:   {}", summary.cells_with_data);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn ept(args: Args) -> CliResult<ExitCode> {
+    let mut reader = CopcReader::from_path(&args.positional[0])?;
+    let options = EptOptions {
+        hierarchy_step: args.value("step-size")?,
+    };
+
+    let summary = reader.export_ept(&args.positional[1], &options)?;
+    println!("Points:            {}", summary.points_written);
+    println!("Nodes:             {}", summary.nodes_written);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn from_ept(args: Args) -> CliResult<ExitCode> {
+    let summary = ept_to_copc(&args.positional[0], &args.positional[1])?;
+    println!("Points:            {}", summary.points_written);
+    println!("Nodes:             {}", summary.nodes_written);
+    println!("Reused chunks:     {}", summary.chunks_reused);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn validate(args: Args) -> CliResult<ExitCode> {
+    let report = validate_path(&args.positional[0])?;
+
+    if args.flag("json") {
+        print!("{}", report.to_json());
+    } else {
+        for issue in &report.issues {
+            let key = issue
+                .key
+                .as_ref()
+                .map_or(String::new(), |k| format!(" [{k}]"));
+            println!(
+                "{:?} {:?}{key}: {}",
+                issue.severity, issue.check, issue.message
+            );
+        }
+        println!(
+            "{} errors, {} warnings, {} points in {} nodes checked",
+            report.errors().count(),
+            report.warnings().count(),
+            report.points_checked,
+            report.nodes_checked
+        );
+    }
+
+    Ok(if report.is_valid() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    })
+}
+
+fn stats(args: Args) -> CliResult<ExitCode> {
+    let mut reader = CopcReader::from_path(&args.positional[0])?;
+    let bounds = bounds_selection(&args, &reader)?;
+    let mut options = StatisticsOptions {
+        attributes: args.options.get("attributes").map(|attributes| {
+            attributes
+                .split(',')
+                .map(|a| a.trim().parse().unwrap_or_else(|e| match e {}))
+                .collect()
+        }),
+        ..Default::default()
+    };
+    if let Some(bins) = args.value("bins")? {
+        options.bins = bins;
+    }
+
+    let statistics = reader
+        .statistics(LodSelection::All, bounds, &options)?
+        .total;
+    if args.flag("json") {
+        print!("{}", statistics.to_json());
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    println!("Points:            {}", statistics.point_count);
+    println!("Attribute                 Count          Min          Max         Mean");
+    for a in &statistics.attributes {
+        match a.mean() {
+            Some(mean) => println!(
+                "{:<17} {:>13} {:>12} {:>12} {:>12.3}",
+                a.attribute.to_string(),
+                a.count,
+                a.min,
+                a.max,
+                mean
+            ),
+            None => println!("{:<17} {:>13}", a.attribute.to_string(), 0),
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn tree(args: Args) -> CliResult<ExitCode> {
+    let reader = CopcReader::from_path(&args.positional[0])?;
+    if args.flag("dot") {
+        print!("{}", reader.hierarchy_dot());
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.flag("json") {
+        print!("{}", reader.hierarchy_json());
+        return Ok(ExitCode::SUCCESS);
+    }
+    let max_level = args.value("max-level")?.unwrap_or(i32::MAX);
+    let entries = reader.hierarchy_entries();
+
+    let mut out = std::io::stdout().lock();
+    let mut stack = vec![VoxelKey::root()];
+    while let Some(key) = stack.pop() {
+        let Some(entry) = entries.get(&key) else {
+            continue;
+        };
+        writeln!(
+            out,
+            "{:indent$}{}  points: {}  bytes: {}",
+            "",
+            key,
+            entry.point_count,
+            entry.byte_size,
+            indent = 2 * key.level as usize
+        )?;
+        if key.level < max_level {
+            // reversed, so the children are printed in order
+            stack.extend(key.children().into_iter().rev());
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
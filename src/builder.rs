@@ -0,0 +1,352 @@
+//! COPC writer builder.
+
+use crate::writer::{check_copc_extension, CopcWriter};
+use crate::{
+    ExtraBytes, ExtraBytesDescriptor, ExtraBytesType, StatisticsOptions, WriterConfigError,
+};
+
+use las::{Bounds, Header, Transform, Vector};
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, Write};
+use std::path::Path;
+
+/// Strategy for distributing the points over the levels of the octree
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SamplingStrategy {
+    /// Stochastic filling if the number of points passed to [CopcWriter::write]
+    /// is at least `max_node_size + min_node_size`, greedy filling otherwise
+    #[default]
+    Auto,
+    /// Fill the first non-full node containing the point,
+    /// only gives a good LOD distribution for randomly ordered input
+    Greedy,
+    /// Add the point at (weighted) random to one of the nodes containing it,
+    /// needs the number of points passed to [CopcWriter::write]
+    Stochastic,
+}
+
+/// How the writer handles points without gps time
+///
+/// Synthesized gps times are not included in the gps time range of the COPC info VLR
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum GpsTimePolicy {
+    /// Use the index of the point in the written points as gps time
+    #[default]
+    FromPointIndex,
+    /// Use a constant gps time
+    Constant(f64),
+    /// Reject the point with [crate::PointAddError::MissingGpsTime]
+    Error,
+}
+
+/// CRS to write to the WKT CRS VLR instead of the one found in the header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CrsOverride {
+    /// A WKT string
+    Wkt(String),
+    /// An EPSG code looked up in the crs-definitions library
+    Epsg(u16),
+}
+
+impl CrsOverride {
+    /// The WKT of the CRS
+    pub(crate) fn wkt(&self) -> crate::Result<Vec<u8>> {
+        match self {
+            CrsOverride::Wkt(wkt) => Ok(wkt.as_bytes().to_owned()),
+            CrsOverride::Epsg(epsg) => crs_definitions::from_code(*epsg)
+                .map(|def| def.wkt.as_bytes().to_owned())
+                .ok_or(crate::Error::InvalidEPSGCode(*epsg)),
+        }
+    }
+}
+
+/// Builder for a [CopcWriter] with explicit configuration
+///
+/// All options are validated once when calling [build] or [build_path]
+/// and any invalid option is reported as a [crate::WriterConfigError]
+///
+/// ```no_run
+/// use copc_rs::{CopcWriterBuilder, SamplingStrategy};
+///
+/// let header = las::Reader::from_path("./lidar.las").unwrap().header().clone();
+/// let writer = CopcWriterBuilder::new(header)
+///     .max_node_size(50_000)
+///     .max_depth(12)
+///     .sampling(SamplingStrategy::Stochastic)
+///     .build_path("./lidar.copc.laz")
+///     .unwrap();
+/// ```
+///
+/// [build]: Self::build
+/// [build_path]: Self::build_path
+#[derive(Clone, Debug)]
+pub struct CopcWriterBuilder {
+    pub(crate) header: Header,
+    pub(crate) min_node_size: i32,
+    pub(crate) max_node_size: i32,
+    pub(crate) max_depth: Option<i32>,
+    pub(crate) sampling: SamplingStrategy,
+    pub(crate) seed: Option<u64>,
+    pub(crate) hierarchy_page_size: Option<usize>,
+    pub(crate) crs: Option<CrsOverride>,
+    pub(crate) fallback_crs: Option<CrsOverride>,
+    pub(crate) transforms: Option<Vector<Transform>>,
+    pub(crate) bounds: Option<Bounds>,
+    pub(crate) auto_bounds: Option<f64>,
+    pub(crate) extra_bytes: Option<u16>,
+    pub(crate) extra_attributes: Vec<ExtraBytesDescriptor>,
+    pub(crate) upgrade_pdrf: bool,
+    pub(crate) gps_time_policy: GpsTimePolicy,
+    pub(crate) statistics: Option<StatisticsOptions>,
+}
+
+impl CopcWriterBuilder {
+    /// Creates a builder for a writer configured with the provided [las::Header]
+    ///
+    /// See [CopcWriter::new] for how the header is used
+    pub fn new(header: Header) -> Self {
+        CopcWriterBuilder {
+            header,
+            min_node_size: crate::MIN_NODE_SIZE_DEFAULT,
+            max_node_size: crate::MAX_NODE_SIZE_DEFAULT,
+            max_depth: None,
+            sampling: SamplingStrategy::default(),
+            seed: None,
+            hierarchy_page_size: None,
+            crs: None,
+            fallback_crs: None,
+            transforms: None,
+            bounds: None,
+            auto_bounds: None,
+            extra_bytes: None,
+            extra_attributes: Vec::new(),
+            upgrade_pdrf: true,
+            gps_time_policy: GpsTimePolicy::default(),
+            statistics: None,
+        }
+    }
+
+    /// The minimal number of [las::Point]s an octree node can hold, this is a hard limit
+    ///
+    /// Defaults to 256
+    pub fn min_node_size(mut self, min_node_size: i32) -> Self {
+        self.min_node_size = min_node_size;
+        self
+    }
+
+    /// The maximal number of [las::Point]s an octree node can hold, this is a soft limit
+    ///
+    /// Defaults to 16384
+    pub fn max_node_size(mut self, max_node_size: i32) -> Self {
+        self.max_node_size = max_node_size;
+        self
+    }
+
+    /// The deepest octree level points are added to, the root node is level 0
+    ///
+    /// Nodes at this level are never split and may hold more than `max_node_size` points.
+    /// Unlimited by default
+    pub fn max_depth(mut self, max_depth: i32) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// The strategy for distributing points over the octree levels
+    pub fn sampling(mut self, sampling: SamplingStrategy) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Seed for the random number generator of the stochastic sampling
+    ///
+    /// Writing the same points with the same header, configuration and seed
+    /// gives byte-identical output. Without a seed the generator is seeded randomly
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// The maximal number of entries in a hierarchy page
+    ///
+    /// By default all entries are written to a single page
+    pub fn hierarchy_page_size(mut self, hierarchy_page_size: usize) -> Self {
+        self.hierarchy_page_size = Some(hierarchy_page_size);
+        self
+    }
+
+    /// Write this CRS instead of the one defined in the header
+    pub fn crs(mut self, crs: CrsOverride) -> Self {
+        self.crs = Some(crs);
+        self
+    }
+
+    /// Write this CRS if the header does not define one, instead of failing with
+    /// [crate::Error::NoCRSDefined]
+    pub fn fallback_crs(mut self, crs: CrsOverride) -> Self {
+        self.fallback_crs = Some(crs);
+        self
+    }
+
+    /// Use these scales and offsets instead of the ones in the header
+    pub fn transforms(mut self, transforms: Vector<Transform>) -> Self {
+        self.transforms = Some(transforms);
+        self
+    }
+
+    /// Use these bounds for the octree instead of the ones in the header
+    pub fn bounds(mut self, bounds: Bounds) -> Self {
+        self.bounds = Some(bounds);
+        self
+    }
+
+    /// Compute the bounds from the written points instead of trusting the header
+    ///
+    /// The scales are set to `precision` (coarsened if the extent can not be represented)
+    /// and the offsets to the center of the bounds, overriding [transforms] and [bounds].
+    /// All points passed to [CopcWriter::write] are collected in memory for this,
+    /// to avoid that, scan the points beforehand and set [bounds] and [transforms]
+    ///
+    /// [bounds]: Self::bounds
+    /// [transforms]: Self::transforms
+    pub fn auto_bounds(mut self, precision: f64) -> Self {
+        self.auto_bounds = Some(precision);
+        self
+    }
+
+    /// The number of extra bytes of each point instead of the number in the header
+    ///
+    /// Any extra bytes VLR in the header no longer describes the layout and is not forwarded
+    pub fn extra_bytes(mut self, extra_bytes: u16) -> Self {
+        self.extra_bytes = Some(extra_bytes);
+        self
+    }
+
+    /// Declares a new extra attribute, stored after the extra bytes of the header
+    ///
+    /// The descriptors of the header and the declared attributes are written to the extra bytes VLR,
+    /// the points passed to [CopcWriter::write] must have the extra bytes of all of them,
+    /// see [ExtraBytes::set]
+    pub fn extra_attribute(mut self, descriptor: ExtraBytesDescriptor) -> Self {
+        self.extra_attributes.push(descriptor);
+        self
+    }
+
+    /// Whether PDRFs other than 6, 7 and 8 are converted, or rejected
+    ///
+    /// See [CopcWriter::new] for the conversions
+    ///
+    /// Defaults to `true`
+    pub fn upgrade_pdrf(mut self, upgrade_pdrf: bool) -> Self {
+        self.upgrade_pdrf = upgrade_pdrf;
+        self
+    }
+
+    /// How points without gps time are handled
+    ///
+    /// Defaults to [GpsTimePolicy::FromPointIndex]
+    pub fn gps_time_policy(mut self, gps_time_policy: GpsTimePolicy) -> Self {
+        self.gps_time_policy = gps_time_policy;
+        self
+    }
+
+    /// Computes the statistics of the written points and stores them in an EVLR,
+    /// read them with [crate::CopcReader::stored_statistics]
+    pub fn statistics(mut self, options: StatisticsOptions) -> Self {
+        self.statistics = Some(options);
+        self
+    }
+
+    /// Validates the configuration and creates a [CopcWriter] for the write- and seekable `write`
+    pub fn build<'a, W: 'a + Write + Seek>(self, write: W) -> crate::Result<CopcWriter<'a, W>> {
+        self.validate().map_err(crate::Error::InvalidWriterConfig)?;
+        CopcWriter::from_builder(write, self)
+    }
+
+    /// Validates the configuration, creates a file at `path`, wraps it in a BufWrite
+    /// and passes it along to [build]
+    ///
+    /// [build]: Self::build
+    pub fn build_path<'a, P: AsRef<Path>>(
+        self,
+        path: P,
+    ) -> crate::Result<CopcWriter<'a, BufWriter<File>>> {
+        check_copc_extension(path.as_ref())?;
+        self.validate().map_err(crate::Error::InvalidWriterConfig)?;
+
+        File::create(path)
+            .map_err(crate::Error::from)
+            .and_then(|file| CopcWriter::from_builder(BufWriter::new(file), self))
+    }
+
+    fn validate(&self) -> Result<(), WriterConfigError> {
+        if self.min_node_size < 1 {
+            return Err(WriterConfigError::InvalidMinNodeSize(self.min_node_size));
+        }
+        if self.max_node_size <= self.min_node_size {
+            return Err(WriterConfigError::InvalidMaxNodeSize {
+                min: self.min_node_size,
+                max: self.max_node_size,
+            });
+        }
+        if let Some(max_depth) = self.max_depth {
+            if max_depth < 0 {
+                return Err(WriterConfigError::InvalidMaxDepth(max_depth));
+            }
+        }
+        if let Some(page_size) = self.hierarchy_page_size {
+            if page_size < 9 {
+                return Err(WriterConfigError::InvalidHierarchyPageSize(page_size));
+            }
+        }
+        for crs in [&self.crs, &self.fallback_crs] {
+            match crs {
+                Some(CrsOverride::Wkt(wkt)) if wkt.trim().is_empty() => {
+                    return Err(WriterConfigError::EmptyCrsWkt);
+                }
+                Some(CrsOverride::Epsg(epsg)) if crs_definitions::from_code(*epsg).is_none() => {
+                    return Err(WriterConfigError::UnknownEpsgCode(*epsg));
+                }
+                _ => (),
+            }
+        }
+        if let Some(transforms) = &self.transforms {
+            for t in [transforms.x, transforms.y, transforms.z] {
+                if !t.scale.is_normal() || !t.scale.is_sign_positive() || !t.offset.is_finite() {
+                    return Err(WriterConfigError::InvalidTransform(t));
+                }
+            }
+        }
+        if let GpsTimePolicy::Constant(gps_time) = self.gps_time_policy {
+            if !gps_time.is_finite() {
+                return Err(WriterConfigError::InvalidGpsTime(gps_time));
+            }
+        }
+        if let Some(precision) = self.auto_bounds {
+            if !precision.is_normal() || !precision.is_sign_positive() {
+                return Err(WriterConfigError::InvalidPrecision(precision));
+            }
+        }
+        let mut names: Vec<String> = ExtraBytes::from_header(&self.header)
+            .map(|e| e.descriptors().iter().map(|d| d.name.clone()).collect())
+            .unwrap_or_default();
+        for descriptor in &self.extra_attributes {
+            if descriptor.name.is_empty()
+                || descriptor.name.len() > 32
+                || descriptor.description.len() > 32
+                || names.contains(&descriptor.name)
+                || descriptor.data_type == ExtraBytesType::Undocumented(0)
+            {
+                return Err(WriterConfigError::InvalidExtraAttribute(
+                    descriptor.name.clone(),
+                ));
+            }
+            names.push(descriptor.name.clone());
+        }
+        let pdrf = self.header.point_format().to_u8().unwrap_or(0) & 0b00111111;
+        if !self.upgrade_pdrf && !matches!(pdrf, 6..=8) {
+            return Err(WriterConfigError::PdrfUpgradeDisabled(pdrf));
+        }
+        Ok(())
+    }
+}
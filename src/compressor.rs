@@ -71,6 +71,28 @@ impl<'a, W: Write + Seek + 'a> CopcCompressor<'a, W> {
         Ok((written_chunk_entry, old_chunk_start_pos))
     }
 
+    /// Appends a chunk already compressed with the laz vlr of the compressor
+    pub(crate) fn copy_chunk(
+        &mut self,
+        chunk: &[u8],
+        point_count: u64,
+    ) -> std::io::Result<(ChunkTableEntry, u64)> {
+        self.record_compressor.get_mut().write_all(chunk)?;
+        let entry = ChunkTableEntry {
+            point_count,
+            byte_count: chunk.len() as u64,
+        };
+        self.chunk_table.push(entry);
+
+        let chunk_start_pos = self.chunk_start_pos;
+        self.chunk_start_pos += entry.byte_count;
+        Ok((entry, chunk_start_pos))
+    }
+
+    pub(crate) fn vlr(&self) -> &LazVlr {
+        &self.vlr
+    }
+
     /// Must be called when you have compressed all your points.
     pub(crate) fn done(&mut self) -> std::io::Result<()> {
         self.record_compressor.done()?;
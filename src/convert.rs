@@ -0,0 +1,477 @@
+//! Conversion of LAS/LAZ files to COPC.
+
+use crate::builder::{CopcWriterBuilder, CrsOverride, GpsTimePolicy, SamplingStrategy};
+use crate::crs;
+use crate::writer::{pad_flat_bounds, transforms_for_bounds, CopcWriter, RejectedPoints};
+use crate::{BoundsSelection, CopcReader, LodSelection};
+
+use las::point::Format;
+use las::{Bounds, Builder, Header, Point, Reader};
+
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// Options for [convert]
+#[derive(Clone, Debug)]
+pub struct ConvertOptions {
+    /// See [CopcWriterBuilder::min_node_size]
+    pub min_node_size: i32,
+    /// See [CopcWriterBuilder::max_node_size]
+    pub max_node_size: i32,
+    /// See [CopcWriterBuilder::max_depth]
+    pub max_depth: Option<i32>,
+    /// See [CopcWriterBuilder::sampling]
+    pub sampling: SamplingStrategy,
+    /// See [CopcWriterBuilder::seed]
+    pub seed: Option<u64>,
+    /// See [CopcWriterBuilder::hierarchy_page_size]
+    pub hierarchy_page_size: Option<usize>,
+    /// See [CopcWriterBuilder::crs]
+    pub crs: Option<CrsOverride>,
+    /// See [CopcWriterBuilder::fallback_crs]
+    pub fallback_crs: Option<CrsOverride>,
+    /// See [CopcWriterBuilder::gps_time_policy]
+    pub gps_time_policy: GpsTimePolicy,
+    /// Compute the bounds from the points in a first pass over the input
+    /// instead of trusting the input header
+    pub recompute_bounds: bool,
+    /// Scale for new transforms computed from the bounds,
+    /// if `None` the transforms of the input header are kept
+    ///
+    /// Only used when `recompute_bounds` is set
+    pub precision: Option<f64>,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        ConvertOptions {
+            min_node_size: crate::MIN_NODE_SIZE_DEFAULT,
+            max_node_size: crate::MAX_NODE_SIZE_DEFAULT,
+            max_depth: None,
+            sampling: SamplingStrategy::default(),
+            seed: None,
+            hierarchy_page_size: None,
+            crs: None,
+            fallback_crs: None,
+            gps_time_policy: GpsTimePolicy::default(),
+            recompute_bounds: true,
+            precision: None,
+        }
+    }
+}
+
+impl ConvertOptions {
+    fn builder(&self, header: Header) -> CopcWriterBuilder {
+        let mut builder = CopcWriterBuilder::new(header)
+            .min_node_size(self.min_node_size)
+            .max_node_size(self.max_node_size)
+            .sampling(self.sampling)
+            .gps_time_policy(self.gps_time_policy);
+        if let Some(max_depth) = self.max_depth {
+            builder = builder.max_depth(max_depth);
+        }
+        if let Some(seed) = self.seed {
+            builder = builder.seed(seed);
+        }
+        if let Some(page_size) = self.hierarchy_page_size {
+            builder = builder.hierarchy_page_size(page_size);
+        }
+        if let Some(crs) = &self.crs {
+            builder = builder.crs(crs.clone());
+        }
+        if let Some(crs) = &self.fallback_crs {
+            builder = builder.fallback_crs(crs.clone());
+        }
+        builder
+    }
+}
+
+/// Summary of a [convert]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConversionSummary {
+    /// Number of points read from the input
+    pub points_read: u64,
+    /// Number of points written to the COPC file
+    pub points_written: u64,
+    /// Number of points that could not be read from the input
+    pub read_errors: u64,
+    /// Number of read points rejected by the writer, by reason
+    pub rejected: RejectedPoints,
+    /// Number of octree nodes with points
+    pub node_count: usize,
+    /// Deepest level of the octree
+    pub depth: i32,
+}
+
+/// Converts the LAS/LAZ file at `input_path` to a COPC file at `output_path`
+///
+/// Any LAS version and point data record format is accepted,
+/// see [crate::CopcWriter::new] for the conversions of the header.
+/// Points that can not be read or written are skipped and counted in the summary.
+///
+/// ```no_run
+/// use copc_rs::{convert, ConvertOptions};
+///
+/// let summary = convert("./lidar.las", "./lidar.copc.laz", &ConvertOptions::default()).unwrap();
+/// println!("{} points written", summary.points_written);
+/// ```
+pub fn convert<P: AsRef<Path>, Q: AsRef<Path>>(
+    input_path: P,
+    output_path: Q,
+    options: &ConvertOptions,
+) -> crate::Result<ConversionSummary> {
+    let mut las_reader = Reader::from_path(input_path.as_ref())?;
+    let header = las_reader.header().clone();
+    let mut num_points = header.number_of_points();
+    let transforms = *header.transforms();
+
+    let mut builder = options.builder(header);
+
+    if options.recompute_bounds {
+        // first pass, only the readable points are of interest
+        let mut bounds = Bounds::default();
+        num_points = 0;
+        for p in las_reader.points().filter_map(las::Result::ok) {
+            bounds.grow(&p);
+            num_points += 1;
+        }
+
+        if num_points > 0 {
+            let transforms = match options.precision {
+                Some(precision) => {
+                    let transforms = transforms_for_bounds(&bounds, precision);
+                    builder = builder.transforms(transforms);
+                    transforms
+                }
+                None => transforms,
+            };
+            builder = builder.bounds(pad_flat_bounds(bounds, &transforms));
+        }
+        las_reader = Reader::from_path(input_path.as_ref())?;
+    }
+
+    let mut copc_writer = builder.build_path(output_path)?;
+
+    let mut points_read = 0;
+    let mut read_errors = 0;
+    let points = las_reader.points().filter_map(|p| match p {
+        Ok(p) => {
+            points_read += 1;
+            Some(p)
+        }
+        Err(e) => {
+            log::warn!("Skipping a point that could not be read: {e}");
+            read_errors += 1;
+            None
+        }
+    });
+
+    let mut summary = write_points(&mut copc_writer, points, num_points)?;
+    summary.points_read = points_read;
+    summary.read_errors = read_errors;
+    Ok(summary)
+}
+
+/// Rewrites the COPC file at `input_path` to a COPC file at `output_path`
+/// with the node sizes, hierarchy paging and sampling of `options`
+///
+/// The points are streamed node by node from the input, they are never all in memory.
+/// The input header is kept, with `recompute_bounds` the bounds are recomputed
+/// in a first streaming pass over the input.
+/// The points of the input are not randomly ordered,
+/// use [SamplingStrategy::Auto] or [SamplingStrategy::Stochastic] for a good LOD distribution
+///
+/// ```no_run
+/// use copc_rs::{recopc, ConvertOptions};
+///
+/// let options = ConvertOptions { max_node_size: 100_000, recompute_bounds: false, ..Default::default() };
+/// recopc("./lidar.copc.laz", "./lidar-large-nodes.copc.laz", &options).unwrap();
+/// ```
+pub fn recopc<P: AsRef<Path>, Q: AsRef<Path>>(
+    input_path: P,
+    output_path: Q,
+    options: &ConvertOptions,
+) -> crate::Result<ConversionSummary> {
+    let mut copc_reader = CopcReader::from_path(input_path)?;
+    copc_from_query(
+        &mut copc_reader,
+        LodSelection::All,
+        BoundsSelection::All,
+        output_path,
+        options,
+    )
+}
+
+/// Writes the points of a query of `copc_reader` to a COPC file at `output_path`
+///
+/// The points are counted in a first pass if they are not all queried
+pub(crate) fn copc_from_query<R: Read + Seek, P: AsRef<Path>>(
+    copc_reader: &mut CopcReader<R>,
+    levels: LodSelection,
+    bounds: BoundsSelection,
+    output_path: P,
+    options: &ConvertOptions,
+) -> crate::Result<ConversionSummary> {
+    let header = copc_reader.header().clone();
+    let mut num_points = header.number_of_points();
+    let is_query = (levels, bounds) != (LodSelection::All, BoundsSelection::All);
+
+    let mut query_bounds = None;
+    if (options.recompute_bounds || is_query) && num_points > 0 {
+        let mut points_bounds = Bounds::default();
+        num_points = 0;
+        for p in copc_reader.points(levels, bounds)? {
+            points_bounds.grow(&p);
+            num_points += 1;
+        }
+        query_bounds = Some(points_bounds);
+    }
+
+    let mut points_read = 0;
+    let points = copc_reader
+        .points(levels, bounds)?
+        .inspect(|_| points_read += 1);
+    let mut summary = copc_from_points(
+        header,
+        num_points,
+        query_bounds,
+        points,
+        output_path,
+        options,
+    )?;
+    summary.points_read = points_read;
+    Ok(summary)
+}
+
+/// Writes the `num_points` points to a COPC file at `output_path` with the `header` of their source
+///
+/// With `recompute_bounds` the octree is fitted to the `bounds` of the points if they are known
+pub(crate) fn copc_from_points<P: AsRef<Path>, I: IntoIterator<Item = Point>>(
+    header: Header,
+    num_points: u64,
+    bounds: Option<Bounds>,
+    points: I,
+    output_path: P,
+    options: &ConvertOptions,
+) -> crate::Result<ConversionSummary> {
+    let transforms = *header.transforms();
+    let mut builder = options.builder(header);
+    if let Some(bounds) = bounds.filter(|_| options.recompute_bounds && num_points > 0) {
+        let transforms = match options.precision {
+            Some(precision) => {
+                let transforms = transforms_for_bounds(&bounds, precision);
+                builder = builder.transforms(transforms);
+                transforms
+            }
+            None => transforms,
+        };
+        builder = builder.bounds(pad_flat_bounds(bounds, &transforms));
+    }
+
+    let mut copc_writer = builder.build_path(output_path)?;
+    write_points(&mut copc_writer, points, num_points)
+}
+
+/// Options for [merge]
+#[derive(Clone, Debug, Default)]
+pub struct MergeOptions {
+    /// Options of the conversion, `precision` defaults to the finest scale of the inputs
+    pub convert: ConvertOptions,
+    /// Set the point source ID of each point to the index of its input
+    pub tag_source: bool,
+}
+
+/// Merges the LAS/LAZ/COPC files at `input_paths` into a single COPC file at `output_path`
+///
+/// The inputs are reconciled into one header:
+/// - the point data record format is the smallest of 6, 7 and 8 holding the attributes of all inputs,
+///   missing colors and NIR are zero
+/// - the number of extra bytes is the largest of the inputs, shorter extra bytes are zero-padded.
+///   The extra bytes descriptors are only kept if they are the same for all inputs
+/// - the bounds are combined and the scales are the finest scale of the inputs
+/// - all inputs must have the same CRS, unless it is overridden in the options
+///
+/// ```no_run
+/// use copc_rs::{merge, MergeOptions};
+///
+/// let options = MergeOptions { tag_source: true, ..Default::default() };
+/// let summary = merge(&["./line_1.las", "./line_2.laz"], "./survey.copc.laz", &options).unwrap();
+/// println!("{} points written", summary.points_written);
+/// ```
+pub fn merge<P: AsRef<Path>, Q: AsRef<Path>>(
+    input_paths: &[P],
+    output_path: Q,
+    options: &MergeOptions,
+) -> crate::Result<ConversionSummary> {
+    let mut readers = input_paths
+        .iter()
+        .map(|p| Reader::from_path(p.as_ref()))
+        .collect::<las::Result<Vec<_>>>()?;
+    let headers: Vec<Header> = readers.iter().map(|r| r.header().clone()).collect();
+    let Some(first) = headers.first() else {
+        return Err(crate::Error::NoMergeInputs);
+    };
+
+    // the superset of the point formats
+    let has_nir = headers.iter().any(|h| h.point_format().has_nir);
+    let has_color = has_nir || headers.iter().any(|h| h.point_format().has_color);
+    let extra_bytes = headers
+        .iter()
+        .map(|h| h.point_format().extra_bytes)
+        .max()
+        .unwrap_or(0);
+    let mut format = Format::new(match (has_color, has_nir) {
+        (_, true) => 8,
+        (true, false) => 7,
+        (false, false) => 6,
+    })?;
+    format.extra_bytes = extra_bytes;
+
+    let mut builder = Builder::from(first.clone());
+    builder.version = las::Version::new(1, 4);
+    builder.point_format = format;
+    let header = builder.into_header()?;
+
+    let convert = &options.convert;
+    let mut builder = convert.builder(header);
+
+    let descriptors = extra_bytes_descriptors(first);
+    if headers.iter().any(|h| {
+        h.point_format().extra_bytes != extra_bytes || extra_bytes_descriptors(h) != descriptors
+    }) {
+        builder = builder.extra_bytes(extra_bytes);
+    }
+
+    if convert.crs.is_none() {
+        let fallback = convert.fallback_crs.as_ref();
+        let wkt = header_wkt(first, fallback)?;
+        for (header, path) in headers.iter().zip(input_paths).skip(1) {
+            if header_wkt(header, fallback)? != wkt {
+                return Err(crate::Error::MergeCrsMismatch(PathBuf::from(path.as_ref())));
+            }
+        }
+        builder = builder.crs(CrsOverride::Wkt(String::from_utf8_lossy(&wkt).into_owned()));
+    }
+
+    let mut bounds = Bounds::default();
+    let mut num_points = 0;
+    if convert.recompute_bounds {
+        for reader in readers.iter_mut() {
+            for p in reader.points().filter_map(las::Result::ok) {
+                bounds.grow(&p);
+                num_points += 1;
+            }
+        }
+        readers = input_paths
+            .iter()
+            .map(|p| Reader::from_path(p.as_ref()))
+            .collect::<las::Result<Vec<_>>>()?;
+    } else {
+        for header in &headers {
+            let b = header.bounds();
+            bounds.grow(&Point {
+                x: b.min.x,
+                y: b.min.y,
+                z: b.min.z,
+                ..Default::default()
+            });
+            bounds.grow(&Point {
+                x: b.max.x,
+                y: b.max.y,
+                z: b.max.z,
+                ..Default::default()
+            });
+            num_points += header.number_of_points();
+        }
+    }
+
+    if num_points > 0 {
+        let precision = convert.precision.unwrap_or_else(|| {
+            headers
+                .iter()
+                .flat_map(|h| {
+                    let t = h.transforms();
+                    [t.x.scale, t.y.scale, t.z.scale]
+                })
+                .fold(f64::INFINITY, f64::min)
+        });
+        let transforms = transforms_for_bounds(&bounds, precision);
+        builder = builder
+            .transforms(transforms)
+            .bounds(pad_flat_bounds(bounds, &transforms));
+    }
+
+    let mut copc_writer = builder.build_path(output_path)?;
+
+    let mut points_read = 0;
+    let mut read_errors = 0;
+    let points = readers
+        .iter_mut()
+        .enumerate()
+        .flat_map(|(i, r)| r.points().map(move |p| (i, p)))
+        .filter_map(|(i, p)| match p {
+            Ok(mut p) => {
+                points_read += 1;
+                p.waveform = None;
+                if has_color && p.color.is_none() {
+                    p.color = Some(Default::default());
+                }
+                if has_nir && p.nir.is_none() {
+                    p.nir = Some(0);
+                }
+                p.extra_bytes.resize(extra_bytes as usize, 0);
+                if options.tag_source {
+                    p.point_source_id = i as u16;
+                }
+                Some(p)
+            }
+            Err(e) => {
+                log::warn!("Skipping a point that could not be read: {e}");
+                read_errors += 1;
+                None
+            }
+        });
+
+    let mut summary = write_points(&mut copc_writer, points, num_points)?;
+    summary.points_read = points_read;
+    summary.read_errors = read_errors;
+    Ok(summary)
+}
+
+/// The data of the extra bytes descriptors (e)vlr of the header
+fn extra_bytes_descriptors(header: &Header) -> Option<&[u8]> {
+    header
+        .all_vlrs()
+        .find(|v| v.user_id.eq_ignore_ascii_case("lasf_spec") && v.record_id == 4)
+        .map(|v| v.data.as_slice())
+}
+
+/// The WKT of the CRS of the header, or of the fallback CRS
+fn header_wkt(header: &Header, fallback: Option<&CrsOverride>) -> crate::Result<Vec<u8>> {
+    match (crs::header_wkt(header)?, fallback) {
+        (Some(wkt), _) => Ok(wkt),
+        (None, Some(fallback)) => fallback.wkt(),
+        (None, None) => Err(crate::Error::NoCRSDefined),
+    }
+}
+
+/// Writes the points and closes the writer, rejected points are reported in the summary
+fn write_points<W: Write + Seek, D: IntoIterator<Item = Point>>(
+    copc_writer: &mut CopcWriter<'_, W>,
+    points: D,
+    num_points: u64,
+) -> crate::Result<ConversionSummary> {
+    match copc_writer.write(points, num_points.min(i32::MAX as u64) as i32) {
+        Ok(()) | Err(crate::Error::InvalidPoint(_)) => (),
+        Err(e) => return Err(e),
+    }
+
+    let entries = &copc_writer.hierarchy_entries().entries;
+    Ok(ConversionSummary {
+        points_written: copc_writer.header().number_of_points(),
+        rejected: *copc_writer.rejected_points(),
+        node_count: entries.len(),
+        depth: entries.iter().map(|e| e.key.level).max().unwrap_or(0),
+        ..Default::default()
+    })
+}
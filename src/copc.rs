@@ -6,10 +6,17 @@ use byteorder::{LittleEndian, ReadBytesExt};
 #[cfg(feature = "writer")]
 use las::Vlr;
 use las::{Bounds, Vector};
+#[cfg(feature = "writer")]
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::hash::Hash;
 use std::io::Read;
 #[cfg(feature = "writer")]
 use std::io::{Cursor, Write};
+use std::str::FromStr;
+
+/// Deepest level of a key, the number of cells along an axis fits in an `i32`
+const MAX_LEVEL: i32 = 30;
 
 /// COPC Info VLR data.
 #[derive(Clone, Debug, Default)]
@@ -77,7 +84,9 @@ impl CopcInfo {
 }
 
 /// EPT hierarchy key
-#[derive(Hash, PartialEq, Eq, Clone, Debug)]
+///
+/// Keys are ordered by level, then x, y and z
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
 pub struct VoxelKey {
     /// Level
     ///
@@ -124,7 +133,18 @@ impl VoxelKey {
         Ok(())
     }
 
-    pub(crate) fn child(&self, dir: i32) -> VoxelKey {
+    /// The key of the root node
+    pub fn root() -> Self {
+        VoxelKey {
+            level: 0,
+            x: 0,
+            y: 0,
+            z: 0,
+        }
+    }
+
+    /// The child in direction `dir`, bit 0 selects the upper half in x, bit 1 in y and bit 2 in z
+    pub fn child(&self, dir: i32) -> VoxelKey {
         VoxelKey {
             level: self.level + 1,
             x: (self.x << 1) | (dir & 0x1),
@@ -132,13 +152,115 @@ impl VoxelKey {
             z: (self.z << 1) | ((dir >> 2) & 0x1),
         }
     }
-    pub(crate) fn children(&self) -> Vec<VoxelKey> {
+
+    /// The parent, `None` for the root
+    pub fn parent(&self) -> Option<VoxelKey> {
+        if self.level < 1 {
+            return None;
+        }
+        Some(VoxelKey {
+            level: self.level - 1,
+            x: self.x >> 1,
+            y: self.y >> 1,
+            z: self.z >> 1,
+        })
+    }
+
+    /// The eight children, in the order of their direction
+    pub fn children(&self) -> Vec<VoxelKey> {
         (0..8).map(|i| self.child(i)).collect()
     }
-    pub(crate) fn bounds(&self, root_bounds: &Bounds) -> Bounds {
+
+    /// The other seven children of the parent, none for the root
+    pub fn siblings(&self) -> Vec<VoxelKey> {
+        self.parent()
+            .map(|parent| {
+                parent
+                    .children()
+                    .into_iter()
+                    .filter(|key| key != self)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The ancestor at `level`, the key itself at its own level
+    ///
+    /// `None` if `level` is negative or deeper than the key
+    pub fn ancestor(&self, level: i32) -> Option<VoxelKey> {
+        if level < 0 || level > self.level {
+            return None;
+        }
+        let shift = self.level - level;
+        Some(VoxelKey {
+            level,
+            x: self.x >> shift,
+            y: self.y >> shift,
+            z: self.z >> shift,
+        })
+    }
+
+    /// The parent, grandparent and so on up to the root
+    pub fn ancestors(&self) -> impl Iterator<Item = VoxelKey> {
+        std::iter::successors(self.parent(), VoxelKey::parent)
+    }
+
+    /// Whether `other` is this key or one of its descendants
+    pub fn contains(&self, other: &VoxelKey) -> bool {
+        other.ancestor(self.level).as_ref() == Some(self)
+    }
+
+    /// The key `dx`, `dy` and `dz` cells away at the same level,
+    /// `None` if it is outside of the octree
+    pub fn neighbour(&self, dx: i32, dy: i32, dz: i32) -> Option<VoxelKey> {
+        if self.level < 0 || self.level > 30 {
+            return None;
+        }
+        let cells = 1 << self.level;
+        let key = VoxelKey {
+            level: self.level,
+            x: self.x.checked_add(dx)?,
+            y: self.y.checked_add(dy)?,
+            z: self.z.checked_add(dz)?,
+        };
+        [key.x, key.y, key.z]
+            .iter()
+            .all(|v| (0..cells).contains(v))
+            .then_some(key)
+    }
+
+    /// The up to six keys at the same level sharing a face with this one
+    pub fn face_neighbours(&self) -> Vec<VoxelKey> {
+        [
+            (-1, 0, 0),
+            (1, 0, 0),
+            (0, -1, 0),
+            (0, 1, 0),
+            (0, 0, -1),
+            (0, 0, 1),
+        ]
+        .into_iter()
+        .filter_map(|(dx, dy, dz)| self.neighbour(dx, dy, dz))
+        .collect()
+    }
+
+    /// The up to twelve keys at the same level sharing only an edge with this one
+    pub fn edge_neighbours(&self) -> Vec<VoxelKey> {
+        let mut neighbours = Vec::with_capacity(12);
+        for a in [-1, 1] {
+            for b in [-1, 1] {
+                neighbours.extend(self.neighbour(a, b, 0));
+                neighbours.extend(self.neighbour(a, 0, b));
+                neighbours.extend(self.neighbour(0, a, b));
+            }
+        }
+        neighbours
+    }
+
+    /// The bounds of the node in the octree of the cube `root_bounds`
+    pub fn bounds(&self, root_bounds: &Bounds) -> Bounds {
         // In an octree every cell is a cube
-        let side_size =
-            (root_bounds.max.x - root_bounds.min.x) / 2_u32.pow(self.level as u32) as f64;
+        let side_size = (root_bounds.max.x - root_bounds.min.x) / 2f64.powi(self.level);
 
         Bounds {
             min: Vector {
@@ -153,6 +275,88 @@ impl VoxelKey {
             },
         }
     }
+
+    /// The key at `level` of the node containing `point` in the octree of the cube `root_bounds`
+    ///
+    /// Points on the upper bounds belong to the last node, `None` if the point is outside
+    pub fn from_point(point: &Vector<f64>, level: i32, root_bounds: &Bounds) -> Option<VoxelKey> {
+        if !(0..=MAX_LEVEL).contains(&level) {
+            return None;
+        }
+        let cells = 1 << level;
+        let side_size = (root_bounds.max.x - root_bounds.min.x) / cells as f64;
+        let index = |v: f64, min: f64, max: f64| {
+            (min..=max)
+                .contains(&v)
+                .then(|| (((v - min) / side_size) as i32).min(cells - 1))
+        };
+        Some(VoxelKey {
+            level,
+            x: index(point.x, root_bounds.min.x, root_bounds.max.x)?,
+            y: index(point.y, root_bounds.min.y, root_bounds.max.y)?,
+            z: index(point.z, root_bounds.min.z, root_bounds.max.z)?,
+        })
+    }
+
+    /// The Morton (Z-order) code of the position of the key in its level,
+    /// the bits of x, y and z interleaved with x in the lowest bit
+    ///
+    /// The code of a child is the code of its parent shifted by three bits
+    /// plus its direction. Unique for the levels up to 21
+    pub fn morton_code(&self) -> u64 {
+        let mut code = 0;
+        for bit in 0..21 {
+            code |= ((self.x as u64 >> bit) & 1) << (3 * bit)
+                | ((self.y as u64 >> bit) & 1) << (3 * bit + 1)
+                | ((self.z as u64 >> bit) & 1) << (3 * bit + 2);
+        }
+        code
+    }
+
+    /// The key at `level` with the Morton `code`, see [Self::morton_code]
+    ///
+    /// `None` if the level is not in `0..=21` or the code is outside of the level
+    pub fn from_morton_code(level: i32, code: u64) -> Option<VoxelKey> {
+        if !(0..=21).contains(&level) || code >> (3 * level) != 0 {
+            return None;
+        }
+        let mut key = VoxelKey {
+            level,
+            x: 0,
+            y: 0,
+            z: 0,
+        };
+        for bit in 0..21 {
+            key.x |= (((code >> (3 * bit)) & 1) << bit) as i32;
+            key.y |= (((code >> (3 * bit + 1)) & 1) << bit) as i32;
+            key.z |= (((code >> (3 * bit + 2)) & 1) << bit) as i32;
+        }
+        Some(key)
+    }
+}
+
+/// The `level-x-y-z` form of the key, like `3-1-2-0`
+impl fmt::Display for VoxelKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}-{}-{}", self.level, self.x, self.y, self.z)
+    }
+}
+
+/// Parses the `level-x-y-z` form of a key, the level must be in `0..=30`
+impl FromStr for VoxelKey {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values: Vec<i32> = s
+            .split('-')
+            .map(|v| v.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| crate::Error::InvalidVoxelKey(s.to_string()))?;
+        match values[..] {
+            [level, x, y, z] if (0..=MAX_LEVEL).contains(&level) => Ok(VoxelKey { level, x, y, z }),
+            _ => Err(crate::Error::InvalidVoxelKey(s.to_string())),
+        }
+    }
 }
 
 /// Hierarchy entry
@@ -216,7 +420,8 @@ impl HierarchyPage {
     /// Reads hierarchy page from a `Read`.
     pub(crate) fn read_from<R: Read>(mut read: R, page_size: u64) -> crate::Result<Self> {
         let num_entries = page_size as usize / 32;
-        let mut entries = Vec::with_capacity(num_entries);
+        // the size comes from the file, the entries may not all be there
+        let mut entries = Vec::with_capacity(num_entries.min(1024));
         for _ in 0..num_entries {
             let entry = Entry::read_from(&mut read)?;
             entries.push(entry);
@@ -244,6 +449,106 @@ impl HierarchyPage {
         })
     }
 
+    /// Writes the entries split over hierarchy pages to a single evlr
+    ///
+    /// Each page holds at most `max_entries` entries, the root page is written first.
+    /// Entries with 0 points are added for nodes without points that have descendants with points.
+    /// `data_offset` is the absolute file offset the evlr data is written at.
+    ///
+    /// Returns the evlr and the size of the root page in bytes
+    #[cfg(feature = "writer")]
+    pub(crate) fn into_paged_evlr(
+        self,
+        max_entries: usize,
+        data_offset: u64,
+    ) -> crate::Result<(Vlr, u64)> {
+        let mut entries: HashMap<VoxelKey, Entry> = HashMap::with_capacity(self.entries.len());
+        for entry in self.entries {
+            // every ancestor of a node must be in the hierarchy to be reachable
+            let mut key = entry.key.clone();
+            while let Some(parent) = key.parent() {
+                entries.entry(parent.clone()).or_insert_with(|| Entry {
+                    key: parent.clone(),
+                    ..Default::default()
+                });
+                key = parent;
+            }
+            entries.insert(entry.key.clone(), entry);
+        }
+        let child_keys = |key: &VoxelKey| -> Vec<VoxelKey> {
+            key.children()
+                .into_iter()
+                .filter(|k| entries.contains_key(k))
+                .collect()
+        };
+
+        // breadth first fill each page, keys that do not fit become the roots of child pages
+        // pages: (keys of the entries, keys of the child page roots)
+        let mut pages: Vec<(Vec<VoxelKey>, Vec<VoxelKey>)> = vec![];
+        let mut page_roots = VecDeque::from([VoxelKey {
+            level: 0,
+            ..Default::default()
+        }]);
+        while let Some(page_root) = page_roots.pop_front() {
+            let mut page_keys = vec![];
+            let mut child_pages = vec![];
+            let mut frontier = VecDeque::from(child_keys(&page_root));
+            page_keys.push(page_root);
+
+            while let Some(key) = frontier.pop_front() {
+                let children = child_keys(&key);
+                // every key left in the frontier needs a slot, at least as a child page entry
+                if page_keys.len() + child_pages.len() + 1 + frontier.len() + children.len()
+                    <= max_entries
+                {
+                    page_keys.push(key);
+                    frontier.extend(children);
+                } else {
+                    child_pages.push(key);
+                }
+            }
+            page_roots.extend(child_pages.iter().cloned());
+            pages.push((page_keys, child_pages));
+        }
+
+        // page offsets and sizes, child pages are in the same order as their roots
+        let mut page_locations: HashMap<VoxelKey, (u64, i32)> = HashMap::new();
+        let mut offset = data_offset;
+        for (page_keys, child_pages) in &pages {
+            let byte_size = ((page_keys.len() + child_pages.len()) * 32) as u64;
+            page_locations.insert(page_keys[0].clone(), (offset, byte_size as i32));
+            offset += byte_size;
+        }
+        let root_page_size = page_locations[&pages[0].0[0]].1 as u64;
+
+        let mut buffer = Cursor::new(Vec::with_capacity((offset - data_offset) as usize));
+        for (page_keys, child_pages) in pages {
+            for key in page_keys {
+                entries[&key].clone().write_to(&mut buffer)?;
+            }
+            for key in child_pages {
+                let (offset, byte_size) = page_locations[&key];
+                Entry {
+                    key,
+                    offset,
+                    byte_size,
+                    point_count: -1,
+                }
+                .write_to(&mut buffer)?;
+            }
+        }
+
+        Ok((
+            Vlr {
+                user_id: "copc".to_string(),
+                record_id: 1000,
+                description: "EPT Hierarchy".to_string(),
+                data: buffer.into_inner(),
+            },
+            root_page_size,
+        ))
+    }
+
     /// The number of bytes the data in the evlr is
     #[cfg(feature = "writer")]
     pub fn byte_size(&self) -> u64 {
@@ -0,0 +1,437 @@
+//! Coordinate reference systems, conversion of GeoTIFF CRS keys to WKT and WKT inspection.
+
+use las::crs::{GeoTiffCrs, GeoTiffData};
+use las::Header;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// EPSG codes are in this range, other values are omitted, user-defined or private
+const EPSG_CODES: std::ops::RangeInclusive<u16> = 1024..=32766;
+
+/// The WKT of the CRS of the header, from the WKT (e)vlr or converted from the GeoTIFF keys
+///
+/// `None` if the header has no CRS, or only a user-defined one
+pub(crate) fn header_wkt(header: &Header) -> crate::Result<Option<Vec<u8>>> {
+    if let Some(vlr) = header
+        .all_vlrs()
+        .find(|v| v.user_id.eq_ignore_ascii_case("lasf_projection") && v.record_id == 2112)
+    {
+        let end = vlr.data.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+        return Ok(Some(vlr.data[..end].to_vec()));
+    }
+    match header.get_geotiff_crs().ok().flatten() {
+        Some(geotiff) => Ok(geotiff_wkt(&geotiff)?.map(String::into_bytes)),
+        None => Ok(None),
+    }
+}
+
+/// Converts GeoTIFF keys to a WKT
+///
+/// The horizontal CRS is the projected CRS, or the geographic CRS if there is none.
+/// A vertical CRS is added as a compound CRS. Vertical CRSs are not defined
+/// in the crs-definitions library, they are written as a minimal VERT_CS with the EPSG code
+fn geotiff_wkt(geotiff: &GeoTiffCrs) -> crate::Result<Option<String>> {
+    let epsg = |code: Option<u16>| code.filter(|c| EPSG_CODES.contains(c));
+
+    let projected = epsg(geotiff.get_projected_crs_geo_key_value());
+    let geodetic = epsg(geotiff.get_geodetic_crs_geo_key_value());
+    let horizontal = match geotiff.get_gt_model_type_geo_key_value() {
+        Some(2 | 3) => geodetic.or(projected),
+        _ => projected.or(geodetic),
+    };
+    let Some(horizontal) = horizontal else {
+        return Ok(None);
+    };
+    let horizontal_wkt = crs_definitions::from_code(horizontal)
+        .ok_or(crate::Error::InvalidEPSGCode(horizontal))?
+        .wkt;
+
+    let Some(vertical) = epsg(geotiff.get_vertical_crs_geo_key_value()) else {
+        return Ok(Some(horizontal_wkt.to_owned()));
+    };
+    // only WKT1 can be combined into a COMPD_CS
+    if !["PROJCS[", "GEOGCS[", "GEOCCS["]
+        .iter()
+        .any(|prefix| horizontal_wkt.starts_with(prefix))
+    {
+        log::warn!("Ignoring the vertical CRS {vertical}, EPSG:{horizontal} is not a WKT1 CRS");
+        return Ok(Some(horizontal_wkt.to_owned()));
+    }
+
+    let vertical_wkt = match crs_definitions::from_code(vertical) {
+        Some(def) if def.wkt.starts_with("VERT_CS[") => def.wkt.to_owned(),
+        _ => {
+            let unit = match geo_key_value(geotiff, 4099) {
+                Some(9002) => r#"UNIT["foot",0.3048,AUTHORITY["EPSG","9002"]]"#,
+                Some(9003) => {
+                    r#"UNIT["US survey foot",0.304800609601219,AUTHORITY["EPSG","9003"]]"#
+                }
+                _ => r#"UNIT["metre",1,AUTHORITY["EPSG","9001"]]"#,
+            };
+            format!(
+                r#"VERT_CS["EPSG:{vertical}",VERT_DATUM["unknown",2005],{unit},AXIS["Up",UP],AUTHORITY["EPSG","{vertical}"]]"#
+            )
+        }
+    };
+
+    Ok(Some(format!(
+        r#"COMPD_CS["{} + {}",{horizontal_wkt},{vertical_wkt}]"#,
+        wkt_name(horizontal_wkt),
+        wkt_name(&vertical_wkt),
+    )))
+}
+
+fn geo_key_value(geotiff: &GeoTiffCrs, id: u16) -> Option<u16> {
+    geotiff
+        .entries
+        .iter()
+        .find(|entry| entry.id == id)
+        .and_then(|entry| match entry.data {
+            GeoTiffData::U16(value) => Some(value),
+            _ => None,
+        })
+}
+
+/// The name of a WKT1 CRS, its first quoted string
+fn wkt_name(wkt: &str) -> &str {
+    wkt.split('"').nth(1).unwrap_or("unknown")
+}
+
+/// Kind of a unit of measure
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnitKind {
+    /// A length, the factor converts to metres
+    Linear,
+    /// An angle, the factor converts to radians
+    Angular,
+}
+
+/// Unit of measure of the axes of a CRS
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrsUnit {
+    /// Name of the unit, like `metre` or `degree`
+    pub name: String,
+    /// Conversion factor to metres or radians
+    pub factor: f64,
+    /// Whether the unit is a length or an angle
+    pub kind: UnitKind,
+}
+
+/// CRS of a point cloud, parsed from its WKT
+///
+/// The resolution of [crate::LodSelection::Resolution] is in the [horizontal_unit]
+///
+/// [horizontal_unit]: Self::horizontal_unit
+#[derive(Clone, Debug, PartialEq)]
+pub struct Crs {
+    wkt: String,
+    epsg: Option<u16>,
+    horizontal_epsg: Option<u16>,
+    vertical_epsg: Option<u16>,
+    vertical_datum: Option<String>,
+    horizontal_unit: Option<CrsUnit>,
+    vertical_unit: Option<CrsUnit>,
+}
+
+impl Crs {
+    /// Parses a WKT1 or WKT2 CRS, anything that can not be parsed is left out
+    pub fn from_wkt(wkt: &str) -> Self {
+        let wkt = wkt.trim_end_matches('\0').trim();
+        let mut crs = Crs {
+            wkt: wkt.to_owned(),
+            epsg: None,
+            horizontal_epsg: None,
+            vertical_epsg: None,
+            vertical_datum: None,
+            horizontal_unit: None,
+            vertical_unit: None,
+        };
+        let Some(root) = WktNode::parse(wkt) else {
+            return crs;
+        };
+
+        let (horizontal, vertical) = match root.keyword.as_str() {
+            "COMPD_CS" | "COMPOUNDCRS" => (
+                root.children().find(|n| n.is_horizontal_crs()),
+                root.children().find(|n| n.is_vertical_crs()),
+            ),
+            _ if root.is_vertical_crs() => (None, Some(&root)),
+            _ => (Some(&root), None),
+        };
+
+        crs.epsg = root
+            .epsg()
+            .or_else(|| root.name().and_then(epsg_from_definitions));
+        crs.horizontal_epsg = horizontal.and_then(|h| {
+            if std::ptr::eq(h, &root) {
+                crs.epsg
+            } else {
+                h.epsg()
+            }
+        });
+        crs.vertical_epsg = vertical.and_then(WktNode::epsg);
+        crs.vertical_datum = vertical
+            .and_then(|v| {
+                v.children().find(|n| {
+                    matches!(
+                        n.keyword.as_str(),
+                        "VERT_DATUM"
+                            | "VDATUM"
+                            | "VERTICALDATUM"
+                            | "VRF"
+                            | "VERTICALREFERENCEFRAME"
+                    )
+                })
+            })
+            .and_then(WktNode::name)
+            .map(str::to_owned);
+        crs.horizontal_unit = horizontal.and_then(WktNode::unit);
+        crs.vertical_unit = vertical.and_then(WktNode::unit);
+        crs
+    }
+
+    /// The WKT of the CRS
+    pub fn wkt(&self) -> &str {
+        &self.wkt
+    }
+
+    /// The EPSG code of the CRS, from its authority
+    /// or by looking up its name in the crs-definitions library
+    pub fn epsg(&self) -> Option<u16> {
+        self.epsg
+    }
+
+    /// The EPSG code of the horizontal CRS, the same as [epsg] for a non-compound CRS
+    ///
+    /// [epsg]: Self::epsg
+    pub fn horizontal_epsg(&self) -> Option<u16> {
+        self.horizontal_epsg
+    }
+
+    /// The EPSG code of the vertical CRS of a compound or vertical CRS
+    pub fn vertical_epsg(&self) -> Option<u16> {
+        self.vertical_epsg
+    }
+
+    /// The name of the vertical datum of a compound or vertical CRS
+    pub fn vertical_datum(&self) -> Option<&str> {
+        self.vertical_datum.as_deref()
+    }
+
+    /// The unit of the horizontal axes
+    pub fn horizontal_unit(&self) -> Option<&CrsUnit> {
+        self.horizontal_unit.as_ref()
+    }
+
+    /// The unit of the vertical axis of a compound or vertical CRS
+    pub fn vertical_unit(&self) -> Option<&CrsUnit> {
+        self.vertical_unit.as_ref()
+    }
+}
+
+/// The EPSG code of the definition in the crs-definitions library with the same name,
+/// ignoring case, whitespace and punctuation
+///
+/// The index of the names is built on the first lookup
+fn epsg_from_definitions(name: &str) -> Option<u16> {
+    static CODES_BY_NAME: OnceLock<HashMap<String, u16>> = OnceLock::new();
+
+    let codes = CODES_BY_NAME.get_or_init(|| {
+        let mut codes = HashMap::new();
+        for code in EPSG_CODES {
+            if let Some(def) = crs_definitions::from_code(code) {
+                // the lowest code of a name
+                codes
+                    .entry(normalize_name(wkt_name(def.wkt)))
+                    .or_insert(code);
+            }
+        }
+        codes
+    });
+    codes.get(&normalize_name(name)).copied()
+}
+
+/// The lowercase letters and digits of a name
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Deepest nesting of WKT elements parsed, real WKTs are nested less than ten levels
+const MAX_WKT_DEPTH: usize = 64;
+
+/// A `KEYWORD[...]` element of a WKT
+#[derive(Debug)]
+struct WktNode {
+    keyword: String,
+    args: Vec<WktArg>,
+}
+
+#[derive(Debug)]
+enum WktArg {
+    /// A quoted string
+    Text(String),
+    /// A number or an enumeration value
+    Value(String),
+    Node(WktNode),
+}
+
+impl WktNode {
+    fn parse(wkt: &str) -> Option<WktNode> {
+        let mut chars = wkt.chars().peekable();
+        let keyword = parse_word(&mut chars);
+        let node = Self::parse_body(keyword, &mut chars, 0)?;
+        chars.all(char::is_whitespace).then_some(node)
+    }
+
+    /// Parses the bracketed arguments following the keyword, `depth` is the nesting of the element
+    ///
+    /// `None` on a syntax error or an element nested deeper than [MAX_WKT_DEPTH]
+    fn parse_body(keyword: String, chars: &mut Chars, depth: usize) -> Option<WktNode> {
+        skip_whitespace(chars);
+        if keyword.is_empty() || depth > MAX_WKT_DEPTH || !matches!(chars.next()?, '[' | '(') {
+            return None;
+        }
+
+        let mut args = Vec::new();
+        loop {
+            skip_whitespace(chars);
+            match *chars.peek()? {
+                '"' => {
+                    chars.next();
+                    let mut text = String::new();
+                    loop {
+                        match chars.next()? {
+                            // a doubled quote is an escaped quote
+                            '"' if chars.next_if_eq(&'"').is_some() => text.push('"'),
+                            '"' => break,
+                            c => text.push(c),
+                        }
+                    }
+                    args.push(WktArg::Text(text));
+                }
+                c if c.is_ascii_alphabetic() => {
+                    let word = parse_word(chars);
+                    skip_whitespace(chars);
+                    if matches!(chars.peek(), Some('[' | '(')) {
+                        args.push(WktArg::Node(Self::parse_body(word, chars, depth + 1)?));
+                    } else {
+                        args.push(WktArg::Value(word));
+                    }
+                }
+                _ => {
+                    let mut value = String::new();
+                    while let Some(c) = chars.next_if(|c| !matches!(c, ',' | ']' | ')')) {
+                        value.push(c);
+                    }
+                    args.push(WktArg::Value(value.trim().to_owned()));
+                }
+            }
+            skip_whitespace(chars);
+            match chars.next()? {
+                ',' => (),
+                ']' | ')' => break,
+                _ => return None,
+            }
+        }
+        Some(WktNode {
+            keyword: keyword.to_ascii_uppercase(),
+            args,
+        })
+    }
+
+    fn children(&self) -> impl Iterator<Item = &WktNode> {
+        self.args.iter().filter_map(|arg| match arg {
+            WktArg::Node(node) => Some(node),
+            _ => None,
+        })
+    }
+
+    /// The first argument, the name of most elements
+    fn name(&self) -> Option<&str> {
+        match self.args.first()? {
+            WktArg::Text(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn is_horizontal_crs(&self) -> bool {
+        matches!(
+            self.keyword.as_str(),
+            "PROJCS"
+                | "GEOGCS"
+                | "GEOCCS"
+                | "PROJCRS"
+                | "PROJECTEDCRS"
+                | "GEOGCRS"
+                | "GEOGRAPHICCRS"
+                | "GEODCRS"
+                | "GEODETICCRS"
+        )
+    }
+
+    fn is_vertical_crs(&self) -> bool {
+        matches!(self.keyword.as_str(), "VERT_CS" | "VERTCRS" | "VERTICALCRS")
+    }
+
+    /// The EPSG code of `AUTHORITY["EPSG","1234"]` or `ID["EPSG",1234]`
+    fn epsg(&self) -> Option<u16> {
+        let id = self
+            .children()
+            .find(|n| matches!(n.keyword.as_str(), "AUTHORITY" | "ID"))?;
+        if !id.name()?.eq_ignore_ascii_case("EPSG") {
+            return None;
+        }
+        match id.args.get(1)? {
+            WktArg::Text(code) | WktArg::Value(code) => code.parse().ok(),
+            WktArg::Node(_) => None,
+        }
+    }
+
+    /// The unit of the element, or of its first axis with a unit
+    fn unit(&self) -> Option<CrsUnit> {
+        let unit = self.children().find(|n| n.is_unit()).or_else(|| {
+            self.children()
+                .filter(|n| n.keyword == "AXIS")
+                .flat_map(WktNode::children)
+                .find(|n| n.is_unit())
+        })?;
+        let factor = match unit.args.get(1)? {
+            WktArg::Value(factor) => factor.parse().ok()?,
+            _ => return None,
+        };
+        let kind = match unit.keyword.as_str() {
+            "ANGLEUNIT" => UnitKind::Angular,
+            "LENGTHUNIT" => UnitKind::Linear,
+            // WKT1 units of geographic CRSs are angles
+            _ if matches!(self.keyword.as_str(), "GEOGCS") => UnitKind::Angular,
+            _ => UnitKind::Linear,
+        };
+        Some(CrsUnit {
+            name: unit.name()?.to_owned(),
+            factor,
+            kind,
+        })
+    }
+
+    fn is_unit(&self) -> bool {
+        matches!(self.keyword.as_str(), "UNIT" | "LENGTHUNIT" | "ANGLEUNIT")
+    }
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+fn skip_whitespace(chars: &mut Chars) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+/// A keyword or an enumeration value
+fn parse_word(chars: &mut Chars) -> String {
+    let mut word = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+        word.push(c);
+    }
+    word
+}
@@ -0,0 +1,506 @@
+//! Import and export of Entwine Point Tile (EPT) datasets.
+
+use crate::copc::VoxelKey;
+use crate::extra_bytes::ExtraBytesType;
+use crate::json::{object, Json};
+use crate::reader::CopcReader;
+use byteorder::{LittleEndian, WriteBytesExt};
+use las::{Bounds, Builder, Header, Vector};
+use laz::laszip::{ChunkTable, ChunkTableEntry};
+use laz::LazVlr;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, Write};
+use std::path::Path;
+
+/// Directory of the node files in the EPT directory
+const DATA_DIRECTORY: &str = "ept-data";
+/// Directory of the hierarchy files in the EPT directory
+const HIERARCHY_DIRECTORY: &str = "ept-hierarchy";
+/// Span of the nodes if the spacing is not known
+const DEFAULT_SPAN: u64 = 128;
+
+/// Options for [CopcReader::export_ept]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EptOptions {
+    /// Number of levels per hierarchy file, all keys are in one file if `None`
+    pub hierarchy_step: Option<u32>,
+}
+
+/// Summary of an EPT import or export
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EptSummary {
+    /// Number of nodes with points
+    pub nodes_written: usize,
+    /// Number of points written
+    pub points_written: u64,
+    /// Number of nodes whose compressed chunk was copied without recompression
+    pub chunks_reused: usize,
+}
+
+fn bounds_json(bounds: &Bounds) -> Json {
+    vec![
+        bounds.min.x,
+        bounds.min.y,
+        bounds.min.z,
+        bounds.max.x,
+        bounds.max.y,
+        bounds.max.z,
+    ]
+    .into()
+}
+
+/// A dimension of the EPT schema
+fn dimension(name: &str, kind: &str, size: usize) -> Json {
+    object([
+        ("name", name.into()),
+        ("type", kind.into()),
+        ("size", size.into()),
+    ])
+}
+
+/// The EPT schema of the points of `header`, the dimension names are the ones of PDAL
+fn schema<R: Read + Seek>(reader: &CopcReader<R>) -> Vec<Json> {
+    let header = reader.header();
+    let format = header.point_format();
+    let transforms = header.transforms();
+    let mut schema: Vec<Json> = [
+        ("X", transforms.x),
+        ("Y", transforms.y),
+        ("Z", transforms.z),
+    ]
+    .into_iter()
+    .map(|(name, transform)| {
+        object([
+            ("name", name.into()),
+            ("type", "signed".into()),
+            ("size", 4.into()),
+            ("scale", transform.scale.into()),
+            ("offset", transform.offset.into()),
+        ])
+    })
+    .collect();
+    schema.extend([
+        dimension("Intensity", "unsigned", 2),
+        dimension("ReturnNumber", "unsigned", 1),
+        dimension("NumberOfReturns", "unsigned", 1),
+        dimension("ScanDirectionFlag", "unsigned", 1),
+        dimension("EdgeOfFlightLine", "unsigned", 1),
+        dimension("Classification", "unsigned", 1),
+        dimension("ScanAngleRank", "float", 4),
+        dimension("UserData", "unsigned", 1),
+        dimension("PointSourceId", "unsigned", 2),
+    ]);
+    if format.has_gps_time {
+        schema.push(dimension("GpsTime", "float", 8));
+    }
+    if format.is_extended {
+        schema.push(dimension("ScanChannel", "unsigned", 1));
+        schema.push(dimension("ClassFlags", "unsigned", 1));
+    }
+    if format.has_color {
+        schema.push(dimension("Red", "unsigned", 2));
+        schema.push(dimension("Green", "unsigned", 2));
+        schema.push(dimension("Blue", "unsigned", 2));
+    }
+    if format.has_nir {
+        schema.push(dimension("Infrared", "unsigned", 2));
+    }
+    for descriptor in reader.extra_bytes().descriptors() {
+        let kind = match descriptor.data_type {
+            ExtraBytesType::I8
+            | ExtraBytesType::I16
+            | ExtraBytesType::I32
+            | ExtraBytesType::I64 => "signed",
+            ExtraBytesType::F32 | ExtraBytesType::F64 => "float",
+            _ => "unsigned",
+        };
+        schema.push(dimension(&descriptor.name, kind, descriptor.size()));
+    }
+    schema
+}
+
+/// The header of the node files: the COPC header without the COPC (e)vlrs and point counts
+fn node_header(header: &Header) -> crate::Result<Header> {
+    let mut builder = Builder::from(header.clone());
+    builder
+        .vlrs
+        .retain(|vlr| !(vlr.user_id.eq_ignore_ascii_case("copc") && vlr.record_id == 1));
+    builder.evlrs.clear();
+    let mut header = builder.into_header()?;
+    header.clear();
+    Ok(header)
+}
+
+/// Writes a LAZ file of one compressed chunk
+fn write_node_file(
+    path: &Path,
+    header: &Header,
+    laz_vlr: &LazVlr,
+    chunk: &[u8],
+    point_count: u64,
+) -> crate::Result<()> {
+    let offset_to_point_data = header.clone().into_raw()?.offset_to_point_data as u64;
+    let mut write = BufWriter::new(File::create(path)?);
+    header.write_to(&mut write)?;
+    let chunk_table_offset =
+        offset_to_point_data + ChunkTable::OFFSET_SIZE as u64 + chunk.len() as u64;
+    write.write_i64::<LittleEndian>(chunk_table_offset as i64)?;
+    write.write_all(chunk)?;
+    let mut chunk_table = ChunkTable::default();
+    chunk_table.push(ChunkTableEntry {
+        point_count,
+        byte_count: chunk.len() as u64,
+    });
+    chunk_table.write_to(&mut write, laz_vlr)?;
+    write.flush()?;
+    Ok(())
+}
+
+/// Writes the hierarchy files of the `counts`, split every `step` levels
+fn write_hierarchy(
+    directory: &Path,
+    counts: &BTreeMap<VoxelKey, u64>,
+    step: Option<u32>,
+) -> crate::Result<()> {
+    let mut files: BTreeMap<VoxelKey, Vec<(String, Json)>> = BTreeMap::new();
+    for (key, count) in counts {
+        let root_level = match step {
+            Some(step) if step > 0 => key.level - key.level % step as i32,
+            _ => 0,
+        };
+        files
+            .entry(
+                key.ancestor(root_level)
+                    .expect("the root level is at most the level of the key"),
+            )
+            .or_default()
+            .push((key.to_string(), (*count).into()));
+        if root_level == key.level && key.level > 0 {
+            // the subtree of the key is in its own file
+            let step = step.unwrap_or_default() as i32;
+            files
+                .entry(
+                    key.ancestor(key.level - step)
+                        .expect("the level of the key is a positive multiple of the step"),
+                )
+                .or_default()
+                .push((key.to_string(), Json::Int(-1)));
+        }
+    }
+    for (root, members) in files {
+        let path = directory
+            .join(HIERARCHY_DIRECTORY)
+            .join(format!("{root}.json"));
+        std::fs::write(path, Json::Object(members).to_string())?;
+    }
+    Ok(())
+}
+
+impl<R: Read + Seek> CopcReader<R> {
+    /// Writes the octree to an EPT dataset in `directory`
+    ///
+    /// Every node is written to `ept-data/L-X-Y-Z.laz` with its compressed chunk copied as is,
+    /// the hierarchy to `ept-hierarchy` and the metadata to `ept.json`
+    pub fn export_ept<P: AsRef<Path>>(
+        &mut self,
+        directory: P,
+        options: &EptOptions,
+    ) -> crate::Result<EptSummary> {
+        let directory = directory.as_ref();
+        std::fs::create_dir_all(directory.join(DATA_DIRECTORY))?;
+        std::fs::create_dir_all(directory.join(HIERARCHY_DIRECTORY))?;
+        let header = node_header(self.header())?;
+        let laz_vlr = self.laz_vlr().clone();
+
+        let keys = self.octree_keys();
+        let mut counts = BTreeMap::new();
+        let mut summary = EptSummary::default();
+        for key in self.keys_by_offset(&keys) {
+            let entry = match self.hierarchy_entries().get(&key) {
+                Some(entry) if entry.point_count > 0 => entry.clone(),
+                _ => {
+                    counts.insert(key, 0);
+                    continue;
+                }
+            };
+            // the header of the node has its own point counts and bounds
+            let mut node_header = header.clone();
+            for point in self.node_points(&key)? {
+                node_header.add_point(&point);
+            }
+            let chunk = self.read_chunk(&entry)?;
+            let path = directory.join(DATA_DIRECTORY).join(format!("{key}.laz"));
+            write_node_file(
+                &path,
+                &node_header,
+                &laz_vlr,
+                &chunk,
+                entry.point_count as u64,
+            )?;
+            summary.nodes_written += 1;
+            summary.chunks_reused += 1;
+            summary.points_written += entry.point_count as u64;
+            counts.insert(key, entry.point_count as u64);
+        }
+        write_hierarchy(directory, &counts, options.hierarchy_step)?;
+
+        let info = self.copc_info();
+        let cube = Bounds {
+            min: Vector {
+                x: info.center.x - info.halfsize,
+                y: info.center.y - info.halfsize,
+                z: info.center.z - info.halfsize,
+            },
+            max: Vector {
+                x: info.center.x + info.halfsize,
+                y: info.center.y + info.halfsize,
+                z: info.center.z + info.halfsize,
+            },
+        };
+        let span = if info.spacing > 0. {
+            ((2. * info.halfsize / info.spacing).round() as u64).max(1)
+        } else {
+            DEFAULT_SPAN
+        };
+        let mut metadata = vec![
+            ("bounds".to_string(), bounds_json(&cube)),
+            (
+                "boundsConforming".to_string(),
+                bounds_json(&self.header().bounds()),
+            ),
+            ("dataType".to_string(), "laszip".into()),
+            ("hierarchyType".to_string(), "json".into()),
+            ("points".to_string(), summary.points_written.into()),
+            ("schema".to_string(), schema(self).into()),
+            ("span".to_string(), span.into()),
+        ];
+        if let Some(wkt) = self.crs_wkt() {
+            let mut srs = vec![];
+            if let Some(epsg) = self.crs_epsg() {
+                srs.push(("authority".to_string(), "EPSG".into()));
+                srs.push(("horizontal".to_string(), epsg.to_string().into()));
+            }
+            srs.push(("wkt".to_string(), wkt.into()));
+            metadata.push(("srs".to_string(), Json::Object(srs)));
+        }
+        metadata.push(("version".to_string(), "1.0.0".into()));
+        std::fs::write(
+            directory.join("ept.json"),
+            Json::Object(metadata).to_string(),
+        )?;
+        Ok(summary)
+    }
+}
+
+/// The metadata of an EPT dataset needed to write a COPC file
+#[cfg(feature = "writer")]
+struct EptMetadata {
+    cube: Bounds,
+    span: Option<f64>,
+    wkt: Option<String>,
+    /// The EPSG code of `srs`, used when it has no WKT
+    epsg: Option<u16>,
+}
+
+#[cfg(feature = "writer")]
+fn invalid_ept<S: Into<String>>(message: S) -> crate::Error {
+    crate::Error::InvalidEpt(message.into())
+}
+
+#[cfg(feature = "writer")]
+fn read_json(path: &Path) -> crate::Result<Json> {
+    let text = std::fs::read_to_string(path)?;
+    Json::parse(&text).map_err(|e| invalid_ept(format!("{}: {e}", path.display())))
+}
+
+#[cfg(feature = "writer")]
+fn read_metadata(directory: &Path) -> crate::Result<EptMetadata> {
+    let json = read_json(&directory.join("ept.json"))?;
+    match json.get("dataType").and_then(Json::as_str) {
+        Some("laszip") => {}
+        Some(data_type) => return Err(invalid_ept(format!("unsupported data type {data_type}"))),
+        None => return Err(invalid_ept("missing data type")),
+    }
+    if let Some(hierarchy_type) = json.get("hierarchyType").and_then(Json::as_str) {
+        if hierarchy_type != "json" {
+            return Err(invalid_ept(format!(
+                "unsupported hierarchy type {hierarchy_type}"
+            )));
+        }
+    }
+    let bounds: Vec<f64> = json
+        .get("bounds")
+        .and_then(Json::as_array)
+        .map(|values| values.iter().filter_map(Json::as_f64).collect())
+        .unwrap_or_default();
+    let [min_x, min_y, min_z, max_x, max_y, max_z] = bounds[..] else {
+        return Err(invalid_ept("the bounds are not 6 numbers"));
+    };
+    Ok(EptMetadata {
+        cube: Bounds {
+            min: Vector {
+                x: min_x,
+                y: min_y,
+                z: min_z,
+            },
+            max: Vector {
+                x: max_x,
+                y: max_y,
+                z: max_z,
+            },
+        },
+        span: json.get("span").and_then(Json::as_f64),
+        wkt: json
+            .get("srs")
+            .and_then(|srs| srs.get("wkt"))
+            .and_then(Json::as_str)
+            .filter(|wkt| !wkt.is_empty())
+            .map(str::to_string),
+        epsg: json.get("srs").and_then(srs_epsg),
+    })
+}
+
+/// The EPSG code of a `srs` object, the horizontal code may be a string or a number
+#[cfg(feature = "writer")]
+fn srs_epsg(srs: &Json) -> Option<u16> {
+    if srs.get("authority").and_then(Json::as_str) != Some("EPSG") {
+        return None;
+    }
+    match srs.get("horizontal")? {
+        Json::String(code) => code.parse().ok(),
+        code => code.as_i64().and_then(|code| u16::try_from(code).ok()),
+    }
+}
+
+/// Reads the point counts of the hierarchy, following the subtree files
+#[cfg(feature = "writer")]
+fn read_hierarchy(directory: &Path) -> crate::Result<BTreeMap<VoxelKey, u64>> {
+    let mut counts = BTreeMap::new();
+    let mut files = vec![VoxelKey::root()];
+    // the subtree files already read, a loop between them is an error
+    let mut visited = std::collections::HashSet::new();
+    while let Some(root) = files.pop() {
+        if !visited.insert(root.clone()) {
+            return Err(invalid_ept(format!(
+                "the hierarchy file {root}.json is referenced more than once"
+            )));
+        }
+        let path = directory
+            .join(HIERARCHY_DIRECTORY)
+            .join(format!("{root}.json"));
+        let json = read_json(&path)?;
+        let members = json
+            .as_object()
+            .ok_or_else(|| invalid_ept(format!("{} is not an object", path.display())))?;
+        for (name, count) in members {
+            let key: VoxelKey = name
+                .parse()
+                .map_err(|_| invalid_ept(format!("invalid key {name}")))?;
+            match count.as_i64() {
+                Some(-1) if key != root => files.push(key),
+                Some(count) if count >= 0 => {
+                    counts.insert(key, count as u64);
+                }
+                _ => return Err(invalid_ept(format!("invalid point count of {name}"))),
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// The compressed chunk of a node file if it has a single chunk
+#[cfg(feature = "writer")]
+fn single_chunk(path: &Path, laz_vlr: &LazVlr) -> crate::Result<Option<Vec<u8>>> {
+    use std::io::{BufReader, SeekFrom};
+
+    let mut read = BufReader::new(File::open(path)?);
+    let raw_header = las::raw::Header::read_from(&mut read)?;
+    read.seek(SeekFrom::Start(raw_header.offset_to_point_data as u64))?;
+    let chunk_table = ChunkTable::read_from(&mut read, laz_vlr)?;
+    if chunk_table.len() != 1 {
+        return Ok(None);
+    }
+    let mut chunk = vec![0; chunk_table[0].byte_count as usize];
+    read.read_exact(&mut chunk)?;
+    Ok(Some(chunk))
+}
+
+/// Writes the EPT dataset of `directory` to a COPC file at `output` with the same octree
+///
+/// Only datasets of LAZ nodes are supported. The compressed chunks of the nodes are copied
+/// if their point format, scales and offsets and compression match the ones of the COPC file,
+/// otherwise the points are recompressed
+#[cfg(feature = "writer")]
+pub fn ept_to_copc<P: AsRef<Path>, Q: AsRef<Path>>(
+    directory: P,
+    output: Q,
+) -> crate::Result<EptSummary> {
+    let directory = directory.as_ref();
+    let metadata = read_metadata(directory)?;
+    let counts = read_hierarchy(directory)?;
+    let node_path = |key: &VoxelKey| directory.join(DATA_DIRECTORY).join(format!("{key}.laz"));
+
+    // the header of the COPC file is the one of the first node
+    let first = counts
+        .iter()
+        .find(|(_, count)| **count > 0)
+        .map(|(key, _)| key)
+        .ok_or(crate::Error::EmptyCopcFile)?;
+    let header = las::Reader::from_path(node_path(first))?.header().clone();
+    let mut builder = crate::CopcWriterBuilder::new(header).bounds(metadata.cube);
+    if let Some(wkt) = metadata.wkt {
+        builder = builder.crs(crate::CrsOverride::Wkt(wkt));
+    } else if let Some(epsg) = metadata.epsg {
+        builder = builder.crs(crate::CrsOverride::Epsg(epsg));
+    }
+    let mut writer = builder.build_path(output)?;
+
+    let mut summary = EptSummary::default();
+    for (key, count) in &counts {
+        if *count == 0 {
+            continue;
+        }
+        let path = node_path(key);
+        let mut reader = las::Reader::from_path(&path)?;
+        let node_header = reader.header().clone();
+        let points = reader.points().collect::<las::Result<Vec<_>>>()?;
+        if points.len() as u64 != *count {
+            return Err(invalid_ept(format!(
+                "{} has {} points instead of {count}",
+                path.display(),
+                points.len()
+            )));
+        }
+        let laz_vlr = node_header.laz_vlr()?;
+        let chunk = if node_header.point_format() == writer.header().point_format()
+            && node_header.transforms() == writer.header().transforms()
+            && laz_vlr.items() == writer.laz_vlr().items()
+        {
+            single_chunk(&path, &laz_vlr)?
+        } else {
+            None
+        };
+        if chunk.is_some() {
+            summary.chunks_reused += 1;
+        }
+        summary.nodes_written += 1;
+        summary.points_written += points.len() as u64;
+        writer.write_node(key.clone(), points, chunk.as_deref())?;
+    }
+    // the nodes without points between the root and the nodes with points
+    let empty_ancestors: std::collections::BTreeSet<VoxelKey> = counts
+        .iter()
+        .filter(|(_, count)| **count > 0)
+        .flat_map(|(key, _)| std::iter::successors(key.parent(), VoxelKey::parent))
+        .filter(|key| counts.get(key).copied().unwrap_or(0) == 0)
+        .collect();
+    for key in empty_ancestors {
+        writer.write_empty_node(key)?;
+    }
+    if let Some(span) = metadata.span.filter(|span| *span > 0.) {
+        writer.set_spacing((metadata.cube.max.x - metadata.cube.min.x) / span);
+    }
+    writer.close()?;
+    Ok(summary)
+}
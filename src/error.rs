@@ -45,6 +45,38 @@ pub enum Error {
     #[error("The source to be read does not contain a EPT hierarchy vlr")]
     EptHierarchyVlrNotFound,
 
+    /// The extra bytes vlr could not be parsed
+    #[error("invalid extra bytes vlr: {}", .0)]
+    InvalidExtraBytesVlr(String),
+
+    /// The statistics vlr could not be parsed
+    #[error("invalid statistics vlr: {}", .0)]
+    InvalidStatisticsVlr(String),
+
+    /// An exported attribute is not a point attribute or an extra attribute of the file
+    #[error("unknown point attribute: {}", .0)]
+    UnknownAttribute(String),
+
+    /// PLY normals were requested without all of the x, y and z attributes
+    #[error("the PLY normals need the x, y and z attributes")]
+    PlyNormalsWithoutCoordinates,
+
+    /// A GeoTIFF raster would be larger than the 4 GiB a TIFF file can address
+    #[error("a GeoTIFF of {} x {} cells is larger than 4 GiB, use the raw format", .0, .1)]
+    RasterTooLarge(usize, usize),
+
+    /// The EPT dataset is invalid or not supported
+    #[error("invalid EPT dataset: {}", .0)]
+    InvalidEpt(String),
+
+    /// A voxel key is not of the form `level-x-y-z`
+    #[error("invalid voxel key: {}", .0)]
+    InvalidVoxelKey(String),
+
+    /// A hierarchy page has an invalid size or offset or is referenced more than once
+    #[error("invalid hierarchy: {}", .0)]
+    InvalidHierarchy(String),
+
     /// The laszip vlr was not found, the points cannot be decompressed.
     #[error("laszip vlr not found")]
     LasZipVlrNotFound,
@@ -69,13 +101,12 @@ pub enum Error {
     #[error(transparent)]
     InvalidPoint(crate::PointAddError),
 
-    /// If a copc writer is created with invalid max or min node cound bounds
+    /// If a copc writer is configured with invalid options
     #[cfg(feature = "writer")]
-    #[error("the set min or max sizes for point in node is invalid")]
-    InvalidNodeSize,
+    #[error(transparent)]
+    InvalidWriterConfig(crate::WriterConfigError),
 
     /// Unsupported epsg
-    #[cfg(feature = "writer")]
     #[error("the found epsg-code is not defined in the crs-definitions library")]
     InvalidEPSGCode(u16),
 
@@ -83,6 +114,21 @@ pub enum Error {
     #[cfg(feature = "writer")]
     #[error("the lidar file have no defined crs")]
     NoCRSDefined,
+
+    /// No input files were passed to [crate::merge]
+    #[cfg(feature = "writer")]
+    #[error("there are no input files to merge")]
+    NoMergeInputs,
+
+    /// An input passed to [crate::merge] has a different CRS than the first input
+    #[cfg(feature = "writer")]
+    #[error("the CRS of {} differs from the CRS of the first input", .0.display())]
+    MergeCrsMismatch(std::path::PathBuf),
+
+    /// [crate::repair] needs to rewrite the file, but the rewrite is not allowed
+    #[cfg(feature = "writer")]
+    #[error("the file can only be repaired by rewriting it: {0}")]
+    RepairNeedsRewrite(String),
 }
 
 /// crate specific Error enum related to adding points to the writer
@@ -104,4 +150,73 @@ pub enum PointAddError {
     /// [write]: crate::writer::CopcWriter::write
     #[error("A point in the iterator was not inside the bounds of the header")]
     PointNotInBounds,
+
+    /// A point in the iterator passed to [write] has no gps time
+    /// and the writer is configured with [GpsTimePolicy::Error]
+    ///
+    /// [write]: crate::writer::CopcWriter::write
+    /// [GpsTimePolicy::Error]: crate::GpsTimePolicy::Error
+    #[error("A point in the iterator has no gps time")]
+    MissingGpsTime,
+}
+
+/// crate specific Error enum related to configuring the writer
+///
+/// All options are validated once in [build]
+///
+/// [build]: crate::CopcWriterBuilder::build
+#[cfg(feature = "writer")]
+#[derive(Error, Debug)]
+pub enum WriterConfigError {
+    /// The minimal number of points in a node must be at least 1
+    #[error("the min node size must be at least 1, got {}", .0)]
+    InvalidMinNodeSize(i32),
+
+    /// The maximal number of points in a node must be greater than the minimal number
+    #[error("the max node size ({max}) must be greater than the min node size ({min})")]
+    InvalidMaxNodeSize {
+        /// the configured min node size
+        min: i32,
+        /// the configured max node size
+        max: i32,
+    },
+
+    /// The maximal octree depth can not be negative
+    #[error("the max octree depth can not be negative, got {}", .0)]
+    InvalidMaxDepth(i32),
+
+    /// A hierarchy page must be able to hold its root entry and the 8 entries of its children
+    #[error("a hierarchy page must hold at least 9 entries, got {}", .0)]
+    InvalidHierarchyPageSize(usize),
+
+    /// The scale of an overridden transform is not a positive normal number
+    /// or its offset is not finite
+    #[error("the transform {} is not valid", .0)]
+    InvalidTransform(las::Transform),
+
+    /// The precision for automatic bounds is not a positive normal number
+    #[error("the precision {} is not valid", .0)]
+    InvalidPrecision(f64),
+
+    /// The constant gps time for points without one is not finite
+    #[error("the constant gps time {} is not finite", .0)]
+    InvalidGpsTime(f64),
+
+    /// The overridden CRS WKT is empty
+    #[error("the provided CRS WKT is empty")]
+    EmptyCrsWkt,
+
+    /// The overridden CRS EPSG code is not defined in the crs-definitions library
+    #[error("the EPSG code {} is not defined in the crs-definitions library", .0)]
+    UnknownEpsgCode(u16),
+
+    /// A declared extra attribute has an empty, too long or duplicate name,
+    /// or is undocumented without bytes
+    #[error("the extra attribute {:?} is not valid", .0)]
+    InvalidExtraAttribute(String),
+
+    /// The point data record format of the header needs an upgrade
+    /// to be written to COPC, but upgrading was disabled
+    #[error("the point data record format {} needs an upgrade, but upgrading is disabled", .0)]
+    PdrfUpgradeDisabled(u8),
 }
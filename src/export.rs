@@ -0,0 +1,492 @@
+//! Export of query results to LAS/LAZ, COPC, PLY and CSV files.
+
+use crate::extra_bytes::ExtraBytes;
+use crate::reader::{BoundsSelection, CopcReader, LodSelection};
+use byteorder::{LittleEndian, WriteBytesExt};
+use las::point::Format;
+use las::{Bounds, Builder, Header, Point};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Summary of an export of a query
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ExportSummary {
+    /// Number of points written
+    pub points_written: u64,
+    /// Bounds of the written points
+    pub bounds: Bounds,
+}
+
+/// A point attribute written by [CopcReader::export_ply] and [CopcReader::export_csv]
+///
+/// Parsed from and displayed as its column name, e.g. `gps_time`,
+/// any other name is an extra attribute
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PointAttribute {
+    /// The x coordinate, `x`
+    X,
+    /// The y coordinate, `y`
+    Y,
+    /// The z coordinate, `z`
+    Z,
+    /// `intensity`
+    Intensity,
+    /// `return_number`
+    ReturnNumber,
+    /// `number_of_returns`
+    NumberOfReturns,
+    /// `classification`
+    Classification,
+    /// The scan angle in degrees, `scan_angle`
+    ScanAngle,
+    /// `user_data`
+    UserData,
+    /// `point_source_id`
+    PointSourceId,
+    /// `gps_time`
+    GpsTime,
+    /// The 16 bit red channel, `red`
+    Red,
+    /// The 16 bit green channel, `green`
+    Green,
+    /// The 16 bit blue channel, `blue`
+    Blue,
+    /// The 16 bit near infrared channel, `nir`
+    Nir,
+    /// An extra attribute described in the extra bytes VLR, by name
+    Extra(String),
+}
+
+impl PointAttribute {
+    /// The attributes of `format`: coordinates, intensity, returns, classification,
+    /// gps time and the colors and NIR if present
+    pub fn defaults(format: &Format) -> Vec<PointAttribute> {
+        use PointAttribute::*;
+
+        let mut attributes = vec![
+            X,
+            Y,
+            Z,
+            Intensity,
+            ReturnNumber,
+            NumberOfReturns,
+            Classification,
+        ];
+        if format.has_gps_time {
+            attributes.push(GpsTime);
+        }
+        if format.has_color {
+            attributes.extend([Red, Green, Blue]);
+        }
+        if format.has_nir {
+            attributes.push(Nir);
+        }
+        attributes
+    }
+
+    /// The value of the attribute, `None` if the point does not have it
+    pub(crate) fn value(&self, point: &Point, extra_bytes: &ExtraBytes) -> Option<f64> {
+        use PointAttribute::*;
+
+        Some(match self {
+            X => point.x,
+            Y => point.y,
+            Z => point.z,
+            Intensity => point.intensity as f64,
+            ReturnNumber => point.return_number as f64,
+            NumberOfReturns => point.number_of_returns as f64,
+            Classification => u8::from(point.classification) as f64,
+            ScanAngle => point.scan_angle as f64,
+            UserData => point.user_data as f64,
+            PointSourceId => point.point_source_id as f64,
+            GpsTime => point.gps_time?,
+            Red => point.color?.red as f64,
+            Green => point.color?.green as f64,
+            Blue => point.color?.blue as f64,
+            Nir => point.nir? as f64,
+            Extra(name) => extra_bytes.get(point, name)?,
+        })
+    }
+
+    /// Whether the values are not integers
+    fn is_float(&self) -> bool {
+        use PointAttribute::*;
+
+        matches!(self, X | Y | Z | ScanAngle | GpsTime | Extra(_))
+    }
+
+    /// The PLY property type
+    fn ply_type(&self) -> PlyType {
+        use PointAttribute::*;
+
+        match self {
+            X | Y | Z | GpsTime | Extra(_) => PlyType::Double,
+            ScanAngle => PlyType::Float,
+            Intensity | PointSourceId | Nir => PlyType::UShort,
+            ReturnNumber | NumberOfReturns | Classification | UserData | Red | Green | Blue => {
+                PlyType::UChar
+            }
+        }
+    }
+}
+
+impl fmt::Display for PointAttribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use PointAttribute::*;
+
+        let name = match self {
+            X => "x",
+            Y => "y",
+            Z => "z",
+            Intensity => "intensity",
+            ReturnNumber => "return_number",
+            NumberOfReturns => "number_of_returns",
+            Classification => "classification",
+            ScanAngle => "scan_angle",
+            UserData => "user_data",
+            PointSourceId => "point_source_id",
+            GpsTime => "gps_time",
+            Red => "red",
+            Green => "green",
+            Blue => "blue",
+            Nir => "nir",
+            Extra(name) => name,
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for PointAttribute {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use PointAttribute::*;
+
+        Ok(match s.to_lowercase().as_str() {
+            "x" => X,
+            "y" => Y,
+            "z" => Z,
+            "intensity" => Intensity,
+            "return_number" => ReturnNumber,
+            "number_of_returns" => NumberOfReturns,
+            "classification" => Classification,
+            "scan_angle" => ScanAngle,
+            "user_data" => UserData,
+            "point_source_id" => PointSourceId,
+            "gps_time" => GpsTime,
+            "red" => Red,
+            "green" => Green,
+            "blue" => Blue,
+            "nir" => Nir,
+            _ => Extra(s.to_string()),
+        })
+    }
+}
+
+/// Encoding of a PLY file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PlyFormat {
+    /// Binary values in little endian byte order
+    #[default]
+    BinaryLittleEndian,
+    /// One line of text per point
+    Ascii,
+}
+
+/// Options for [CopcReader::export_ply]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlyOptions {
+    /// The encoding of the vertices, defaults to binary little endian
+    pub format: PlyFormat,
+    /// The vertex properties, defaults to [PointAttribute::defaults]
+    ///
+    /// Coordinates, gps time and extra attributes are doubles, colors are scaled to 8 bit
+    pub attributes: Option<Vec<PointAttribute>>,
+    /// Add `nx`, `ny` and `nz` float properties of 0 after the z coordinate,
+    /// to be filled by normal estimation, the x, y and z attributes are required
+    pub normals: bool,
+}
+
+/// Options for [CopcReader::export_csv]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsvOptions {
+    /// The column separator, defaults to `,`, use `\t` for TSV
+    pub delimiter: char,
+    /// The columns, defaults to [PointAttribute::defaults]
+    pub columns: Option<Vec<PointAttribute>>,
+    /// Number of decimals of the non-integer columns, defaults to the shortest exact representation
+    pub precision: Option<usize>,
+    /// Write a first line with the column names, defaults to true
+    pub header: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            columns: None,
+            precision: None,
+            header: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum PlyType {
+    UChar,
+    UShort,
+    Float,
+    Double,
+}
+
+impl PlyType {
+    fn name(&self) -> &str {
+        match self {
+            PlyType::UChar => "uchar",
+            PlyType::UShort => "ushort",
+            PlyType::Float => "float",
+            PlyType::Double => "double",
+        }
+    }
+}
+
+impl<R: Read + Seek> CopcReader<R> {
+    /// Writes the points of a query to a LAS or LAZ file at `path`,
+    /// compressed if the extension is `.laz`
+    ///
+    /// The header of the COPC file is kept with its (e)vlrs, like the CRS and the extra bytes,
+    /// without the COPC info VLR and the EPT hierarchy EVLR.
+    /// The point counts, return counts and bounds are recomputed from the written points.
+    ///
+    /// ```no_run
+    /// use copc_rs::{Bounds, BoundsSelection, CopcReader, LodSelection, Vector};
+    ///
+    /// let mut copc_reader = CopcReader::from_path("./lidar.copc.laz").unwrap();
+    /// let clip = Bounds {
+    ///     min: Vector { x: 637000., y: 851000., z: 0. },
+    ///     max: Vector { x: 638000., y: 852000., z: 1000. },
+    /// };
+    /// copc_reader
+    ///     .export_las("./clip.laz", LodSelection::All, BoundsSelection::Within(clip))
+    ///     .unwrap();
+    /// ```
+    pub fn export_las<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        levels: LodSelection,
+        bounds: BoundsSelection,
+    ) -> crate::Result<ExportSummary> {
+        let header = export_header(self.header())?;
+        let mut writer = las::Writer::from_path(path, header)?;
+        for point in self.points(levels, bounds)? {
+            writer.write_point(point)?;
+        }
+        let summary = ExportSummary {
+            points_written: writer.header().number_of_points(),
+            bounds: writer.header().bounds(),
+        };
+        writer.close()?;
+        Ok(summary)
+    }
+
+    /// Writes the points of a query to a new COPC file at `path`
+    ///
+    /// The points are first counted in a streaming pass,
+    /// with `recompute_bounds` the octree is fitted to the queried points.
+    /// See [crate::recopc] for the other options
+    #[cfg(feature = "writer")]
+    pub fn export_copc<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        levels: LodSelection,
+        bounds: BoundsSelection,
+        options: &crate::ConvertOptions,
+    ) -> crate::Result<crate::ConversionSummary> {
+        crate::convert::copc_from_query(self, levels, bounds, path, options)
+    }
+
+    /// Writes the points of a query to a PLY file at `path`
+    ///
+    /// The points are counted first, without bounds from the hierarchy, then streamed to the file.
+    /// Missing attributes, like the colors of a format without colors, are 0
+    pub fn export_ply<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        levels: LodSelection,
+        bounds: BoundsSelection,
+        options: &PlyOptions,
+    ) -> crate::Result<ExportSummary> {
+        let attributes = self.export_attributes(options.attributes.as_ref())?;
+        // the index of the attribute the normals follow
+        let normals_after = if options.normals {
+            let has = |a: PointAttribute| attributes.contains(&a);
+            if !(has(PointAttribute::X) && has(PointAttribute::Y) && has(PointAttribute::Z)) {
+                return Err(crate::Error::PlyNormalsWithoutCoordinates);
+            }
+            attributes.iter().position(|a| *a == PointAttribute::Z)
+        } else {
+            None
+        };
+        let extra_bytes = self.extra_bytes().clone();
+        // the vertex count precedes the vertices
+        let vertex_count = self.query_point_count(levels, bounds)?;
+        let mut write = BufWriter::new(File::create(path)?);
+
+        let format = match options.format {
+            PlyFormat::BinaryLittleEndian => "binary_little_endian",
+            PlyFormat::Ascii => "ascii",
+        };
+        let mut ply_header = format!(
+            "ply\nformat {format} 1.0\ncomment generated by copc-rs {}\nelement vertex {vertex_count}\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        for (i, attribute) in attributes.iter().enumerate() {
+            ply_header.push_str(&format!(
+                "property {} {attribute}\n",
+                attribute.ply_type().name()
+            ));
+            if normals_after == Some(i) {
+                ply_header.push_str("property float nx\nproperty float ny\nproperty float nz\n");
+            }
+        }
+        ply_header.push_str("end_header\n");
+        write.write_all(ply_header.as_bytes())?;
+
+        let mut summary = ExportSummary::default();
+        for point in self.points(levels, bounds)? {
+            for (i, attribute) in attributes.iter().enumerate() {
+                let mut value = attribute.value(&point, &extra_bytes).unwrap_or(0.);
+                if matches!(
+                    attribute,
+                    PointAttribute::Red | PointAttribute::Green | PointAttribute::Blue
+                ) {
+                    value = (value as u16 >> 8) as f64;
+                }
+                write_ply_value(&mut write, options.format, attribute.ply_type(), value, i)?;
+                if normals_after == Some(i) {
+                    for _ in 0..3 {
+                        write_ply_value(&mut write, options.format, PlyType::Float, 0., 1)?;
+                    }
+                }
+            }
+            if options.format == PlyFormat::Ascii {
+                writeln!(write)?;
+            }
+            summary.points_written += 1;
+            summary.bounds.grow(&point);
+        }
+
+        write.flush()?;
+        Ok(summary)
+    }
+
+    /// Writes the points of a query to a CSV file at `path`, or a TSV file with a `\t` delimiter
+    ///
+    /// The points are streamed to the file, missing attributes are empty
+    pub fn export_csv<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        levels: LodSelection,
+        bounds: BoundsSelection,
+        options: &CsvOptions,
+    ) -> crate::Result<ExportSummary> {
+        let columns = self.export_attributes(options.columns.as_ref())?;
+        let extra_bytes = self.extra_bytes().clone();
+        let mut write = BufWriter::new(File::create(path)?);
+        let delimiter = options.delimiter;
+
+        if options.header {
+            let names: Vec<String> = columns.iter().map(ToString::to_string).collect();
+            writeln!(write, "{}", names.join(&delimiter.to_string()))?;
+        }
+
+        let mut summary = ExportSummary::default();
+        for point in self.points(levels, bounds)? {
+            for (i, column) in columns.iter().enumerate() {
+                if i > 0 {
+                    write!(write, "{delimiter}")?;
+                }
+                match (column.value(&point, &extra_bytes), options.precision) {
+                    (None, _) => (),
+                    (Some(value), Some(precision)) if column.is_float() => {
+                        write!(write, "{value:.precision$}")?
+                    }
+                    (Some(value), _) => write!(write, "{value}")?,
+                }
+            }
+            writeln!(write)?;
+            summary.points_written += 1;
+            summary.bounds.grow(&point);
+        }
+        write.flush()?;
+        Ok(summary)
+    }
+
+    /// The `attributes`, or the defaults of the point format, checked to exist
+    fn export_attributes(
+        &self,
+        attributes: Option<&Vec<PointAttribute>>,
+    ) -> crate::Result<Vec<PointAttribute>> {
+        let attributes = attributes
+            .cloned()
+            .unwrap_or_else(|| PointAttribute::defaults(self.header().point_format()));
+        for attribute in &attributes {
+            if let PointAttribute::Extra(name) = attribute {
+                if !self
+                    .extra_bytes()
+                    .descriptors()
+                    .iter()
+                    .any(|d| &d.name == name)
+                {
+                    return Err(crate::Error::UnknownAttribute(name.clone()));
+                }
+            }
+        }
+        Ok(attributes)
+    }
+}
+
+/// The header of a COPC file without the COPC (e)vlrs, to be written as LAS/LAZ
+fn export_header(header: &Header) -> crate::Result<Header> {
+    let mut builder = Builder::from(header.clone());
+    builder.vlrs.retain(|vlr| {
+        !matches!(
+            (vlr.user_id.to_lowercase().as_str(), vlr.record_id),
+            ("copc", 1) | ("laszip encoded", 22204)
+        )
+    });
+    builder
+        .evlrs
+        .retain(|evlr| !(evlr.user_id.eq_ignore_ascii_case("copc") && evlr.record_id == 1000));
+    builder.vlr_padding.clear();
+    Ok(builder.into_header()?)
+}
+
+fn write_ply_value<W: Write>(
+    write: &mut W,
+    format: PlyFormat,
+    ply_type: PlyType,
+    value: f64,
+    index: usize,
+) -> std::io::Result<()> {
+    match format {
+        PlyFormat::BinaryLittleEndian => match ply_type {
+            PlyType::UChar => write.write_u8(value as u8),
+            PlyType::UShort => write.write_u16::<LittleEndian>(value as u16),
+            PlyType::Float => write.write_f32::<LittleEndian>(value as f32),
+            PlyType::Double => write.write_f64::<LittleEndian>(value),
+        },
+        PlyFormat::Ascii => {
+            if index > 0 {
+                write!(write, " ")?;
+            }
+            match ply_type {
+                PlyType::Float => write!(write, "{}", value as f32),
+                _ => write!(write, "{value}"),
+            }
+        }
+    }
+}
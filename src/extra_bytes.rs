@@ -0,0 +1,407 @@
+//! Extra bytes descriptors (LAS extra bytes VLR).
+
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use las::{Header, Point, Vlr};
+use std::io::{Cursor, Read, Write};
+
+/// Size of one descriptor in the extra bytes VLR
+const DESCRIPTOR_SIZE: usize = 192;
+
+/// Data type of an extra attribute
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtraBytesType {
+    /// The given number of bytes without a documented type
+    Undocumented(u8),
+    /// unsigned char
+    U8,
+    /// char
+    I8,
+    /// unsigned short
+    U16,
+    /// short
+    I16,
+    /// unsigned long
+    U32,
+    /// long
+    I32,
+    /// unsigned long long
+    U64,
+    /// long long
+    I64,
+    /// float
+    F32,
+    /// double
+    F64,
+}
+
+impl ExtraBytesType {
+    /// Number of bytes of a value of this type
+    pub fn size(&self) -> usize {
+        match self {
+            ExtraBytesType::Undocumented(size) => *size as usize,
+            ExtraBytesType::U8 | ExtraBytesType::I8 => 1,
+            ExtraBytesType::U16 | ExtraBytesType::I16 => 2,
+            ExtraBytesType::U32 | ExtraBytesType::I32 | ExtraBytesType::F32 => 4,
+            ExtraBytesType::U64 | ExtraBytesType::I64 | ExtraBytesType::F64 => 8,
+        }
+    }
+
+    fn is_float(&self) -> bool {
+        matches!(self, ExtraBytesType::F32 | ExtraBytesType::F64)
+    }
+
+    fn is_signed(&self) -> bool {
+        matches!(
+            self,
+            ExtraBytesType::I8 | ExtraBytesType::I16 | ExtraBytesType::I32 | ExtraBytesType::I64
+        )
+    }
+
+    /// The type of a raw data type, unsupported types are read as undocumented bytes
+    fn from_raw(data_type: u8, options: u8) -> Self {
+        match data_type {
+            0 => ExtraBytesType::Undocumented(options),
+            1 => ExtraBytesType::U8,
+            2 => ExtraBytesType::I8,
+            3 => ExtraBytesType::U16,
+            4 => ExtraBytesType::I16,
+            5 => ExtraBytesType::U32,
+            6 => ExtraBytesType::I32,
+            7 => ExtraBytesType::U64,
+            8 => ExtraBytesType::I64,
+            9 => ExtraBytesType::F32,
+            10 => ExtraBytesType::F64,
+            // 11-30 are deprecated arrays of two or three values of the types 1-10
+            11..=30 => {
+                let values = (data_type - 11) / 10 + 2;
+                let size = Self::from_raw((data_type - 11) % 10 + 1, 0).size() as u8;
+                log::warn!(
+                    "deprecated extra bytes data type {data_type}, read as undocumented bytes"
+                );
+                ExtraBytesType::Undocumented(values * size)
+            }
+            _ => {
+                log::warn!("unknown extra bytes data type {data_type}, read as undocumented bytes");
+                ExtraBytesType::Undocumented(options)
+            }
+        }
+    }
+
+    fn to_raw(self) -> u8 {
+        match self {
+            ExtraBytesType::Undocumented(_) => 0,
+            ExtraBytesType::U8 => 1,
+            ExtraBytesType::I8 => 2,
+            ExtraBytesType::U16 => 3,
+            ExtraBytesType::I16 => 4,
+            ExtraBytesType::U32 => 5,
+            ExtraBytesType::I32 => 6,
+            ExtraBytesType::U64 => 7,
+            ExtraBytesType::I64 => 8,
+            ExtraBytesType::F32 => 9,
+            ExtraBytesType::F64 => 10,
+        }
+    }
+
+    /// The unscaled value stored in `bytes`, `None` for undocumented bytes
+    pub(crate) fn decode(&self, bytes: &[u8]) -> Option<f64> {
+        Some(match self {
+            ExtraBytesType::Undocumented(_) => return None,
+            ExtraBytesType::U8 => bytes[0] as f64,
+            ExtraBytesType::I8 => bytes[0] as i8 as f64,
+            ExtraBytesType::U16 => LittleEndian::read_u16(bytes) as f64,
+            ExtraBytesType::I16 => LittleEndian::read_i16(bytes) as f64,
+            ExtraBytesType::U32 => LittleEndian::read_u32(bytes) as f64,
+            ExtraBytesType::I32 => LittleEndian::read_i32(bytes) as f64,
+            ExtraBytesType::U64 => LittleEndian::read_u64(bytes) as f64,
+            ExtraBytesType::I64 => LittleEndian::read_i64(bytes) as f64,
+            ExtraBytesType::F32 => LittleEndian::read_f32(bytes) as f64,
+            ExtraBytesType::F64 => LittleEndian::read_f64(bytes),
+        })
+    }
+
+    /// Stores the unscaled `value` in `bytes`, saturating at the limits of the type
+    fn encode(&self, value: f64, bytes: &mut [u8]) -> bool {
+        match self {
+            ExtraBytesType::Undocumented(_) => return false,
+            ExtraBytesType::U8 => bytes[0] = value.round() as u8,
+            ExtraBytesType::I8 => bytes[0] = value.round() as i8 as u8,
+            ExtraBytesType::U16 => LittleEndian::write_u16(bytes, value.round() as u16),
+            ExtraBytesType::I16 => LittleEndian::write_i16(bytes, value.round() as i16),
+            ExtraBytesType::U32 => LittleEndian::write_u32(bytes, value.round() as u32),
+            ExtraBytesType::I32 => LittleEndian::write_i32(bytes, value.round() as i32),
+            ExtraBytesType::U64 => LittleEndian::write_u64(bytes, value.round() as u64),
+            ExtraBytesType::I64 => LittleEndian::write_i64(bytes, value.round() as i64),
+            ExtraBytesType::F32 => LittleEndian::write_f32(bytes, value as f32),
+            ExtraBytesType::F64 => LittleEndian::write_f64(bytes, value),
+        }
+        true
+    }
+}
+
+/// Description of one extra attribute of the points
+///
+/// `no_data`, `min` and `max` are unscaled values,
+/// the value of the attribute is `raw * scale + offset`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtraBytesDescriptor {
+    /// Name of the attribute, at most 32 bytes
+    pub name: String,
+    /// Data type of the attribute
+    pub data_type: ExtraBytesType,
+    /// Description of the attribute, at most 32 bytes
+    pub description: String,
+    /// Value marking a point without a value for this attribute
+    pub no_data: Option<f64>,
+    /// Minimal value of the attribute
+    pub min: Option<f64>,
+    /// Maximal value of the attribute
+    pub max: Option<f64>,
+    /// Scale of the attribute
+    pub scale: Option<f64>,
+    /// Offset of the attribute
+    pub offset: Option<f64>,
+}
+
+impl ExtraBytesDescriptor {
+    /// Creates a descriptor without description, no-data value, limits, scale and offset
+    pub fn new<S: Into<String>>(name: S, data_type: ExtraBytesType) -> Self {
+        ExtraBytesDescriptor {
+            name: name.into(),
+            data_type,
+            description: String::new(),
+            no_data: None,
+            min: None,
+            max: None,
+            scale: None,
+            offset: None,
+        }
+    }
+
+    /// Number of extra bytes of the attribute in each point
+    pub fn size(&self) -> usize {
+        self.data_type.size()
+    }
+
+    /// Reads all descriptors from the data of an extra bytes VLR
+    pub fn read_all(data: &[u8]) -> crate::Result<Vec<Self>> {
+        if !data.len().is_multiple_of(DESCRIPTOR_SIZE) {
+            return Err(crate::Error::InvalidExtraBytesVlr(format!(
+                "the vlr data length {} is not a multiple of {DESCRIPTOR_SIZE}",
+                data.len()
+            )));
+        }
+        data.chunks_exact(DESCRIPTOR_SIZE)
+            .map(|chunk| Self::read_from(Cursor::new(chunk)))
+            .collect()
+    }
+
+    fn read_from<R: Read>(mut read: R) -> crate::Result<Self> {
+        let _reserved = read.read_u16::<LittleEndian>()?;
+        let raw_type = read.read_u8()?;
+        let options = read.read_u8()?;
+        let data_type = ExtraBytesType::from_raw(raw_type, options);
+
+        let mut name = [0; 32];
+        read.read_exact(&mut name)?;
+        let _unused = read.read_u32::<LittleEndian>()?;
+
+        // each value has room for three, only the first is used since LAS 1.4 R15
+        let read_value = |read: &mut R| -> crate::Result<f64> {
+            let mut value = [0; 24];
+            read.read_exact(&mut value)?;
+            Ok(if data_type.is_float() {
+                LittleEndian::read_f64(&value)
+            } else if data_type.is_signed() {
+                LittleEndian::read_i64(&value) as f64
+            } else {
+                LittleEndian::read_u64(&value) as f64
+            })
+        };
+        let no_data = read_value(&mut read)?;
+        let min = read_value(&mut read)?;
+        let max = read_value(&mut read)?;
+
+        let read_f64 = |read: &mut R| -> crate::Result<f64> {
+            let mut value = [0; 24];
+            read.read_exact(&mut value)?;
+            Ok(LittleEndian::read_f64(&value))
+        };
+        let scale = read_f64(&mut read)?;
+        let offset = read_f64(&mut read)?;
+
+        let mut description = [0; 32];
+        read.read_exact(&mut description)?;
+
+        // the options of undocumented bytes hold the number of bytes
+        let documented = !matches!(data_type, ExtraBytesType::Undocumented(_));
+        let option =
+            |bit: u8, value: f64| (documented && options & (1 << bit) != 0).then_some(value);
+        Ok(ExtraBytesDescriptor {
+            name: string_from_bytes(&name),
+            data_type,
+            description: string_from_bytes(&description),
+            no_data: option(0, no_data),
+            min: option(1, min),
+            max: option(2, max),
+            scale: option(3, scale),
+            offset: option(4, offset),
+        })
+    }
+
+    fn write_to<W: Write>(&self, mut write: W) -> crate::Result<()> {
+        write.write_u16::<LittleEndian>(0)?;
+        write.write_u8(self.data_type.to_raw())?;
+        let options = match self.data_type {
+            ExtraBytesType::Undocumented(size) => size,
+            _ => [self.no_data, self.min, self.max, self.scale, self.offset]
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| v.is_some())
+                .fold(0, |options, (bit, _)| options | (1 << bit)),
+        };
+        write.write_u8(options)?;
+        write.write_all(&bytes_from_string(&self.name))?;
+        write.write_u32::<LittleEndian>(0)?;
+
+        for value in [self.no_data, self.min, self.max] {
+            let value = value.unwrap_or(0.);
+            let mut bytes = [0; 24];
+            if self.data_type.is_float() {
+                LittleEndian::write_f64(&mut bytes, value);
+            } else if self.data_type.is_signed() {
+                LittleEndian::write_i64(&mut bytes, value as i64);
+            } else {
+                LittleEndian::write_u64(&mut bytes, value as u64);
+            }
+            write.write_all(&bytes)?;
+        }
+        for value in [self.scale, self.offset] {
+            let mut bytes = [0; 24];
+            LittleEndian::write_f64(&mut bytes, value.unwrap_or(0.));
+            write.write_all(&bytes)?;
+        }
+        write.write_all(&bytes_from_string(&self.description))?;
+        Ok(())
+    }
+}
+
+/// Extra attributes of the points, parsed from the extra bytes (e)vlr of a header
+///
+/// ```no_run
+/// use copc_rs::{BoundsSelection, CopcReader, LodSelection};
+///
+/// let mut reader = CopcReader::from_path("./lidar.copc.laz").unwrap();
+/// let extra_bytes = reader.extra_bytes().clone();
+/// for point in reader.points(LodSelection::Level(0), BoundsSelection::All).unwrap() {
+///     println!("{:?}", extra_bytes.get(&point, "Amplitude"));
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExtraBytes {
+    descriptors: Vec<ExtraBytesDescriptor>,
+}
+
+impl ExtraBytes {
+    /// Creates the extra attributes from descriptors, in the order of the extra bytes of the points
+    pub fn new(descriptors: Vec<ExtraBytesDescriptor>) -> Self {
+        ExtraBytes { descriptors }
+    }
+
+    /// Parses the extra bytes (e)vlr of the header, if there is one
+    pub fn from_header(header: &Header) -> crate::Result<Self> {
+        match header
+            .all_vlrs()
+            .find(|v| v.user_id.eq_ignore_ascii_case("lasf_spec") && v.record_id == 4)
+        {
+            Some(vlr) => Ok(ExtraBytes::new(ExtraBytesDescriptor::read_all(&vlr.data)?)),
+            None => Ok(ExtraBytes::default()),
+        }
+    }
+
+    /// The descriptors of the attributes
+    pub fn descriptors(&self) -> &[ExtraBytesDescriptor] {
+        &self.descriptors
+    }
+
+    /// Creates an extra bytes VLR from the descriptors
+    pub fn to_vlr(&self) -> crate::Result<Vlr> {
+        let mut data = Vec::with_capacity(self.descriptors.len() * DESCRIPTOR_SIZE);
+        for descriptor in &self.descriptors {
+            descriptor.write_to(&mut data)?;
+        }
+        Ok(Vlr {
+            user_id: "LASF_Spec".to_string(),
+            record_id: 4,
+            description: "Extra bytes".to_string(),
+            data,
+        })
+    }
+
+    /// Total number of extra bytes described
+    pub fn size(&self) -> usize {
+        self.descriptors.iter().map(|d| d.size()).sum()
+    }
+
+    /// The descriptor of the attribute `name` and the range of its bytes in the extra bytes
+    fn find(&self, name: &str) -> Option<(&ExtraBytesDescriptor, std::ops::Range<usize>)> {
+        let mut start = 0;
+        for descriptor in &self.descriptors {
+            let end = start + descriptor.size();
+            if descriptor.name == name {
+                return Some((descriptor, start..end));
+            }
+            start = end;
+        }
+        None
+    }
+
+    /// The raw bytes of the attribute `name` of the point
+    pub fn raw<'p>(&self, point: &'p Point, name: &str) -> Option<&'p [u8]> {
+        let (_, range) = self.find(name)?;
+        point.extra_bytes.get(range)
+    }
+
+    /// The scaled value of the attribute `name` of the point
+    ///
+    /// `None` if there is no such attribute, the attribute is undocumented
+    /// or the point has the no-data value
+    pub fn get(&self, point: &Point, name: &str) -> Option<f64> {
+        let (descriptor, range) = self.find(name)?;
+        let raw = descriptor.data_type.decode(point.extra_bytes.get(range)?)?;
+        if descriptor.no_data == Some(raw) {
+            return None;
+        }
+        Some(raw * descriptor.scale.unwrap_or(1.) + descriptor.offset.unwrap_or(0.))
+    }
+
+    /// Sets the attribute `name` of the point to the scaled `value`,
+    /// the extra bytes of the point are zero-padded to the described size
+    ///
+    /// Returns `false` if there is no such attribute or the attribute is undocumented
+    pub fn set(&self, point: &mut Point, name: &str, value: f64) -> bool {
+        let Some((descriptor, range)) = self.find(name) else {
+            return false;
+        };
+        let raw = (value - descriptor.offset.unwrap_or(0.)) / descriptor.scale.unwrap_or(1.);
+        if point.extra_bytes.len() < range.end {
+            point.extra_bytes.resize(range.end, 0);
+        }
+        descriptor
+            .data_type
+            .encode(raw, &mut point.extra_bytes[range])
+    }
+}
+
+fn string_from_bytes(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn bytes_from_string(string: &str) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    for (i, byte) in string.bytes().take(32).enumerate() {
+        bytes[i] = byte;
+    }
+    bytes
+}
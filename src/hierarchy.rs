@@ -0,0 +1,294 @@
+//! Inspection of the hierarchy of a COPC file.
+
+use crate::copc::VoxelKey;
+use crate::json::{object, Json};
+use crate::reader::CopcReader;
+use las::{Bounds, Vector};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{Read, Seek};
+
+/// Size of a hierarchy entry in bytes
+const ENTRY_SIZE: u64 = 32;
+
+/// A node of the hierarchy with the location and size of its data chunk
+#[derive(Clone, Debug, PartialEq)]
+pub struct HierarchyNode {
+    /// Key of the node in the octree
+    pub key: VoxelKey,
+    /// Bounds of the octree cell of the node
+    pub bounds: Bounds,
+    /// Offset of the data chunk, 0 without points
+    pub offset: u64,
+    /// Number of points in the node, 0 for a negative count of the entry
+    pub point_count: u64,
+    /// Size of the compressed data chunk in bytes
+    pub byte_size: u64,
+    /// Size of the uncompressed point records divided by the compressed size,
+    /// `None` without points
+    pub compression_ratio: Option<f64>,
+}
+
+/// Number of nodes, points and compressed bytes of an octree level
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LevelSummary {
+    /// Octree level, 0 for the root
+    pub level: i32,
+    /// Number of nodes of the level in the hierarchy, with or without points
+    pub nodes: usize,
+    /// Number of points in the nodes of the level
+    pub points: u64,
+    /// Size of the compressed data chunks of the level in bytes
+    pub bytes: u64,
+}
+
+/// Location of a hierarchy page
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HierarchyPageInfo {
+    /// Key of the first node of the page, the root of its subtree
+    pub key: VoxelKey,
+    /// Offset of the page in the file, as read from the COPC info or the parent page
+    pub offset: u64,
+    /// Size of the page in bytes, as read from the COPC info or the parent page,
+    /// not checked against the length of the file
+    pub byte_size: u64,
+}
+
+impl HierarchyPageInfo {
+    /// Number of entries in the page, including the ones pointing to child pages
+    pub fn entry_count(&self) -> u64 {
+        self.byte_size / ENTRY_SIZE
+    }
+}
+
+impl<R: Read + Seek> CopcReader<R> {
+    /// The nodes of the hierarchy ordered by key
+    pub fn hierarchy_nodes(&self) -> Vec<HierarchyNode> {
+        let root_bounds = self.root_bounds();
+        let record_length = self.header().point_format().len() as f64;
+        let mut nodes: Vec<HierarchyNode> = self
+            .hierarchy_entries()
+            .values()
+            .map(|entry| {
+                let point_count = entry.point_count.max(0) as u64;
+                let byte_size = entry.byte_size.max(0) as u64;
+                HierarchyNode {
+                    key: entry.key.clone(),
+                    bounds: entry.key.bounds(&root_bounds),
+                    offset: entry.offset,
+                    point_count,
+                    byte_size,
+                    compression_ratio: (point_count > 0 && byte_size > 0)
+                        .then(|| point_count as f64 * record_length / byte_size as f64),
+                }
+            })
+            .collect();
+        nodes.sort_by(|a, b| a.key.cmp(&b.key));
+        nodes
+    }
+
+    /// The number of nodes, points and compressed bytes of each level, from the root down
+    pub fn level_summaries(&self) -> Vec<LevelSummary> {
+        let mut levels: BTreeMap<i32, LevelSummary> = BTreeMap::new();
+        for entry in self.hierarchy_entries().values() {
+            let level = levels.entry(entry.key.level).or_insert(LevelSummary {
+                level: entry.key.level,
+                ..Default::default()
+            });
+            level.nodes += 1;
+            level.points += entry.point_count.max(0) as u64;
+            level.bytes += entry.byte_size.max(0) as u64;
+        }
+        levels.into_values().collect()
+    }
+
+    /// The hierarchy as a Graphviz digraph, with the point count and size of each node
+    ///
+    /// The nodes of each hierarchy page are grouped in a cluster
+    pub fn hierarchy_dot(&self) -> String {
+        let nodes = self.hierarchy_nodes();
+        // the page of a node is the page of its nearest ancestor starting a page
+        let page_roots: BTreeMap<VoxelKey, usize> = self
+            .hierarchy_pages()
+            .iter()
+            .enumerate()
+            .map(|(i, page)| (page.key.clone(), i))
+            .collect();
+        let mut pages: BTreeMap<usize, Vec<&HierarchyNode>> = BTreeMap::new();
+        for node in &nodes {
+            let page = std::iter::successors(Some(node.key.clone()), VoxelKey::parent)
+                .find_map(|key| page_roots.get(&key).copied())
+                .unwrap_or(0);
+            pages.entry(page).or_default().push(node);
+        }
+
+        let mut dot = String::from("digraph hierarchy {\n  node [shape=box];\n");
+        for (page, page_nodes) in &pages {
+            let _ = writeln!(dot, "  subgraph cluster_{page} {{");
+            let _ = writeln!(dot, "    label=\"page {page}\";");
+            for node in page_nodes {
+                let _ = writeln!(
+                    dot,
+                    "    \"{}\" [label=\"{}\\n{} points\\n{} bytes\"];",
+                    node.key, node.key, node.point_count, node.byte_size
+                );
+            }
+            let _ = writeln!(dot, "  }}");
+        }
+        for node in &nodes {
+            if let Some(parent) = node.key.parent() {
+                let _ = writeln!(dot, "  \"{parent}\" -> \"{}\";", node.key);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The header, COPC info, CRS and hierarchy summary as a JSON document,
+    /// as printed by `copc info --json`
+    pub fn info_json(&self) -> crate::Result<String> {
+        let header = self.header();
+        let copc_info = self.copc_info();
+        let bounds = header.bounds();
+        let levels = self.level_summaries();
+        let crs = self.crs().map(|crs| {
+            object([
+                ("epsg", crs.epsg().into()),
+                ("horizontal_epsg", crs.horizontal_epsg().into()),
+                ("vertical_epsg", crs.vertical_epsg().into()),
+                ("vertical_datum", crs.vertical_datum().into()),
+                (
+                    "horizontal_unit",
+                    crs.horizontal_unit().map(|u| u.name.as_str()).into(),
+                ),
+                (
+                    "vertical_unit",
+                    crs.vertical_unit().map(|u| u.name.as_str()).into(),
+                ),
+                ("wkt", crs.wkt().into()),
+            ])
+        });
+        let levels: Vec<Json> = levels
+            .iter()
+            .map(|level| {
+                object([
+                    ("level", level.level.into()),
+                    ("nodes", level.nodes.into()),
+                    ("points", level.points.into()),
+                    ("bytes", level.bytes.into()),
+                ])
+            })
+            .collect();
+        let json = object([
+            (
+                "header",
+                object([
+                    ("version", header.version().to_string().into()),
+                    ("point_format", header.point_format().to_u8()?.into()),
+                    ("number_of_points", header.number_of_points().into()),
+                    (
+                        "scale",
+                        vector_json(&Vector {
+                            x: header.transforms().x.scale,
+                            y: header.transforms().y.scale,
+                            z: header.transforms().z.scale,
+                        }),
+                    ),
+                    (
+                        "offset",
+                        vector_json(&Vector {
+                            x: header.transforms().x.offset,
+                            y: header.transforms().y.offset,
+                            z: header.transforms().z.offset,
+                        }),
+                    ),
+                    ("min", vector_json(&bounds.min)),
+                    ("max", vector_json(&bounds.max)),
+                ]),
+            ),
+            (
+                "copc_info",
+                object([
+                    ("center", vector_json(&copc_info.center)),
+                    ("halfsize", copc_info.halfsize.into()),
+                    ("spacing", copc_info.spacing.into()),
+                    ("root_hier_offset", copc_info.root_hier_offset.into()),
+                    ("root_hier_size", copc_info.root_hier_size.into()),
+                    ("gpstime_minimum", copc_info.gpstime_minimum.into()),
+                    ("gpstime_maximum", copc_info.gpstime_maximum.into()),
+                ]),
+            ),
+            ("crs", crs.into()),
+            (
+                "hierarchy",
+                object([
+                    ("nodes", self.hierarchy_entries().len().into()),
+                    ("depth", levels.len().into()),
+                    ("pages", self.hierarchy_pages().len().into()),
+                    ("levels", Json::Array(levels)),
+                ]),
+            ),
+        ]);
+        Ok(format!("{json}\n"))
+    }
+
+    /// The nodes, level summaries and pages of the hierarchy as a JSON document
+    pub fn hierarchy_json(&self) -> String {
+        let nodes: Vec<Json> = self
+            .hierarchy_nodes()
+            .iter()
+            .map(|node| {
+                let b = &node.bounds;
+                object([
+                    ("key", node.key.to_string().into()),
+                    ("level", node.key.level.into()),
+                    (
+                        "bounds",
+                        vec![b.min.x, b.min.y, b.min.z, b.max.x, b.max.y, b.max.z].into(),
+                    ),
+                    ("offset", node.offset.into()),
+                    ("point_count", node.point_count.into()),
+                    ("byte_size", node.byte_size.into()),
+                    (
+                        "compression_ratio",
+                        node.compression_ratio.map_or(Json::Null, Json::from),
+                    ),
+                ])
+            })
+            .collect();
+        let levels: Vec<Json> = self
+            .level_summaries()
+            .iter()
+            .map(|level| {
+                object([
+                    ("level", level.level.into()),
+                    ("nodes", level.nodes.into()),
+                    ("points", level.points.into()),
+                    ("bytes", level.bytes.into()),
+                ])
+            })
+            .collect();
+        let pages: Vec<Json> = self
+            .hierarchy_pages()
+            .iter()
+            .map(|page| {
+                object([
+                    ("key", page.key.to_string().into()),
+                    ("offset", page.offset.into()),
+                    ("byte_size", page.byte_size.into()),
+                    ("entries", page.entry_count().into()),
+                ])
+            })
+            .collect();
+        let json = object([
+            ("nodes", Json::Array(nodes)),
+            ("levels", Json::Array(levels)),
+            ("pages", Json::Array(pages)),
+        ]);
+        format!("{json}\n")
+    }
+}
+
+fn vector_json(v: &Vector<f64>) -> Json {
+    vec![v.x, v.y, v.z].into()
+}
@@ -7,17 +7,55 @@ const MAX_NODE_SIZE_DEFAULT: i32 = 16384;
 #[cfg(feature = "writer")]
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+#[cfg(feature = "writer")]
+mod builder;
 #[cfg(feature = "writer")]
 mod compressor;
+#[cfg(feature = "writer")]
+mod convert;
 mod copc;
+mod crs;
 mod decompressor;
+mod ept;
 mod error;
+mod export;
+mod extra_bytes;
+mod hierarchy;
+mod json;
+mod potree;
+mod raster;
 mod reader;
 #[cfg(feature = "writer")]
+mod repair;
+#[cfg(feature = "reproject")]
+mod reproject;
+mod statistics;
+mod tiles;
+mod validate;
+#[cfg(feature = "writer")]
 mod writer;
 
+#[cfg(feature = "writer")]
+pub use builder::*;
+#[cfg(feature = "writer")]
+pub use convert::*;
+pub use copc::{CopcInfo, Entry, HierarchyPage, VoxelKey};
+pub use crs::{Crs, CrsUnit, UnitKind};
+pub use ept::*;
 pub use error::*;
+pub use export::*;
+pub use extra_bytes::*;
+pub use hierarchy::*;
 pub use las::{Bounds, Vector};
+pub use potree::*;
+pub use raster::*;
 pub use reader::*;
 #[cfg(feature = "writer")]
+pub use repair::*;
+#[cfg(feature = "reproject")]
+pub use reproject::*;
+pub use statistics::*;
+pub use tiles::*;
+pub use validate::*;
+#[cfg(feature = "writer")]
 pub use writer::*;
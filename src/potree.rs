@@ -0,0 +1,466 @@
+//! Export of a COPC octree to a Potree 2.0 dataset.
+
+use crate::copc::VoxelKey;
+use crate::extra_bytes::ExtraBytesType;
+use crate::json::{object, Json};
+use crate::reader::CopcReader;
+use byteorder::{LittleEndian, WriteBytesExt};
+use las::Point;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// Size of a node in `hierarchy.bin`
+const NODE_SIZE: u64 = 22;
+
+/// Options for [CopcReader::export_potree]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PotreeOptions {
+    /// Name of the dataset in `metadata.json`, defaults to the name of the output directory
+    pub name: Option<String>,
+    /// Number of levels in a chunk of `hierarchy.bin`, defaults to 4
+    ///
+    /// The deeper nodes are in further chunks, loaded when the viewer needs them
+    pub hierarchy_step_size: u32,
+}
+
+impl Default for PotreeOptions {
+    fn default() -> Self {
+        PotreeOptions {
+            name: None,
+            hierarchy_step_size: 4,
+        }
+    }
+}
+
+/// Summary of [CopcReader::export_potree]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PotreeSummary {
+    /// Number of nodes in the hierarchy
+    pub nodes_written: usize,
+    /// Number of points written
+    pub points_written: u64,
+    /// Deepest level of the octree
+    pub depth: i32,
+}
+
+/// Source of the values of a Potree attribute
+#[derive(Clone, Debug)]
+enum Source {
+    Position,
+    Intensity,
+    ReturnNumber,
+    NumberOfReturns,
+    Classification,
+    ScanAngleRank,
+    ScanAngle,
+    UserData,
+    PointSourceId,
+    GpsTime,
+    Rgb,
+    Nir,
+    /// The range of the attribute in the extra bytes
+    Extra(Range<usize>, ExtraBytesType),
+}
+
+/// A Potree attribute with the range of its values
+struct Attribute {
+    name: String,
+    description: String,
+    source: Source,
+    potree_type: &'static str,
+    num_elements: usize,
+    element_size: usize,
+    min: Vec<f64>,
+    max: Vec<f64>,
+}
+
+impl Attribute {
+    fn new(name: &str, source: Source, potree_type: &'static str, num_elements: usize) -> Self {
+        let element_size = match potree_type {
+            "int8" | "uint8" => 1,
+            "int16" | "uint16" => 2,
+            "int32" | "uint32" | "float" => 4,
+            _ => 8,
+        };
+        Attribute {
+            name: name.to_string(),
+            description: String::new(),
+            source,
+            potree_type,
+            num_elements,
+            element_size,
+            min: vec![f64::INFINITY; num_elements],
+            max: vec![f64::NEG_INFINITY; num_elements],
+        }
+    }
+
+    /// The attributes of the points of `reader`, in the order of the point records
+    fn of<R: Read + Seek>(reader: &CopcReader<R>) -> Vec<Attribute> {
+        let format = reader.header().point_format();
+        let mut attributes = vec![
+            Attribute::new("position", Source::Position, "int32", 3),
+            Attribute::new("intensity", Source::Intensity, "uint16", 1),
+            Attribute::new("return number", Source::ReturnNumber, "uint8", 1),
+            Attribute::new("number of returns", Source::NumberOfReturns, "uint8", 1),
+            Attribute::new("classification", Source::Classification, "uint8", 1),
+            if format.is_extended {
+                Attribute::new("scan angle", Source::ScanAngle, "int16", 1)
+            } else {
+                Attribute::new("scan angle rank", Source::ScanAngleRank, "int8", 1)
+            },
+            Attribute::new("user data", Source::UserData, "uint8", 1),
+            Attribute::new("point source id", Source::PointSourceId, "uint16", 1),
+        ];
+        if format.has_gps_time {
+            attributes.push(Attribute::new("gps-time", Source::GpsTime, "double", 1));
+        }
+        if format.has_color {
+            attributes.push(Attribute::new("rgb", Source::Rgb, "uint16", 3));
+        }
+        if format.has_nir {
+            attributes.push(Attribute::new("nir", Source::Nir, "uint16", 1));
+        }
+
+        let mut start = 0;
+        for descriptor in reader.extra_bytes().descriptors() {
+            let range = start..start + descriptor.size();
+            start = range.end;
+            let potree_type = match descriptor.data_type {
+                ExtraBytesType::Undocumented(_) => "undefined",
+                ExtraBytesType::U8 => "uint8",
+                ExtraBytesType::I8 => "int8",
+                ExtraBytesType::U16 => "uint16",
+                ExtraBytesType::I16 => "int16",
+                ExtraBytesType::U32 => "uint32",
+                ExtraBytesType::I32 => "int32",
+                ExtraBytesType::U64 => "uint64",
+                ExtraBytesType::I64 => "int64",
+                ExtraBytesType::F32 => "float",
+                ExtraBytesType::F64 => "double",
+            };
+            let mut attribute = Attribute::new(
+                &descriptor.name,
+                Source::Extra(range, descriptor.data_type),
+                potree_type,
+                1,
+            );
+            attribute.element_size = descriptor.size();
+            attribute.description = descriptor.description.clone();
+            attributes.push(attribute);
+        }
+        attributes
+    }
+
+    fn size(&self) -> usize {
+        self.num_elements * self.element_size
+    }
+
+    /// Appends the value of the point to `buffer`, `raw` are the unscaled coordinates
+    fn encode(&mut self, point: &Point, raw: [i32; 3], buffer: &mut Vec<u8>) {
+        let mut values = [0.; 3];
+        match &self.source {
+            Source::Position => {
+                for value in raw {
+                    buffer.write_i32::<LittleEndian>(value).unwrap();
+                }
+                values = [point.x, point.y, point.z];
+            }
+            Source::Intensity => {
+                buffer.write_u16::<LittleEndian>(point.intensity).unwrap();
+                values[0] = point.intensity as f64;
+            }
+            Source::ReturnNumber => {
+                buffer.push(point.return_number);
+                values[0] = point.return_number as f64;
+            }
+            Source::NumberOfReturns => {
+                buffer.push(point.number_of_returns);
+                values[0] = point.number_of_returns as f64;
+            }
+            Source::Classification => {
+                let classification = u8::from(point.classification);
+                buffer.push(classification);
+                values[0] = classification as f64;
+            }
+            Source::ScanAngleRank => {
+                let rank = point.scan_angle.round() as i8;
+                buffer.push(rank as u8);
+                values[0] = rank as f64;
+            }
+            Source::ScanAngle => {
+                // in the 0.006° steps of the LAS 1.4 point formats
+                let angle = (point.scan_angle / 0.006).round() as i16;
+                buffer.write_i16::<LittleEndian>(angle).unwrap();
+                values[0] = angle as f64;
+            }
+            Source::UserData => {
+                buffer.push(point.user_data);
+                values[0] = point.user_data as f64;
+            }
+            Source::PointSourceId => {
+                buffer
+                    .write_u16::<LittleEndian>(point.point_source_id)
+                    .unwrap();
+                values[0] = point.point_source_id as f64;
+            }
+            Source::GpsTime => {
+                let gps_time = point.gps_time.unwrap_or(0.);
+                buffer.write_f64::<LittleEndian>(gps_time).unwrap();
+                values[0] = gps_time;
+            }
+            Source::Rgb => {
+                let color = point.color.unwrap_or_default();
+                for value in [color.red, color.green, color.blue] {
+                    buffer.write_u16::<LittleEndian>(value).unwrap();
+                }
+                values = [color.red as f64, color.green as f64, color.blue as f64];
+            }
+            Source::Nir => {
+                let nir = point.nir.unwrap_or(0);
+                buffer.write_u16::<LittleEndian>(nir).unwrap();
+                values[0] = nir as f64;
+            }
+            Source::Extra(range, data_type) => {
+                // zero-padded if the point has less extra bytes
+                let bytes = point.extra_bytes.get(range.clone()).unwrap_or_default();
+                buffer.extend_from_slice(bytes);
+                buffer.resize(buffer.len() + range.len() - bytes.len(), 0);
+                match data_type.decode(&buffer[buffer.len() - range.len()..]) {
+                    Some(value) => values[0] = value,
+                    // no range of undocumented bytes
+                    None => return,
+                }
+            }
+        }
+        for (i, value) in values.iter().take(self.num_elements).enumerate() {
+            self.min[i] = self.min[i].min(*value);
+            self.max[i] = self.max[i].max(*value);
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        let range = |values: &[f64]| -> Json {
+            if self.min[0] > self.max[0] {
+                // no points or undocumented bytes
+                vec![0.; self.num_elements].into()
+            } else {
+                values.to_vec().into()
+            }
+        };
+        object([
+            ("name", self.name.as_str().into()),
+            ("description", self.description.as_str().into()),
+            ("size", self.size().into()),
+            ("numElements", self.num_elements.into()),
+            ("elementSize", self.element_size.into()),
+            ("type", self.potree_type.into()),
+            ("min", range(&self.min)),
+            ("max", range(&self.max)),
+        ])
+    }
+}
+
+/// Location of the points of a node in `octree.bin`
+#[derive(Clone, Copy, Default)]
+struct NodeData {
+    point_count: u32,
+    offset: u64,
+    size: u64,
+}
+
+/// The index of `key` among its siblings in Potree, with the x bit first
+fn child_index(key: &VoxelKey) -> u8 {
+    (((key.x & 1) << 2) | ((key.y & 1) << 1) | (key.z & 1)) as u8
+}
+
+/// The existing children of `key` in the order of their Potree index
+fn children(key: &VoxelKey, keys: &BTreeSet<VoxelKey>) -> Vec<VoxelKey> {
+    let mut children: Vec<VoxelKey> = key
+        .children()
+        .into_iter()
+        .filter(|child| keys.contains(child))
+        .collect();
+    children.sort_by_key(child_index);
+    children
+}
+
+impl<R: Read + Seek> CopcReader<R> {
+    /// Writes the octree to a Potree 2.0 dataset in `directory`,
+    /// `metadata.json`, `hierarchy.bin` and `octree.bin` in the default encoding
+    ///
+    /// The nodes keep their points, the attributes of the point format
+    /// and the extra attributes are re-encoded per node
+    pub fn export_potree<P: AsRef<Path>>(
+        &mut self,
+        directory: P,
+        options: &PotreeOptions,
+    ) -> crate::Result<PotreeSummary> {
+        let directory = directory.as_ref();
+        std::fs::create_dir_all(directory)?;
+        let transforms = *self.header().transforms();
+        let mut attributes = Attribute::of(self);
+        let point_size: usize = attributes.iter().map(Attribute::size).sum();
+
+        // the points of the nodes, in the order of the data chunks for sequential reading
+        let keys = self.octree_keys();
+        let ordered = self.keys_by_offset(&keys);
+        let mut octree = BufWriter::new(File::create(directory.join("octree.bin"))?);
+        let mut nodes: HashMap<VoxelKey, NodeData> = HashMap::new();
+        let mut summary = PotreeSummary::default();
+        let mut offset = 0;
+        let mut buffer = Vec::new();
+        for key in ordered {
+            buffer.clear();
+            let mut point_count = 0;
+            for point in self.node_points(&key)? {
+                let raw = [
+                    transforms.x.inverse(point.x)?,
+                    transforms.y.inverse(point.y)?,
+                    transforms.z.inverse(point.z)?,
+                ];
+                for attribute in &mut attributes {
+                    attribute.encode(&point, raw, &mut buffer);
+                }
+                point_count += 1;
+            }
+            debug_assert_eq!(buffer.len(), point_count as usize * point_size);
+            octree.write_all(&buffer)?;
+            nodes.insert(
+                key.clone(),
+                NodeData {
+                    point_count,
+                    offset,
+                    size: buffer.len() as u64,
+                },
+            );
+            offset += buffer.len() as u64;
+            summary.points_written += point_count as u64;
+            summary.depth = summary.depth.max(key.level);
+        }
+        octree.flush()?;
+        summary.nodes_written = nodes.len();
+
+        // the hierarchy in chunks of `step` levels, the nodes below a chunk are proxies
+        // in it referencing their own chunk
+        let step = options.hierarchy_step_size.max(1) as i32;
+        let root = VoxelKey {
+            level: 0,
+            ..Default::default()
+        };
+        let mut chunks: Vec<Vec<(VoxelKey, bool)>> = vec![];
+        let mut chunk_roots = VecDeque::new();
+        if keys.contains(&root) {
+            chunk_roots.push_back(root);
+        }
+        while let Some(chunk_root) = chunk_roots.pop_front() {
+            let mut chunk = vec![];
+            let mut queue = VecDeque::from([chunk_root.clone()]);
+            while let Some(key) = queue.pop_front() {
+                if key.level - chunk_root.level == step {
+                    chunk_roots.push_back(key.clone());
+                    chunk.push((key, true));
+                    continue;
+                }
+                queue.extend(children(&key, &keys));
+                chunk.push((key, false));
+            }
+            chunks.push(chunk);
+        }
+        let mut chunk_locations = HashMap::new();
+        let mut chunk_offset = 0;
+        for chunk in &chunks {
+            let size = chunk.len() as u64 * NODE_SIZE;
+            chunk_locations.insert(chunk[0].0.clone(), (chunk_offset, size));
+            chunk_offset += size;
+        }
+        let mut hierarchy = BufWriter::new(File::create(directory.join("hierarchy.bin"))?);
+        for (key, is_proxy) in chunks.iter().flatten() {
+            let child_mask = children(key, &keys)
+                .iter()
+                .fold(0u8, |mask, child| mask | (1 << child_index(child)));
+            let data = nodes[key];
+            let (node_type, offset, size) = if *is_proxy {
+                let (offset, size) = chunk_locations[key];
+                (2, offset, size)
+            } else if child_mask == 0 {
+                (1, data.offset, data.size)
+            } else {
+                (0, data.offset, data.size)
+            };
+            hierarchy.write_u8(node_type)?;
+            hierarchy.write_u8(child_mask)?;
+            hierarchy.write_u32::<LittleEndian>(data.point_count)?;
+            hierarchy.write_u64::<LittleEndian>(offset)?;
+            hierarchy.write_u64::<LittleEndian>(size)?;
+        }
+        hierarchy.flush()?;
+
+        // the cube of the octree and the attributes with their ranges
+        let info = self.copc_info();
+        let cube_min = vec![
+            info.center.x - info.halfsize,
+            info.center.y - info.halfsize,
+            info.center.z - info.halfsize,
+        ];
+        let cube_max = vec![
+            info.center.x + info.halfsize,
+            info.center.y + info.halfsize,
+            info.center.z + info.halfsize,
+        ];
+        let name = options.name.clone().unwrap_or_else(|| {
+            directory
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+        let metadata = object([
+            ("version", "2.0".into()),
+            ("name", name.into()),
+            ("description", "".into()),
+            ("points", summary.points_written.into()),
+            ("projection", self.crs_wkt().unwrap_or_default().into()),
+            (
+                "hierarchy",
+                object([
+                    (
+                        "firstChunkSize",
+                        chunks
+                            .first()
+                            .map_or(0, |chunk| chunk.len() as u64 * NODE_SIZE)
+                            .into(),
+                    ),
+                    ("stepSize", step.into()),
+                    ("depth", summary.depth.into()),
+                ]),
+            ),
+            (
+                "offset",
+                vec![
+                    transforms.x.offset,
+                    transforms.y.offset,
+                    transforms.z.offset,
+                ]
+                .into(),
+            ),
+            (
+                "scale",
+                vec![transforms.x.scale, transforms.y.scale, transforms.z.scale].into(),
+            ),
+            ("spacing", info.spacing.into()),
+            (
+                "boundingBox",
+                object([("min", cube_min.into()), ("max", cube_max.into())]),
+            ),
+            ("encoding", "DEFAULT".into()),
+            (
+                "attributes",
+                Json::Array(attributes.iter().map(Attribute::to_json).collect()),
+            ),
+        ]);
+        std::fs::write(directory.join("metadata.json"), format!("{metadata}\n"))?;
+        Ok(summary)
+    }
+}
@@ -0,0 +1,518 @@
+//! Raster products of the points: surface and terrain models, density and intensity grids.
+
+use crate::copc::VoxelKey;
+use crate::crs::{Crs, UnitKind};
+use crate::reader::{BoundsSelection, CopcReader, LodSelection};
+use byteorder::{LittleEndian, WriteBytesExt};
+use las::{Bounds, Point, Vector};
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Classification of the ground points used for [RasterProduct::Dtm]
+const GROUND: u8 = 2;
+
+/// The value of a raster cell computed from its points
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RasterProduct {
+    /// Digital surface model, the highest z of the cell
+    #[default]
+    Dsm,
+    /// Digital terrain model, the lowest z of the ground points of the cell
+    Dtm,
+    /// The lowest z of the cell
+    MinZ,
+    /// Number of points per square unit, 0 for cells without points
+    Density,
+    /// Mean intensity of the cell
+    Intensity,
+}
+
+/// File format of a raster
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RasterFormat {
+    /// A float32 GeoTIFF with the CRS as GeoTIFF keys and the no data value
+    #[default]
+    GeoTiff,
+    /// Little endian float32 values row by row from the top left cell,
+    /// with a `.wld` world file and the WKT in a `.prj` file
+    Raw,
+}
+
+/// Options for [CopcReader::export_raster]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RasterOptions {
+    /// The value computed for each cell, defaults to the DSM
+    pub product: RasterProduct,
+    /// The file format, defaults to GeoTIFF
+    pub format: RasterFormat,
+    /// Size of the cells in the horizontal unit of the CRS
+    pub cell_size: f64,
+    /// Horizontal extent of the raster, the bounds of the file if `None`
+    pub bounds: Option<Bounds>,
+    /// Number of rows of the bands computed at once
+    pub tile_size: usize,
+    /// Value of the cells without points
+    pub nodata: f32,
+    /// Levels of the octree the points are read from, `None` picks the levels
+    /// with a spacing matching the cell size, or all levels for the density
+    pub lod: Option<LodSelection>,
+}
+
+impl Default for RasterOptions {
+    fn default() -> Self {
+        RasterOptions {
+            product: RasterProduct::default(),
+            format: RasterFormat::default(),
+            cell_size: 1.,
+            bounds: None,
+            tile_size: 256,
+            nodata: -9999.,
+            lod: None,
+        }
+    }
+}
+
+/// Summary of [CopcReader::export_raster]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RasterSummary {
+    /// Number of columns
+    pub width: usize,
+    /// Number of rows
+    pub height: usize,
+    /// Number of points in the cells
+    pub points_used: u64,
+    /// Number of cells with points
+    pub cells_with_data: u64,
+    /// Levels of the octree the points were read from
+    pub lod: LodSelection,
+}
+
+/// The accumulated points of a band of rows
+struct Band {
+    product: RasterProduct,
+    values: Vec<f64>,
+    counts: Vec<u32>,
+}
+
+impl Band {
+    fn new(product: RasterProduct, len: usize) -> Self {
+        Band {
+            product,
+            values: vec![0.; len],
+            counts: vec![0; len],
+        }
+    }
+
+    /// Adds the point to cell `i`, returns whether it is used by the product
+    fn add(&mut self, i: usize, point: &Point) -> bool {
+        let value = match self.product {
+            RasterProduct::Dtm if u8::from(point.classification) != GROUND => return false,
+            RasterProduct::Dsm | RasterProduct::Dtm | RasterProduct::MinZ => point.z,
+            RasterProduct::Density => 0.,
+            RasterProduct::Intensity => point.intensity as f64,
+        };
+        let current = &mut self.values[i];
+        *current = match (self.product, self.counts[i]) {
+            (_, 0) => value,
+            (RasterProduct::Dsm, _) => current.max(value),
+            (RasterProduct::Dtm | RasterProduct::MinZ, _) => current.min(value),
+            (RasterProduct::Density | RasterProduct::Intensity, _) => *current + value,
+        };
+        self.counts[i] += 1;
+        true
+    }
+
+    fn value(&self, i: usize, cell_size: f64, nodata: f32) -> f32 {
+        match (self.product, self.counts[i]) {
+            (RasterProduct::Density, count) => (count as f64 / (cell_size * cell_size)) as f32,
+            (_, 0) => nodata,
+            (RasterProduct::Intensity, count) => (self.values[i] / count as f64) as f32,
+            _ => self.values[i] as f32,
+        }
+    }
+}
+
+impl<R: Read + Seek> CopcReader<R> {
+    /// Writes a raster of the points to `path`
+    ///
+    /// The raster is computed band by band, each node of the octree overlapping the raster
+    /// is decompressed once, the points of the nodes overlapping several bands are kept
+    /// until their last band.
+    /// By default only the levels with a spacing down to the cell size are read,
+    /// so coarse overviews only decompress the top of the octree
+    pub fn export_raster<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        options: &RasterOptions,
+    ) -> crate::Result<RasterSummary> {
+        let cell_size = options.cell_size;
+        if !cell_size.is_normal() || cell_size < 0. {
+            return Err(crate::Error::InvalidResolution(cell_size));
+        }
+        let header_bounds = self.header().bounds();
+        let bounds = options.bounds.unwrap_or(header_bounds);
+        let width = ((bounds.max.x - bounds.min.x) / cell_size).ceil().max(1.) as usize;
+        let height = ((bounds.max.y - bounds.min.y) / cell_size).ceil().max(1.) as usize;
+        let tile_size = options.tile_size.max(1);
+        let spacing = self.copc_info().spacing;
+        // an overview only needs the upper levels, the density needs all points
+        let lod = options.lod.unwrap_or(
+            if options.product == RasterProduct::Density || !spacing.is_normal() {
+                LodSelection::All
+            } else {
+                LodSelection::Resolution(cell_size)
+            },
+        );
+        let mut summary = RasterSummary {
+            width,
+            height,
+            points_used: 0,
+            cells_with_data: 0,
+            lod,
+        };
+
+        let path = path.as_ref();
+        let mut raster = RasterWriter::create(path, options.format, width, height)?;
+
+        // the nodes by the first band they overlap, with the last one
+        let extent = Bounds {
+            min: Vector {
+                x: bounds.min.x,
+                y: bounds.min.y,
+                z: header_bounds.min.z,
+            },
+            max: Vector {
+                x: bounds.max.x,
+                y: bounds.max.y,
+                z: header_bounds.max.z,
+            },
+        };
+        let root_bounds = self.root_bounds();
+        // points on the boundary of a node may be rounded to just outside of it
+        let tolerance = self.header().transforms().y.scale;
+        let band = |y: f64| {
+            let row = ((bounds.max.y - y) / cell_size).floor();
+            row.clamp(0., (height - 1) as f64) as usize / tile_size
+        };
+        let mut nodes: Vec<Vec<(VoxelKey, usize)>> = vec![vec![]; height.div_ceil(tile_size)];
+        for key in self.query_keys(lod, &BoundsSelection::Within(extent))? {
+            let node = key.bounds(&root_bounds);
+            let last = band(node.min.y - tolerance);
+            nodes[band(node.max.y + tolerance)].push((key, last));
+        }
+
+        // the points of the nodes overlapping more than one band, until their last band
+        let mut cached: Vec<(Vec<Point>, usize)> = vec![];
+        for (band_index, row_start) in (0..height).step_by(tile_size).enumerate() {
+            let rows = tile_size.min(height - row_start);
+            let mut band = Band::new(options.product, rows * width);
+            let mut add = |point: &Point| {
+                if point.x < bounds.min.x
+                    || point.x > bounds.max.x
+                    || point.y < bounds.min.y
+                    || point.y > bounds.max.y
+                {
+                    return;
+                }
+                // the points on the right and bottom edges of the raster are in the last cells
+                let column = (((point.x - bounds.min.x) / cell_size) as usize).min(width - 1);
+                let row = (((bounds.max.y - point.y) / cell_size) as usize).min(height - 1);
+                if (row_start..row_start + rows).contains(&row)
+                    && band.add((row - row_start) * width + column, point)
+                {
+                    summary.points_used += 1;
+                }
+            };
+            for (key, last) in std::mem::take(&mut nodes[band_index]) {
+                let points = self.node_points_within(&key, &BoundsSelection::Within(extent))?;
+                if last == band_index {
+                    points.for_each(|point| add(&point));
+                } else {
+                    cached.push((points.collect(), last));
+                }
+            }
+            for (points, _) in &cached {
+                points.iter().for_each(&mut add);
+            }
+            cached.retain(|(_, last)| *last > band_index);
+
+            summary.cells_with_data += band.counts.iter().filter(|c| **c > 0).count() as u64;
+            let values: Vec<f32> = (0..rows * width)
+                .map(|i| band.value(i, cell_size, options.nodata))
+                .collect();
+            raster.write_band(&values)?;
+        }
+
+        let origin = Vector {
+            x: bounds.min.x,
+            y: bounds.max.y,
+            z: 0.,
+        };
+        raster.finish(origin, cell_size, options.nodata, self.crs())?;
+        Ok(summary)
+    }
+}
+
+/// Streams the rows of a raster to a GeoTIFF or raw file
+struct RasterWriter {
+    path: std::path::PathBuf,
+    format: RasterFormat,
+    write: BufWriter<File>,
+    width: usize,
+    height: usize,
+    /// Offsets and byte counts of the strips of a GeoTIFF, one strip per band
+    strips: Vec<(u32, u32)>,
+    rows_per_strip: usize,
+}
+
+impl RasterWriter {
+    fn create(
+        path: &Path,
+        format: RasterFormat,
+        width: usize,
+        height: usize,
+    ) -> crate::Result<Self> {
+        // the offsets of a TIFF are 32 bits, the IFD is checked when it is written
+        let too_large = || crate::Error::RasterTooLarge(width, height);
+        if format == RasterFormat::GeoTiff {
+            let size = width
+                .checked_mul(height)
+                .and_then(|cells| cells.checked_mul(4))
+                .and_then(|bytes| bytes.checked_add(8))
+                .ok_or_else(too_large)?;
+            if size > u32::MAX as usize {
+                return Err(too_large());
+            }
+        }
+        let mut write = BufWriter::new(File::create(path)?);
+        if format == RasterFormat::GeoTiff {
+            // the offset of the IFD is written when the strips are
+            write.write_all(b"II")?;
+            write.write_u16::<LittleEndian>(42)?;
+            write.write_u32::<LittleEndian>(0)?;
+        }
+        Ok(RasterWriter {
+            path: path.to_owned(),
+            format,
+            write,
+            width,
+            height,
+            strips: vec![],
+            rows_per_strip: 0,
+        })
+    }
+
+    fn write_band(&mut self, values: &[f32]) -> crate::Result<()> {
+        let offset = self.write.stream_position()?;
+        for value in values {
+            self.write.write_f32::<LittleEndian>(*value)?;
+        }
+        self.rows_per_strip = self.rows_per_strip.max(values.len() / self.width);
+        let too_large = || crate::Error::RasterTooLarge(self.width, self.height);
+        let offset = u32::try_from(offset).map_err(|_| too_large())?;
+        let byte_count = u32::try_from(values.len() * 4).map_err(|_| too_large())?;
+        self.strips.push((offset, byte_count));
+        Ok(())
+    }
+
+    /// Writes the georeferencing, `origin` is the top left corner of the raster
+    fn finish(
+        mut self,
+        origin: Vector<f64>,
+        cell_size: f64,
+        nodata: f32,
+        crs: Option<&Crs>,
+    ) -> crate::Result<()> {
+        match self.format {
+            RasterFormat::GeoTiff => {
+                let ifd = self.geotiff_ifd(origin, cell_size, nodata, crs);
+                let mut offset = self.write.stream_position()?;
+                if offset % 2 == 1 {
+                    self.write.write_u8(0)?;
+                    offset += 1;
+                }
+                if offset + ifd_len(&ifd) > u32::MAX as u64 {
+                    return Err(crate::Error::RasterTooLarge(self.width, self.height));
+                }
+                write_ifd(&mut self.write, ifd, offset as u32)?;
+                self.write.seek(SeekFrom::Start(4))?;
+                self.write.write_u32::<LittleEndian>(offset as u32)?;
+            }
+            RasterFormat::Raw => {
+                // the world file has the center of the top left cell
+                std::fs::write(
+                    self.path.with_extension("wld"),
+                    format!(
+                        "{cell_size}\n0\n0\n{}\n{}\n{}\n",
+                        -cell_size,
+                        origin.x + cell_size / 2.,
+                        origin.y - cell_size / 2.
+                    ),
+                )?;
+                if let Some(crs) = crs {
+                    std::fs::write(self.path.with_extension("prj"), crs.wkt())?;
+                }
+            }
+        }
+        self.write.flush()?;
+        Ok(())
+    }
+
+    fn geotiff_ifd(
+        &self,
+        origin: Vector<f64>,
+        cell_size: f64,
+        nodata: f32,
+        crs: Option<&Crs>,
+    ) -> Vec<TiffEntry> {
+        let (offsets, byte_counts): (Vec<u32>, Vec<u32>) = self.strips.iter().copied().unzip();
+        let mut ifd = vec![
+            TiffEntry::long(256, &[self.width as u32]),
+            TiffEntry::long(257, &[self.height as u32]),
+            // 32 bits per sample, no compression, min is black
+            TiffEntry::short(258, &[32]),
+            TiffEntry::short(259, &[1]),
+            TiffEntry::short(262, &[1]),
+            TiffEntry::long(273, &offsets),
+            TiffEntry::short(277, &[1]),
+            TiffEntry::long(278, &[self.rows_per_strip as u32]),
+            TiffEntry::long(279, &byte_counts),
+            TiffEntry::short(284, &[1]),
+            // IEEE floating point samples
+            TiffEntry::short(339, &[3]),
+            TiffEntry::double(33550, &[cell_size, cell_size, 0.]),
+            TiffEntry::double(33922, &[0., 0., 0., origin.x, origin.y, 0.]),
+        ];
+        let (keys, citation) = geo_keys(crs);
+        ifd.push(TiffEntry::short(34735, &keys));
+        if let Some(citation) = citation {
+            ifd.push(TiffEntry::ascii(34737, &citation));
+        }
+        // the no data value as read by GDAL
+        ifd.push(TiffEntry::ascii(42113, &nodata.to_string()));
+        ifd
+    }
+}
+
+/// The GeoTIFF key directory of the CRS and the ASCII parameters it refers to
+///
+/// The horizontal and vertical CRSs are written as EPSG codes, a CRS without EPSG code
+/// is written as the citation of a user-defined CRS. The citation is left out
+/// if it is too long for the key directory
+fn geo_keys(crs: Option<&Crs>) -> (Vec<u16>, Option<String>) {
+    // pixel is area
+    let mut keys: Vec<[u16; 4]> = vec![[1025, 0, 1, 1]];
+    let mut citation = None;
+    if let Some(crs) = crs {
+        let geographic = crs
+            .horizontal_unit()
+            .is_some_and(|unit| unit.kind == UnitKind::Angular);
+        let (model_type, crs_key) = if geographic { (2, 2048) } else { (1, 3072) };
+        keys.push([1024, 0, 1, model_type]);
+        match crs.horizontal_epsg() {
+            Some(epsg) => keys.push([crs_key, 0, 1, epsg]),
+            None => {
+                let text = format!("{}|", crs.wkt());
+                if let Ok(length) = u16::try_from(text.len()) {
+                    keys.push([1026, 34737, length, 0]);
+                    citation = Some(text);
+                }
+                keys.push([crs_key, 0, 1, 32767]);
+            }
+        }
+        if let Some(epsg) = crs.vertical_epsg() {
+            keys.push([4096, 0, 1, epsg]);
+        }
+    }
+    keys.sort();
+    let mut directory = vec![1, 1, 0, keys.len() as u16];
+    directory.extend(keys.into_iter().flatten());
+    (directory, citation)
+}
+
+/// An entry of a TIFF image file directory
+struct TiffEntry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    data: Vec<u8>,
+}
+
+impl TiffEntry {
+    fn short(tag: u16, values: &[u16]) -> Self {
+        TiffEntry {
+            tag,
+            field_type: 3,
+            count: values.len() as u32,
+            data: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn long(tag: u16, values: &[u32]) -> Self {
+        TiffEntry {
+            tag,
+            field_type: 4,
+            count: values.len() as u32,
+            data: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn double(tag: u16, values: &[f64]) -> Self {
+        TiffEntry {
+            tag,
+            field_type: 12,
+            count: values.len() as u32,
+            data: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn ascii(tag: u16, value: &str) -> Self {
+        let mut data = value.as_bytes().to_vec();
+        data.push(0);
+        TiffEntry {
+            tag,
+            field_type: 2,
+            count: data.len() as u32,
+            data,
+        }
+    }
+}
+
+/// Size in bytes of the IFD with the values following it
+fn ifd_len(ifd: &[TiffEntry]) -> u64 {
+    let values: usize = ifd
+        .iter()
+        .filter(|entry| entry.data.len() > 4)
+        .map(|entry| entry.data.len() + entry.data.len() % 2)
+        .sum();
+    (2 + 12 * ifd.len() + 4 + values) as u64
+}
+
+/// Writes the IFD at `offset`, the values larger than 4 bytes follow the IFD
+fn write_ifd<W: Write>(write: &mut W, mut ifd: Vec<TiffEntry>, offset: u32) -> std::io::Result<()> {
+    ifd.sort_by_key(|entry| entry.tag);
+    let mut data_offset = offset + 2 + 12 * ifd.len() as u32 + 4;
+    let mut data = vec![];
+    write.write_u16::<LittleEndian>(ifd.len() as u16)?;
+    for entry in &ifd {
+        write.write_u16::<LittleEndian>(entry.tag)?;
+        write.write_u16::<LittleEndian>(entry.field_type)?;
+        write.write_u32::<LittleEndian>(entry.count)?;
+        if entry.data.len() <= 4 {
+            let mut value = [0; 4];
+            value[..entry.data.len()].copy_from_slice(&entry.data);
+            write.write_all(&value)?;
+        } else {
+            write.write_u32::<LittleEndian>(data_offset)?;
+            data.extend_from_slice(&entry.data);
+            if data.len() % 2 == 1 {
+                data.push(0);
+            }
+            data_offset = offset + 2 + 12 * ifd.len() as u32 + 4 + data.len() as u32;
+        }
+    }
+    // no next IFD
+    write.write_u32::<LittleEndian>(0)?;
+    write.write_all(&data)
+}
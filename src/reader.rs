@@ -1,12 +1,17 @@
 //! COPC file reader.
 
 use crate::copc::{CopcInfo, Entry, HierarchyPage, OctreeNode, VoxelKey};
+use crate::crs::{self, Crs};
 use crate::decompressor::CopcDecompressor;
+use crate::extra_bytes::ExtraBytes;
+use crate::hierarchy::HierarchyPageInfo;
+#[cfg(feature = "reproject")]
+use crate::reproject::{PointTransform, TransformedPointIter};
 use las::raw;
 use las::{Bounds, Builder, Header, Transform, Vector, Vlr};
 use laz::LazVlr;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
 use std::path::Path;
@@ -20,8 +25,12 @@ pub struct CopcReader<R> {
     header: Header,
     copc_info: CopcInfo,
     laz_vlr: LazVlr,
+    extra_bytes: ExtraBytes,
+    crs: Option<Crs>,
     /// Entries of loaded hierarchy pages
     hierarchy_entries: HashMap<VoxelKey, Entry>,
+    /// Layout of the loaded hierarchy pages, the root page first
+    hierarchy_pages: Vec<HierarchyPageInfo>,
 }
 
 impl CopcReader<BufReader<File>> {
@@ -107,6 +116,11 @@ impl<R: Read + Seek> CopcReader<R> {
         let copc_info = copc_info.ok_or(crate::Error::CopcInfoVlrNotFound)?;
 
         // store all ept-hierarchy entries in a hashmap
+        let mut hierarchy_pages = vec![HierarchyPageInfo {
+            key: VoxelKey::root(),
+            offset: copc_info.root_hier_offset,
+            byte_size: copc_info.root_hier_size,
+        }];
         let hierarchy_entries = match ept_hierarchy {
             None => return Err(crate::Error::EptHierarchyVlrNotFound),
             Some(vlr) => {
@@ -118,13 +132,55 @@ impl<R: Read + Seek> CopcReader<R> {
                 let mut page =
                     HierarchyPage::read_from(&mut read_vlr, copc_info.root_hier_size)?.entries;
 
+                // the pages already read, a page referenced again would be read forever
+                let mut visited =
+                    HashSet::from([(copc_info.root_hier_offset, copc_info.root_hier_size)]);
                 while let Some(entry) = page.pop() {
                     if entry.point_count == -1 {
-                        // read a new hierarchy page
-                        read.seek(SeekFrom::Start(entry.offset - copc_info.root_hier_offset))?;
-                        page.extend(
-                            HierarchyPage::read_from(&mut read, entry.byte_size as u64)?.entries,
-                        );
+                        // read a new hierarchy page, from the hierarchy evlr if it is located there
+                        let page_size = u64::try_from(entry.byte_size).map_err(|_| {
+                            crate::Error::InvalidHierarchy(format!(
+                                "the hierarchy page of {} has a negative size",
+                                entry.key
+                            ))
+                        })?;
+                        if !visited.insert((entry.offset, page_size)) {
+                            return Err(crate::Error::InvalidHierarchy(format!(
+                                "the hierarchy page at {} is referenced more than once",
+                                entry.offset
+                            )));
+                        }
+                        hierarchy_pages.push(HierarchyPageInfo {
+                            key: entry.key.clone(),
+                            offset: entry.offset,
+                            byte_size: page_size,
+                        });
+                        let page_end = entry
+                            .offset
+                            .checked_sub(copc_info.root_hier_offset)
+                            .and_then(|page_start| {
+                                Some((page_start, page_start.checked_add(page_size)?))
+                            });
+                        match page_end {
+                            Some((page_start, page_end)) if page_end <= vlr.data.len() as u64 => {
+                                read_vlr.seek(SeekFrom::Start(page_start))?;
+                                page.extend(
+                                    HierarchyPage::read_from(&mut read_vlr, page_size)?.entries,
+                                );
+                            }
+                            _ => {
+                                let offset = entry.offset.checked_add(start).ok_or_else(|| {
+                                    crate::Error::InvalidHierarchy(format!(
+                                        "the hierarchy page offset {} is out of range",
+                                        entry.offset
+                                    ))
+                                })?;
+                                read.seek(SeekFrom::Start(offset))?;
+                                page.extend(
+                                    HierarchyPage::read_from(&mut read, page_size)?.entries,
+                                );
+                            }
+                        }
                     } else {
                         hierarchy_entries.insert(entry.key.clone(), entry);
                     }
@@ -135,13 +191,27 @@ impl<R: Read + Seek> CopcReader<R> {
 
         // set the read pointer to the start of the compressed data block
         let _ = read.seek(SeekFrom::Start(offset_to_point_data + start))?;
+        let extra_bytes = ExtraBytes::from_header(&header).unwrap_or_else(|e| {
+            log::warn!("The extra bytes descriptors could not be read: {e}");
+            ExtraBytes::default()
+        });
+        let crs = match crs::header_wkt(&header) {
+            Ok(wkt) => wkt.map(|wkt| Crs::from_wkt(&String::from_utf8_lossy(&wkt))),
+            Err(e) => {
+                log::warn!("The CRS could not be read: {e}");
+                None
+            }
+        };
         Ok(CopcReader {
             start,
             read,
             header,
             copc_info,
             laz_vlr: laszip_vlr.ok_or(crate::Error::LasZipVlrNotFound)?,
+            extra_bytes,
+            crs,
             hierarchy_entries,
+            hierarchy_pages,
         })
     }
 
@@ -155,10 +225,107 @@ impl<R: Read + Seek> CopcReader<R> {
         &self.copc_info
     }
 
+    /// CRS of the point cloud, `None` if the file has no (readable) CRS
+    ///
+    /// See [Crs] for the WKT, EPSG codes, vertical datum and units
+    pub fn crs(&self) -> Option<&Crs> {
+        self.crs.as_ref()
+    }
+
+    /// WKT of the CRS of the point cloud
+    pub fn crs_wkt(&self) -> Option<&str> {
+        self.crs.as_ref().map(Crs::wkt)
+    }
+
+    /// EPSG code of the CRS of the point cloud, if it can be identified
+    pub fn crs_epsg(&self) -> Option<u16> {
+        self.crs.as_ref().and_then(Crs::epsg)
+    }
+
+    /// Extra attributes of the points, described by the extra bytes VLR
+    pub fn extra_bytes(&self) -> &ExtraBytes {
+        &self.extra_bytes
+    }
+
     pub fn num_entries(&self) -> usize {
         self.hierarchy_entries.len()
     }
 
+    /// The entries of the EPT hierarchy by key, without the entries of child pages
+    pub fn hierarchy_entries(&self) -> &HashMap<VoxelKey, Entry> {
+        &self.hierarchy_entries
+    }
+
+    /// The hierarchy pages in the order they were read, the root page first
+    pub fn hierarchy_pages(&self) -> &[HierarchyPageInfo] {
+        &self.hierarchy_pages
+    }
+
+    /// The compressed data chunk of a hierarchy entry
+    pub(crate) fn read_chunk(&mut self, entry: &Entry) -> crate::Result<Vec<u8>> {
+        let mut chunk = vec![0; entry.byte_size.max(0) as usize];
+        self.read.seek(SeekFrom::Start(self.start + entry.offset))?;
+        self.read.read_exact(&mut chunk)?;
+        Ok(chunk)
+    }
+
+    /// The points of the node `key`, an error if its chunk can not be decompressed
+    #[cfg(feature = "writer")]
+    pub(crate) fn checked_node_points(&mut self, key: &VoxelKey) -> crate::Result<Vec<las::Point>> {
+        let Some(entry) = self.hierarchy_entries.get(key).cloned() else {
+            return Ok(vec![]);
+        };
+        self.read.seek(SeekFrom::Start(self.start))?;
+        let mut decompressor = CopcDecompressor::new(&mut self.read, &self.laz_vlr)?;
+        decompressor.source_seek(entry.offset)?;
+        let format = *self.header.point_format();
+        let transforms = *self.header.transforms();
+        let mut buffer = vec![0u8; (format.len() + format.extra_bytes) as usize];
+        let mut points = Vec::new();
+        for _ in 0..entry.point_count.max(0) {
+            decompressor.decompress_one(&mut buffer)?;
+            let point = raw::Point::read_from(buffer.as_slice(), &format)?;
+            points.push(las::Point::new(point, &transforms));
+        }
+        Ok(points)
+    }
+
+    /// The laszip vlr the chunks are compressed with
+    pub(crate) fn laz_vlr(&self) -> &LazVlr {
+        &self.laz_vlr
+    }
+
+    /// The keys of the nodes with points and of their ancestors
+    pub(crate) fn octree_keys(&self) -> BTreeSet<VoxelKey> {
+        let mut keys = BTreeSet::new();
+        for (key, entry) in &self.hierarchy_entries {
+            if entry.point_count < 0 || key.level < 0 {
+                continue;
+            }
+            let mut key = Some(key.clone());
+            while let Some(k) = key {
+                key = k.parent();
+                if !keys.insert(k) {
+                    break;
+                }
+            }
+        }
+        keys
+    }
+
+    /// The `keys` in the order of the data chunks of the nodes, for sequential reading
+    pub(crate) fn keys_by_offset(&self, keys: &BTreeSet<VoxelKey>) -> Vec<VoxelKey> {
+        let mut ordered: Vec<(u64, VoxelKey)> = keys
+            .iter()
+            .map(|key| {
+                let offset = self.hierarchy_entries.get(key).map_or(0, |e| e.offset);
+                (offset, key.clone())
+            })
+            .collect();
+        ordered.sort();
+        ordered.into_iter().map(|(_, key)| key).collect()
+    }
+
     /// Loads the nodes of the COPC octree that
     /// satisfies the parameters `query_bounds` and `level_range`.
     ///
@@ -183,18 +350,7 @@ impl<R: Read + Seek> CopcReader<R> {
             LodSelection::LevelMinMax(min, max) => (min, max),
         };
 
-        let root_bounds = Bounds {
-            min: Vector {
-                x: self.copc_info.center.x - self.copc_info.halfsize,
-                y: self.copc_info.center.y - self.copc_info.halfsize,
-                z: self.copc_info.center.z - self.copc_info.halfsize,
-            },
-            max: Vector {
-                x: self.copc_info.center.x + self.copc_info.halfsize,
-                y: self.copc_info.center.y + self.copc_info.halfsize,
-                z: self.copc_info.center.z + self.copc_info.halfsize,
-            },
-        };
+        let root_bounds = self.root_bounds();
 
         let mut root_node = OctreeNode::new();
         root_node.entry.key.level = 0;
@@ -253,11 +409,84 @@ impl<R: Read + Seek> CopcReader<R> {
     ) -> crate::Result<PointIter<'_, R>> {
         let nodes = self.load_octree_for_query(levels, &bounds)?;
         let total_points_left = nodes.iter().map(|n| n.entry.point_count as usize).sum();
+        let raw_bounds = self.raw_bounds(&bounds)?;
+        self.point_iter(nodes, raw_bounds, total_points_left)
+    }
+
+    /// Number of points of a query, from the hierarchy without bounds and by reading the points with
+    pub(crate) fn query_point_count(
+        &mut self,
+        levels: LodSelection,
+        bounds: BoundsSelection,
+    ) -> crate::Result<u64> {
+        Ok(match bounds {
+            BoundsSelection::All => self
+                .load_octree_for_query(levels, &bounds)?
+                .iter()
+                .map(|node| node.entry.point_count as u64)
+                .sum(),
+            BoundsSelection::Within(_) => self.points(levels, bounds)?.count() as u64,
+        })
+    }
 
-        let transforms = *self.header().transforms();
+    /// Point iterator for the points of the node `key`, empty if the hierarchy has no points for it
+    pub fn node_points(&mut self, key: &VoxelKey) -> crate::Result<PointIter<'_, R>> {
+        self.node_points_within(key, &BoundsSelection::All)
+    }
 
-        // Reverse transform to unscaled values
-        let raw_bounds = match bounds {
+    /// Point iterator for the points of the node `key` within `bounds`
+    pub(crate) fn node_points_within(
+        &mut self,
+        key: &VoxelKey,
+        bounds: &BoundsSelection,
+    ) -> crate::Result<PointIter<'_, R>> {
+        let nodes: Vec<OctreeNode> = self
+            .hierarchy_entries
+            .get(key)
+            .filter(|entry| entry.point_count > 0)
+            .map(|entry| {
+                let mut node = OctreeNode::new();
+                node.entry = entry.clone();
+                node
+            })
+            .into_iter()
+            .collect();
+        let total_points_left = nodes.iter().map(|n| n.entry.point_count as usize).sum();
+        let raw_bounds = self.raw_bounds(bounds)?;
+        self.point_iter(nodes, raw_bounds, total_points_left)
+    }
+
+    /// The keys of the nodes with points selected by a query, in the order of their data chunks
+    pub(crate) fn query_keys(
+        &mut self,
+        levels: LodSelection,
+        bounds: &BoundsSelection,
+    ) -> crate::Result<Vec<VoxelKey>> {
+        let nodes = self.load_octree_for_query(levels, bounds)?;
+        Ok(nodes.into_iter().rev().map(|node| node.entry.key).collect())
+    }
+
+    /// The bounds of the octree cube
+    pub(crate) fn root_bounds(&self) -> Bounds {
+        let info = &self.copc_info;
+        Bounds {
+            min: Vector {
+                x: info.center.x - info.halfsize,
+                y: info.center.y - info.halfsize,
+                z: info.center.z - info.halfsize,
+            },
+            max: Vector {
+                x: info.center.x + info.halfsize,
+                y: info.center.y + info.halfsize,
+                z: info.center.z + info.halfsize,
+            },
+        }
+    }
+
+    /// The bounds in the unscaled integer coordinates of the points
+    fn raw_bounds(&self, bounds: &BoundsSelection) -> crate::Result<Option<RawBounds>> {
+        let transforms = self.header().transforms();
+        Ok(match bounds {
             BoundsSelection::All => None,
             BoundsSelection::Within(bounds) => Some(RawBounds {
                 min: Vector {
@@ -271,8 +500,15 @@ impl<R: Read + Seek> CopcReader<R> {
                     z: transforms.z.inverse(bounds.max.z)?,
                 },
             }),
-        };
+        })
+    }
 
+    fn point_iter(
+        &mut self,
+        nodes: Vec<OctreeNode>,
+        bounds: Option<RawBounds>,
+        total_points_left: usize,
+    ) -> crate::Result<PointIter<'_, R>> {
         self.read.seek(SeekFrom::Start(self.start))?;
         let decompressor = CopcDecompressor::new(&mut self.read, &self.laz_vlr)?;
         let point = vec![
@@ -283,15 +519,42 @@ impl<R: Read + Seek> CopcReader<R> {
 
         Ok(PointIter {
             nodes,
-            bounds: raw_bounds,
+            bounds,
             point_format: *self.header.point_format(),
-            transforms,
+            transforms: *self.header.transforms(),
             decompressor,
             point_buffer: point,
             node_points_left: 0,
             total_points_left,
         })
     }
+
+    /// Point iterator for selected level and bounds, transformed to the target CRS of `transform`
+    ///
+    /// The `bounds` are in the target CRS, the octree is queried with the source bounds
+    /// enclosing them (see [PointTransform::inverse_bounds])
+    /// and the transformed points are filtered by `bounds`.
+    /// The resolution of [LodSelection::Resolution] is in the unit of the source CRS
+    #[cfg(feature = "reproject")]
+    pub fn transformed_points<T: PointTransform>(
+        &mut self,
+        levels: LodSelection,
+        bounds: BoundsSelection,
+        transform: T,
+    ) -> crate::Result<TransformedPointIter<'_, R, T>> {
+        let (source_bounds, bounds) = match bounds {
+            BoundsSelection::All => (BoundsSelection::All, None),
+            BoundsSelection::Within(bounds) => (
+                BoundsSelection::Within(transform.inverse_bounds(&bounds)),
+                Some(bounds),
+            ),
+        };
+        Ok(TransformedPointIter {
+            points: self.points(levels, source_bounds)?,
+            transform,
+            bounds,
+        })
+    }
 }
 
 struct RawBounds {
@@ -334,6 +597,7 @@ fn bounds_intersect(a: &Bounds, b: &Bounds) -> bool {
 /// level: The level of detail (LOD).
 ///
 /// If absent, all LOD are going to be considered
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum LodSelection {
     /// Full resolution (all LODs)
     All,
@@ -352,6 +616,7 @@ pub enum LodSelection {
 }
 
 /// Select points within bounds
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum BoundsSelection {
     /// No bounds filter.
     All,
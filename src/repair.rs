@@ -0,0 +1,430 @@
+//! In place repair of COPC files.
+
+use crate::convert::{copc_from_points, ConvertOptions};
+use crate::copc::{Entry, HierarchyPage, VoxelKey};
+use crate::crs;
+use crate::reader::CopcReader;
+use crate::validate::{key_in_octree, scan, FileScan, ValidationCheck, MISSING_WKT};
+
+use las::Bounds;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Options for [repair]
+#[derive(Clone, Debug)]
+pub struct RepairOptions {
+    /// Rewrite the whole file if the point data itself is inconsistent, defaults to true
+    pub allow_rewrite: bool,
+    /// Options of the rewrite, see [crate::recopc]
+    pub convert: ConvertOptions,
+}
+
+impl Default for RepairOptions {
+    fn default() -> Self {
+        RepairOptions {
+            allow_rewrite: true,
+            convert: ConvertOptions::default(),
+        }
+    }
+}
+
+/// A defect fixed by [repair]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RepairFix {
+    /// The part of the file that was fixed
+    pub check: ValidationCheck,
+    /// Description of the fix
+    pub message: String,
+}
+
+/// Result of [repair]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RepairSummary {
+    /// The fixes, if the file was rewritten the defects that required it
+    pub fixes: Vec<RepairFix>,
+    /// Whether the whole file was rewritten
+    pub rewritten: bool,
+}
+
+impl RepairSummary {
+    fn fix(&mut self, check: ValidationCheck, message: String) {
+        self.fixes.push(RepairFix { check, message });
+    }
+}
+
+/// Repairs the COPC file at `path`
+///
+/// The points are decompressed to find the correct header point counts and bounds,
+/// gps time range and hierarchy of the file. Defects of the header, the COPC info VLR
+/// and the EPT hierarchy EVLR are fixed by overwriting them in place.
+/// The hierarchy is written as a single page, it grows the file only if it is the last EVLR.
+///
+/// Only if the point data itself is inconsistent, chunks can not be decompressed,
+/// overlap or hold points outside their node, or the WKT CRS is missing,
+/// the file is rewritten like with [crate::recopc].
+/// The points of the chunks that can not be decompressed are dropped.
+/// A missing WKT CRS is only fixed with the CRS of the GeoTIFF keys of the file
+/// or the `crs` or `fallback_crs` of the options, the rewrite of a file without CRS fails
+/// with [crate::Error::NoCRSDefined] otherwise.
+/// Without `allow_rewrite` this returns [crate::Error::RepairNeedsRewrite] instead.
+///
+/// ```no_run
+/// use copc_rs::{repair, validate_path, RepairOptions};
+///
+/// if !validate_path("./lidar.copc.laz").unwrap().is_valid() {
+///     let summary = repair("./lidar.copc.laz", &RepairOptions::default()).unwrap();
+///     for fix in summary.fixes {
+///         println!("{:?}: {}", fix.check, fix.message);
+///     }
+/// }
+/// ```
+pub fn repair<P: AsRef<Path>>(path: P, options: &RepairOptions) -> crate::Result<RepairSummary> {
+    let path = path.as_ref();
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+
+    let mut file_scan = scan(BufReader::new(&mut file), None)?;
+    // a wrong root page offset is recovered from the start of the hierarchy evlr
+    let mut root_page_moved = false;
+    if let (Some((_, copc_info)), Some(evlr)) = (&file_scan.copc_info, &file_scan.hierarchy_evlr) {
+        if copc_info.root_hier_offset != evlr.data_offset
+            || copc_info.root_hier_size > evlr.data_len
+        {
+            let size = if copc_info.root_hier_size <= evlr.data_len
+                && copc_info.root_hier_size.is_multiple_of(32)
+            {
+                copc_info.root_hier_size
+            } else {
+                evlr.data_len
+            };
+            file_scan = scan(BufReader::new(&mut file), Some((evlr.data_offset, size)))?;
+            root_page_moved = true;
+        }
+    }
+
+    // the CRS of a rewrite
+    let crs_available = options.convert.crs.is_some()
+        || options.convert.fallback_crs.is_some()
+        || las::Reader::from_path(path)
+            .ok()
+            .and_then(|reader| crs::header_wkt(reader.header()).ok().flatten())
+            .is_some();
+    let rewrite_reasons = rewrite_reasons(&file_scan, crs_available);
+    if !rewrite_reasons.is_empty() {
+        return rewrite(path, file, rewrite_reasons, options);
+    }
+
+    let mut summary = RepairSummary::default();
+    let FileScan {
+        report,
+        mut header,
+        copc_info,
+        copc_info_reserved,
+        hierarchy_evlr,
+        entries,
+        points,
+    } = file_scan;
+    let (copc_info_offset, mut copc_info) = copc_info.expect("checked by rewrite_reasons");
+    let hierarchy_evlr = hierarchy_evlr.expect("checked by rewrite_reasons");
+
+    let root_key = VoxelKey {
+        level: 0,
+        ..Default::default()
+    };
+    let root_points = entries.get(&root_key).map_or(0, |e| e.point_count.max(0));
+
+    // hierarchy
+    let mut hierarchy = None;
+    if root_page_moved
+        || report
+            .issues
+            .iter()
+            .any(|i| i.check == ValidationCheck::Hierarchy)
+    {
+        let page = HierarchyPage {
+            entries: flat_hierarchy(entries),
+        };
+        let evlr = page.into_evlr()?;
+        let size = evlr.data.len() as u64;
+        if size > hierarchy_evlr.data_len && !hierarchy_evlr.is_last {
+            let reason = RepairFix {
+                check: ValidationCheck::Hierarchy,
+                message: "the repaired hierarchy does not fit in the hierarchy EVLR".to_string(),
+            };
+            return rewrite(path, file, vec![reason], options);
+        }
+        copc_info.root_hier_offset = hierarchy_evlr.data_offset;
+        copc_info.root_hier_size = size;
+        hierarchy = Some(evlr);
+        summary.fix(
+            ValidationCheck::Hierarchy,
+            "rewrote the hierarchy as a single page".to_string(),
+        );
+    }
+
+    // header
+    let mut header_changed = false;
+    let number_of_points = report.points_checked;
+    let large_file = header.large_file.get_or_insert_with(Default::default);
+    if large_file.number_of_point_records != number_of_points {
+        summary.fix(
+            ValidationCheck::PointCount,
+            format!(
+                "set the number of points from {} to {number_of_points}",
+                large_file.number_of_point_records
+            ),
+        );
+        large_file.number_of_point_records = number_of_points;
+        header_changed = true;
+    }
+    if large_file.number_of_points_by_return != points.by_return {
+        summary.fix(
+            ValidationCheck::PointCount,
+            "recomputed the number of points by return".to_string(),
+        );
+        large_file.number_of_points_by_return = points.by_return;
+        header_changed = true;
+    }
+    // the legacy counts are either 0 or the counts that fit in them
+    let legacy = |n: u64| u32::try_from(n).unwrap_or(0);
+    let legacy_by_return = [0, 1, 2, 3, 4].map(|i| legacy(points.by_return[i]));
+    if (
+        header.number_of_point_records,
+        header.number_of_points_by_return,
+    ) != (0, [0; 5])
+        && (
+            header.number_of_point_records,
+            header.number_of_points_by_return,
+        ) != (legacy(number_of_points), legacy_by_return)
+    {
+        summary.fix(
+            ValidationCheck::PointCount,
+            "recomputed the legacy point counts".to_string(),
+        );
+        header.number_of_point_records = legacy(number_of_points);
+        header.number_of_points_by_return = legacy_by_return;
+        header_changed = true;
+    }
+    if number_of_points > 0 {
+        let b = &points.bounds;
+        // loose header bounds are kept, points more than half a scale unit outside are not
+        let outside = |bound: f64, value: f64, scale: f64| value - bound > scale / 2.;
+        if outside(b.min.x, header.min_x, header.x_scale_factor)
+            || outside(b.min.y, header.min_y, header.y_scale_factor)
+            || outside(b.min.z, header.min_z, header.z_scale_factor)
+            || outside(header.max_x, b.max.x, header.x_scale_factor)
+            || outside(header.max_y, b.max.y, header.y_scale_factor)
+            || outside(header.max_z, b.max.z, header.z_scale_factor)
+        {
+            summary.fix(
+                ValidationCheck::Header,
+                "recomputed the header bounds".to_string(),
+            );
+            (header.min_x, header.min_y, header.min_z) = (b.min.x, b.min.y, b.min.z);
+            (header.max_x, header.max_y, header.max_z) = (b.max.x, b.max.y, b.max.z);
+            header_changed = true;
+        }
+    }
+    if header.global_encoding & 0b10000 == 0 {
+        summary.fix(
+            ValidationCheck::Header,
+            "set the WKT bit of the global encoding".to_string(),
+        );
+        header.global_encoding |= 0b10000;
+        header_changed = true;
+    }
+
+    // copc info
+    let mut copc_info_changed = hierarchy.is_some();
+    let (gpstime_minimum, gpstime_maximum) = points.gps_time.unwrap_or((0., 0.));
+    if (copc_info.gpstime_minimum, copc_info.gpstime_maximum) != (gpstime_minimum, gpstime_maximum)
+    {
+        summary.fix(
+            ValidationCheck::GpsTime,
+            format!(
+                "set the gps time range from {} - {} to {gpstime_minimum} - {gpstime_maximum}",
+                copc_info.gpstime_minimum, copc_info.gpstime_maximum
+            ),
+        );
+        copc_info.gpstime_minimum = gpstime_minimum;
+        copc_info.gpstime_maximum = gpstime_maximum;
+        copc_info_changed = true;
+    }
+    if !copc_info.spacing.is_finite() || copc_info.spacing <= 0. {
+        // the spacing of the writer
+        let spacing = 2. * copc_info.halfsize / root_points.max(1) as f64;
+        summary.fix(
+            ValidationCheck::CopcInfo,
+            format!("set the spacing from {} to {spacing}", copc_info.spacing),
+        );
+        copc_info.spacing = spacing;
+        copc_info_changed = true;
+    }
+    if copc_info_reserved {
+        summary.fix(
+            ValidationCheck::CopcInfo,
+            "set the reserved fields of the COPC info VLR to 0".to_string(),
+        );
+        copc_info_changed = true;
+    }
+
+    // write the fixes
+    if let Some(evlr) = hierarchy {
+        let size = evlr.data.len() as u64;
+        if size <= hierarchy_evlr.data_len {
+            file.seek(SeekFrom::Start(hierarchy_evlr.data_offset))?;
+            file.write_all(&evlr.data)?;
+            // the rest of the evlr is no longer referenced
+            file.write_all(&vec![0; (hierarchy_evlr.data_len - size) as usize])?;
+        } else {
+            file.seek(SeekFrom::Start(hierarchy_evlr.offset))?;
+            evlr.into_raw(true)?.write_to(&mut file)?;
+            file.set_len(hierarchy_evlr.data_offset + size)?;
+        }
+    }
+    if header_changed {
+        file.seek(SeekFrom::Start(0))?;
+        header.write_to(&mut file)?;
+    }
+    if copc_info_changed {
+        file.seek(SeekFrom::Start(copc_info_offset))?;
+        file.write_all(&copc_info.into_vlr()?.data)?;
+    }
+    file.flush()?;
+
+    Ok(summary)
+}
+
+/// The defects of the file that can only be fixed by rewriting it
+///
+/// A missing WKT CRS is left as it is if no CRS is `crs_available` for the rewrite
+fn rewrite_reasons(file_scan: &FileScan, crs_available: bool) -> Vec<RepairFix> {
+    use ValidationCheck::*;
+
+    let header = &file_scan.header;
+    let header_fixable = header.version == las::Version::new(1, 4)
+        && header.header_size == 375
+        && (6..=8).contains(&(header.point_data_record_format & 0b00111111));
+    let halfsize_fixable = file_scan
+        .copc_info
+        .as_ref()
+        .is_some_and(|(_, c)| c.halfsize.is_normal() && c.halfsize > 0.);
+
+    let mut reasons: Vec<RepairFix> = file_scan
+        .report
+        .errors()
+        .filter(|i| match i.check {
+            Header => !header_fixable,
+            Vlrs => crs_available || i.message != MISSING_WKT,
+            Chunks | PointBounds => true,
+            PointCount => i.key.is_some(),
+            Hierarchy => i.key.as_ref().is_some_and(|k| !key_in_octree(k)),
+            CopcInfo => !halfsize_fixable,
+            GpsTime => false,
+        })
+        .map(|i| RepairFix {
+            check: i.check,
+            message: i.message.clone(),
+        })
+        .collect();
+    if file_scan.copc_info.is_some() && file_scan.hierarchy_evlr.is_none() && reasons.is_empty() {
+        reasons.push(RepairFix {
+            check: Vlrs,
+            message: "the EPT hierarchy EVLR is missing".to_string(),
+        });
+    }
+    reasons
+}
+
+/// Rewrites the points of the file at `path` to a temporary file that then replaces it
+///
+/// The nodes whose chunk can not be decompressed are left out
+fn rewrite(
+    path: &Path,
+    file: File,
+    mut reasons: Vec<RepairFix>,
+    options: &RepairOptions,
+) -> crate::Result<RepairSummary> {
+    if !options.allow_rewrite {
+        return Err(crate::Error::RepairNeedsRewrite(reasons[0].message.clone()));
+    }
+    drop(file);
+
+    // the nodes with points that can be decompressed, in the order of their chunks
+    let mut reader = CopcReader::from_path(path)?;
+    let keys: BTreeSet<VoxelKey> = reader
+        .hierarchy_entries()
+        .iter()
+        .filter(|(_, entry)| entry.point_count > 0)
+        .map(|(key, _)| key.clone())
+        .collect();
+    let mut nodes = Vec::new();
+    let mut num_points = 0;
+    let mut bounds = Bounds::default();
+    for key in reader.keys_by_offset(&keys) {
+        match reader.checked_node_points(&key) {
+            Ok(points) => {
+                num_points += points.len() as u64;
+                points.iter().for_each(|p| bounds.grow(p));
+                nodes.push(key);
+            }
+            Err(e) => reasons.push(RepairFix {
+                check: ValidationCheck::PointCount,
+                message: format!("dropped the points of the node {key}: {e}"),
+            }),
+        }
+    }
+    let header = reader.header().clone();
+    let points = nodes
+        .into_iter()
+        .flat_map(|key| reader.checked_node_points(&key).unwrap_or_default());
+
+    // the writer needs the .copc.laz extension
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = path.with_file_name(format!(".repair-{file_name}"));
+    let written = copc_from_points(
+        header,
+        num_points,
+        Some(bounds),
+        points,
+        &temp_path,
+        &options.convert,
+    );
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    std::fs::rename(&temp_path, path)?;
+
+    Ok(RepairSummary {
+        fixes: reasons,
+        rewritten: true,
+    })
+}
+
+/// The entries sorted by key, with empty entries for missing ancestors
+fn flat_hierarchy(mut entries: HashMap<VoxelKey, Entry>) -> Vec<Entry> {
+    let keys: Vec<VoxelKey> = entries.keys().cloned().collect();
+    for mut key in keys {
+        while let Some(parent) = key.parent() {
+            entries.entry(parent.clone()).or_insert_with(|| Entry {
+                key: parent.clone(),
+                ..Default::default()
+            });
+            key = parent;
+        }
+    }
+    let mut entries: Vec<Entry> = entries
+        .into_values()
+        .map(|mut e| {
+            if e.point_count == 0 {
+                e.offset = 0;
+                e.byte_size = 0;
+            }
+            e
+        })
+        .collect();
+    entries.sort_by(|a, b| a.key.cmp(&b.key));
+    entries
+}
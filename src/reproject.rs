@@ -0,0 +1,388 @@
+//! Reprojection of query results.
+
+use crate::reader::PointIter;
+use las::{Bounds, Point, Vector};
+use std::f64::consts::FRAC_PI_4;
+use std::io::{Read, Seek};
+
+/// Number of segments each edge of a bounding box is split into when transforming bounds
+const EDGE_SEGMENTS: usize = 16;
+
+/// A transform of coordinates from the CRS of the point cloud (source) to a target CRS
+///
+/// Geographic coordinates are longitude (x) and latitude (y) in degrees
+pub trait PointTransform {
+    /// Transforms source coordinates to target coordinates
+    fn forward(&self, p: Vector<f64>) -> Vector<f64>;
+
+    /// Transforms target coordinates to source coordinates
+    fn inverse(&self, p: Vector<f64>) -> Vector<f64>;
+
+    /// The source bounds enclosing the target `bounds`
+    ///
+    /// Points along the edges of the bounds are transformed,
+    /// which is exact for transforms keeping straight lines straight
+    /// and a close approximation for the usual map projections
+    fn inverse_bounds(&self, bounds: &Bounds) -> Bounds {
+        transform_bounds(bounds, |p| self.inverse(p))
+    }
+
+    /// The target bounds enclosing the source `bounds`, see [inverse_bounds]
+    ///
+    /// [inverse_bounds]: Self::inverse_bounds
+    fn forward_bounds(&self, bounds: &Bounds) -> Bounds {
+        transform_bounds(bounds, |p| self.forward(p))
+    }
+}
+
+/// The inverse of a transform
+#[derive(Clone, Copy, Debug)]
+pub struct Inverse<T>(pub T);
+
+impl<T: PointTransform> PointTransform for Inverse<T> {
+    fn forward(&self, p: Vector<f64>) -> Vector<f64> {
+        self.0.inverse(p)
+    }
+
+    fn inverse(&self, p: Vector<f64>) -> Vector<f64> {
+        self.0.forward(p)
+    }
+}
+
+/// The first transform followed by the second
+///
+/// ```
+/// use copc_rs::{Chain, Inverse, PointTransform, TransverseMercator, WebMercator};
+///
+/// // from UTM zone 32N to Web Mercator
+/// let transform = Chain(Inverse(TransverseMercator::utm(32, true)), WebMercator);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Chain<A, B>(pub A, pub B);
+
+impl<A: PointTransform, B: PointTransform> PointTransform for Chain<A, B> {
+    fn forward(&self, p: Vector<f64>) -> Vector<f64> {
+        self.1.forward(self.0.forward(p))
+    }
+
+    fn inverse(&self, p: Vector<f64>) -> Vector<f64> {
+        self.0.inverse(self.1.inverse(p))
+    }
+}
+
+/// Reference ellipsoid of a geographic CRS
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ellipsoid {
+    /// Semi-major axis in metres
+    pub a: f64,
+    /// Flattening
+    pub f: f64,
+}
+
+impl Ellipsoid {
+    /// The WGS 84 ellipsoid
+    pub const WGS84: Ellipsoid = Ellipsoid {
+        a: 6_378_137.,
+        f: 1. / 298.257_223_563,
+    };
+
+    /// The GRS 1980 ellipsoid, used by ETRS89 and NAD83
+    pub const GRS80: Ellipsoid = Ellipsoid {
+        a: 6_378_137.,
+        f: 1. / 298.257_222_101,
+    };
+}
+
+/// Spherical (Pseudo-)Mercator of web maps (EPSG:3857) from geographic coordinates
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WebMercator;
+
+impl WebMercator {
+    const RADIUS: f64 = 6_378_137.;
+}
+
+impl PointTransform for WebMercator {
+    fn forward(&self, p: Vector<f64>) -> Vector<f64> {
+        Vector {
+            x: Self::RADIUS * p.x.to_radians(),
+            y: Self::RADIUS * (FRAC_PI_4 + p.y.to_radians() / 2.).tan().ln(),
+            z: p.z,
+        }
+    }
+
+    fn inverse(&self, p: Vector<f64>) -> Vector<f64> {
+        Vector {
+            x: (p.x / Self::RADIUS).to_degrees(),
+            y: (2. * (p.y / Self::RADIUS).exp().atan() - 2. * FRAC_PI_4).to_degrees(),
+            z: p.z,
+        }
+    }
+}
+
+/// Ellipsoidal Transverse Mercator from geographic coordinates,
+/// using the Krüger series, accurate to the millimetre within a few degrees of the central meridian
+///
+/// Only the projection is computed, there is no datum shift
+#[derive(Clone, Copy, Debug)]
+pub struct TransverseMercator {
+    lon_0: f64,
+    k_0: f64,
+    false_easting: f64,
+    false_northing: f64,
+    n: f64,
+    // rectifying radius
+    a_r: f64,
+    alpha: [f64; 3],
+    beta: [f64; 3],
+}
+
+impl TransverseMercator {
+    /// Transverse Mercator with the central meridian `lon_0` and the latitude of origin `lat_0`
+    /// in degrees and the scale factor `k_0` on the central meridian
+    pub fn new(
+        ellipsoid: Ellipsoid,
+        lon_0: f64,
+        lat_0: f64,
+        k_0: f64,
+        false_easting: f64,
+        false_northing: f64,
+    ) -> Self {
+        let n = ellipsoid.f / (2. - ellipsoid.f);
+        let (n2, n3) = (n * n, n * n * n);
+        let mut tm = TransverseMercator {
+            lon_0,
+            k_0,
+            false_easting,
+            false_northing,
+            n,
+            a_r: ellipsoid.a / (1. + n) * (1. + n2 / 4. + n2 * n2 / 64.),
+            alpha: [
+                n / 2. - 2. * n2 / 3. + 5. * n3 / 16.,
+                13. * n2 / 48. - 3. * n3 / 5.,
+                61. * n3 / 240.,
+            ],
+            beta: [
+                n / 2. - 2. * n2 / 3. + 37. * n3 / 96.,
+                n2 / 48. + n3 / 15.,
+                17. * n3 / 480.,
+            ],
+        };
+        // northing of the origin, subtracted from all northings
+        if lat_0 != 0. {
+            let origin = tm.forward(Vector {
+                x: lon_0,
+                y: lat_0,
+                z: 0.,
+            });
+            tm.false_northing -= origin.y - false_northing;
+        }
+        tm
+    }
+
+    /// UTM `zone` (1-60) on the WGS 84 ellipsoid, of the northern or southern hemisphere
+    pub fn utm(zone: u8, north: bool) -> Self {
+        TransverseMercator::new(
+            Ellipsoid::WGS84,
+            zone as f64 * 6. - 183.,
+            0.,
+            0.9996,
+            500_000.,
+            if north { 0. } else { 10_000_000. },
+        )
+    }
+}
+
+impl PointTransform for TransverseMercator {
+    fn forward(&self, p: Vector<f64>) -> Vector<f64> {
+        let lat = p.y.to_radians();
+        let d_lon = (p.x - self.lon_0).to_radians();
+
+        let c = 2. * self.n.sqrt() / (1. + self.n);
+        let t = (lat.sin().atanh() - c * (c * lat.sin()).atanh()).sinh();
+        let xi_p = t.atan2(d_lon.cos());
+        let eta_p = (d_lon.sin() / (1. + t * t).sqrt()).atanh();
+
+        let (mut xi, mut eta) = (xi_p, eta_p);
+        for (j, alpha) in self.alpha.iter().enumerate() {
+            let j2 = 2. * (j + 1) as f64;
+            xi += alpha * (j2 * xi_p).sin() * (j2 * eta_p).cosh();
+            eta += alpha * (j2 * xi_p).cos() * (j2 * eta_p).sinh();
+        }
+
+        Vector {
+            x: self.false_easting + self.k_0 * self.a_r * eta,
+            y: self.false_northing + self.k_0 * self.a_r * xi,
+            z: p.z,
+        }
+    }
+
+    fn inverse(&self, p: Vector<f64>) -> Vector<f64> {
+        let xi = (p.y - self.false_northing) / (self.k_0 * self.a_r);
+        let eta = (p.x - self.false_easting) / (self.k_0 * self.a_r);
+
+        let (mut xi_p, mut eta_p) = (xi, eta);
+        for (j, beta) in self.beta.iter().enumerate() {
+            let j2 = 2. * (j + 1) as f64;
+            xi_p -= beta * (j2 * xi).sin() * (j2 * eta).cosh();
+            eta_p -= beta * (j2 * xi).cos() * (j2 * eta).sinh();
+        }
+
+        // solve the conformal latitude for the latitude with newton's method
+        let chi = (xi_p.sin() / eta_p.cosh()).asin();
+        let e = 2. * self.n.sqrt() / (1. + self.n);
+        let psi = chi.tan().asinh();
+        let mut lat = chi;
+        for _ in 0..10 {
+            let sin_lat = lat.sin();
+            let f = sin_lat.atanh() - e * (e * sin_lat).atanh() - psi;
+            let df = (1. - e * e) / ((1. - e * e * sin_lat * sin_lat) * lat.cos());
+            let step = f / df;
+            lat -= step;
+            if step.abs() < 1e-15 {
+                break;
+            }
+        }
+
+        Vector {
+            x: self.lon_0 + eta_p.sinh().atan2(xi_p.cos()).to_degrees(),
+            y: lat.to_degrees(),
+            z: p.z,
+        }
+    }
+}
+
+/// Earth-centered, earth-fixed (ECEF) coordinates from geographic coordinates
+/// with ellipsoidal heights, the coordinates of 3D Tiles
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Geocentric {
+    /// The ellipsoid of the geographic coordinates
+    pub ellipsoid: Ellipsoid,
+}
+
+impl Geocentric {
+    /// Geocentric coordinates on the WGS 84 ellipsoid (EPSG:4978)
+    pub const WGS84: Geocentric = Geocentric {
+        ellipsoid: Ellipsoid::WGS84,
+    };
+
+    fn e2(&self) -> f64 {
+        self.ellipsoid.f * (2. - self.ellipsoid.f)
+    }
+}
+
+impl PointTransform for Geocentric {
+    fn forward(&self, p: Vector<f64>) -> Vector<f64> {
+        let (lon, lat) = (p.x.to_radians(), p.y.to_radians());
+        let e2 = self.e2();
+        // radius of curvature in the prime vertical
+        let n = self.ellipsoid.a / (1. - e2 * lat.sin().powi(2)).sqrt();
+        Vector {
+            x: (n + p.z) * lat.cos() * lon.cos(),
+            y: (n + p.z) * lat.cos() * lon.sin(),
+            z: (n * (1. - e2) + p.z) * lat.sin(),
+        }
+    }
+
+    fn inverse(&self, p: Vector<f64>) -> Vector<f64> {
+        let e2 = self.e2();
+        let r = p.x.hypot(p.y);
+        // a few fixed-point iterations converge to well below a millimetre
+        let mut lat = p.z.atan2(r * (1. - e2));
+        for _ in 0..5 {
+            let n = self.ellipsoid.a / (1. - e2 * lat.sin().powi(2)).sqrt();
+            lat = (p.z + e2 * n * lat.sin()).atan2(r);
+        }
+        let height = r * lat.cos() + p.z * lat.sin()
+            - self.ellipsoid.a * (1. - e2 * lat.sin().powi(2)).sqrt();
+        Vector {
+            x: p.y.atan2(p.x).to_degrees(),
+            y: lat.to_degrees(),
+            z: height,
+        }
+    }
+}
+
+/// Iterator over the points of a query, transformed to the target CRS
+pub struct TransformedPointIter<'a, R: Read + Seek, T: PointTransform> {
+    pub(crate) points: PointIter<'a, R>,
+    pub(crate) transform: T,
+    pub(crate) bounds: Option<Bounds>,
+}
+
+impl<R: Read + Seek, T: PointTransform> Iterator for TransformedPointIter<'_, R, T> {
+    type Item = Point;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for mut point in self.points.by_ref() {
+            let p = self.transform.forward(Vector {
+                x: point.x,
+                y: point.y,
+                z: point.z,
+            });
+            if let Some(bounds) = &self.bounds {
+                if p.x < bounds.min.x
+                    || p.y < bounds.min.y
+                    || p.z < bounds.min.z
+                    || p.x > bounds.max.x
+                    || p.y > bounds.max.y
+                    || p.z > bounds.max.z
+                {
+                    continue;
+                }
+            }
+            point.x = p.x;
+            point.y = p.y;
+            point.z = p.z;
+            return Some(point);
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.points.size_hint().1)
+    }
+}
+
+/// The bounds enclosing the transformed points along the edges of `bounds`
+fn transform_bounds<F: Fn(Vector<f64>) -> Vector<f64>>(bounds: &Bounds, transform: F) -> Bounds {
+    let mut transformed = Bounds::default();
+    let corner = |i: usize| Vector {
+        x: if i & 1 == 0 {
+            bounds.min.x
+        } else {
+            bounds.max.x
+        },
+        y: if i & 2 == 0 {
+            bounds.min.y
+        } else {
+            bounds.max.y
+        },
+        z: if i & 4 == 0 {
+            bounds.min.z
+        } else {
+            bounds.max.z
+        },
+    };
+    // the 12 edges connect the corners differing in one coordinate
+    for (a, b) in (0..8)
+        .flat_map(|a| [1, 2, 4].map(|bit| (a, a | bit)))
+        .filter(|(a, b)| a != b)
+    {
+        let (a, b) = (corner(a), corner(b));
+        for i in 0..=EDGE_SEGMENTS {
+            let t = i as f64 / EDGE_SEGMENTS as f64;
+            let p = transform(Vector {
+                x: a.x + (b.x - a.x) * t,
+                y: a.y + (b.y - a.y) * t,
+                z: a.z + (b.z - a.z) * t,
+            });
+            transformed.grow(&Point {
+                x: p.x,
+                y: p.y,
+                z: p.z,
+                ..Default::default()
+            });
+        }
+    }
+    transformed
+}
@@ -0,0 +1,485 @@
+//! Statistics of the point attributes, for the whole file and per octree node.
+
+use crate::copc::VoxelKey;
+use crate::export::PointAttribute;
+use crate::extra_bytes::ExtraBytes;
+use crate::json::{object, Json};
+use crate::reader::{BoundsSelection, CopcReader, LodSelection};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use las::point::Format;
+use las::{Bounds, Point, Vlr};
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read, Seek};
+use std::ops::Range;
+
+/// User id of the EVLR of the statistics stored at write time
+pub const STATISTICS_USER_ID: &str = "copc-rs";
+/// Record id of the EVLR of the statistics stored at write time
+pub const STATISTICS_RECORD_ID: u16 = 1;
+
+/// Maximal number of bins of a histogram, a bin per value of the 16 bit attributes
+const MAX_BINS: usize = 1 << 16;
+
+/// Histogram of the values of an attribute in bins of equal width,
+/// values outside of the range are counted in the first or last bin
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    /// Lower bound of the first bin
+    pub min: f64,
+    /// Width of the bins
+    pub bin_width: f64,
+    /// Number of values in each bin
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    fn new(min: f64, max: f64, bins: usize) -> Self {
+        let bins = bins.clamp(1, MAX_BINS);
+        Histogram {
+            min,
+            bin_width: ((max - min) / bins as f64).max(f64::MIN_POSITIVE),
+            counts: vec![0; bins],
+        }
+    }
+
+    /// The range of the values of bin `i`
+    pub fn bin_range(&self, i: usize) -> Range<f64> {
+        let start = self.min + i as f64 * self.bin_width;
+        start..start + self.bin_width
+    }
+
+    fn add(&mut self, value: f64) {
+        let i = ((value - self.min) / self.bin_width).floor().max(0.) as usize;
+        let last = self.counts.len() - 1;
+        self.counts[i.min(last)] += 1;
+    }
+
+    fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+    }
+}
+
+/// Statistics of the values of an attribute
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttributeStatistics {
+    /// The attribute the values are of
+    pub attribute: PointAttribute,
+    /// Number of points with a value, the points of a format without the attribute
+    /// or with the no-data value of an extra attribute have none
+    pub count: u64,
+    /// Smallest value, infinity without values
+    pub min: f64,
+    /// Largest value, negative infinity without values
+    pub max: f64,
+    /// Sum of the values
+    pub sum: f64,
+    /// Histogram of the values, `None` for the gps time
+    /// and the extra attributes without minimum and maximum
+    pub histogram: Option<Histogram>,
+}
+
+impl AttributeStatistics {
+    fn new(attribute: PointAttribute, histogram: Option<Histogram>) -> Self {
+        AttributeStatistics {
+            attribute,
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            sum: 0.,
+            histogram,
+        }
+    }
+
+    /// Mean of the values, `None` without values
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        if let Some(histogram) = &mut self.histogram {
+            histogram.add(value);
+        }
+    }
+
+    fn merge(&mut self, other: &AttributeStatistics) {
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        if let (Some(histogram), Some(other)) = (&mut self.histogram, &other.histogram) {
+            histogram.merge(other);
+        }
+    }
+}
+
+/// Statistics of the attributes of a set of points
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Statistics {
+    /// Number of points
+    pub point_count: u64,
+    /// Statistics of each attribute, in the order of [StatisticsOptions::attributes]
+    pub attributes: Vec<AttributeStatistics>,
+}
+
+impl Statistics {
+    /// The statistics of `attribute`, `None` if it was not computed
+    pub fn get(&self, attribute: &PointAttribute) -> Option<&AttributeStatistics> {
+        self.attributes.iter().find(|a| &a.attribute == attribute)
+    }
+
+    /// The statistics as a JSON document, as printed by `copc stats --json`
+    pub fn to_json(&self) -> String {
+        let attributes: Vec<Json> = self
+            .attributes
+            .iter()
+            .map(|a| {
+                let histogram = a.histogram.as_ref().map(|h| {
+                    object([
+                        ("min", h.min.into()),
+                        ("bin_width", h.bin_width.into()),
+                        ("counts", h.counts.clone().into()),
+                    ])
+                });
+                object([
+                    ("attribute", a.attribute.to_string().into()),
+                    ("count", a.count.into()),
+                    ("min", (a.count > 0).then_some(a.min).into()),
+                    ("max", (a.count > 0).then_some(a.max).into()),
+                    ("mean", a.mean().into()),
+                    ("histogram", histogram.into()),
+                ])
+            })
+            .collect();
+        let json = object([
+            ("points", self.point_count.into()),
+            ("attributes", Json::Array(attributes)),
+        ]);
+        format!("{json}\n")
+    }
+
+    fn merge(&mut self, other: &Statistics) {
+        self.point_count += other.point_count;
+        for (attribute, other) in self.attributes.iter_mut().zip(&other.attributes) {
+            attribute.merge(other);
+        }
+    }
+}
+
+/// Statistics of the whole file, or of a query, and of each octree node
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileStatistics {
+    /// Statistics of all points
+    pub total: Statistics,
+    /// Statistics of the nodes with points
+    pub nodes: BTreeMap<VoxelKey, Statistics>,
+}
+
+impl FileStatistics {
+    /// The statistics as the data of the statistics EVLR, without the total
+    #[cfg_attr(not(feature = "writer"), allow(dead_code))]
+    fn to_vlr(&self) -> crate::Result<Vlr> {
+        let mut data = Vec::new();
+        data.write_u32::<LittleEndian>(self.total.attributes.len() as u32)?;
+        for attribute in &self.total.attributes {
+            let name = attribute.attribute.to_string();
+            data.write_u8(name.len() as u8)?;
+            data.extend_from_slice(name.as_bytes());
+            match &attribute.histogram {
+                Some(histogram) => {
+                    data.write_f64::<LittleEndian>(histogram.min)?;
+                    data.write_f64::<LittleEndian>(histogram.bin_width)?;
+                    data.write_u32::<LittleEndian>(histogram.counts.len() as u32)?;
+                }
+                None => {
+                    data.write_f64::<LittleEndian>(0.)?;
+                    data.write_f64::<LittleEndian>(0.)?;
+                    data.write_u32::<LittleEndian>(0)?;
+                }
+            }
+        }
+        data.write_u32::<LittleEndian>(self.nodes.len() as u32)?;
+        for (key, statistics) in &self.nodes {
+            for value in [key.level, key.x, key.y, key.z] {
+                data.write_i32::<LittleEndian>(value)?;
+            }
+            data.write_u64::<LittleEndian>(statistics.point_count)?;
+            for attribute in &statistics.attributes {
+                data.write_u64::<LittleEndian>(attribute.count)?;
+                data.write_f64::<LittleEndian>(attribute.min)?;
+                data.write_f64::<LittleEndian>(attribute.max)?;
+                data.write_f64::<LittleEndian>(attribute.sum)?;
+                // only the bins with values
+                let counts = attribute.histogram.iter().flat_map(|h| &h.counts);
+                let bins: Vec<(usize, &u64)> =
+                    counts.enumerate().filter(|(_, c)| **c > 0).collect();
+                data.write_u32::<LittleEndian>(bins.len() as u32)?;
+                for (i, count) in bins {
+                    data.write_u32::<LittleEndian>(i as u32)?;
+                    data.write_u64::<LittleEndian>(*count)?;
+                }
+            }
+        }
+        Ok(Vlr {
+            user_id: STATISTICS_USER_ID.to_string(),
+            record_id: STATISTICS_RECORD_ID,
+            description: "Point attribute statistics".to_string(),
+            data,
+        })
+    }
+
+    fn from_vlr(vlr: &Vlr) -> crate::Result<Self> {
+        let mut read = Cursor::new(vlr.data.as_slice());
+        // a count of records of at least `size` bytes, checked against the remaining data
+        let count = |read: &mut Cursor<&[u8]>, size: u64, records: &str| {
+            let count = read.read_u32::<LittleEndian>()?;
+            let remaining = (vlr.data.len() as u64).saturating_sub(read.position());
+            if count as u64 * size > remaining {
+                return Err(crate::Error::InvalidStatisticsVlr(format!(
+                    "{count} {records} do not fit in the remaining {remaining} bytes"
+                )));
+            }
+            Ok(count)
+        };
+
+        let mut empty = Statistics::default();
+        for _ in 0..count(&mut read, 21, "attributes")? {
+            let mut name = vec![0; read.read_u8()? as usize];
+            read.read_exact(&mut name)?;
+            let attribute = String::from_utf8_lossy(&name)
+                .parse()
+                .unwrap_or_else(|e| match e {});
+            let min = read.read_f64::<LittleEndian>()?;
+            let bin_width = read.read_f64::<LittleEndian>()?;
+            let bins = read.read_u32::<LittleEndian>()? as usize;
+            if bins > MAX_BINS {
+                return Err(crate::Error::InvalidStatisticsVlr(format!(
+                    "{bins} histogram bins are more than {MAX_BINS}"
+                )));
+            }
+            let histogram = (bins > 0).then(|| Histogram {
+                min,
+                bin_width,
+                counts: vec![0; bins],
+            });
+            empty
+                .attributes
+                .push(AttributeStatistics::new(attribute, histogram));
+        }
+
+        let mut statistics = FileStatistics {
+            total: empty.clone(),
+            nodes: BTreeMap::new(),
+        };
+        let node_size = 24 + 36 * empty.attributes.len() as u64;
+        for _ in 0..count(&mut read, node_size, "nodes")? {
+            let key = VoxelKey {
+                level: read.read_i32::<LittleEndian>()?,
+                x: read.read_i32::<LittleEndian>()?,
+                y: read.read_i32::<LittleEndian>()?,
+                z: read.read_i32::<LittleEndian>()?,
+            };
+            let mut node = empty.clone();
+            node.point_count = read.read_u64::<LittleEndian>()?;
+            for attribute in &mut node.attributes {
+                attribute.count = read.read_u64::<LittleEndian>()?;
+                attribute.min = read.read_f64::<LittleEndian>()?;
+                attribute.max = read.read_f64::<LittleEndian>()?;
+                attribute.sum = read.read_f64::<LittleEndian>()?;
+                for _ in 0..count(&mut read, 12, "histogram bins")? {
+                    let i = read.read_u32::<LittleEndian>()? as usize;
+                    let count = read.read_u64::<LittleEndian>()?;
+                    if let Some(bin) = attribute
+                        .histogram
+                        .as_mut()
+                        .and_then(|h| h.counts.get_mut(i))
+                    {
+                        *bin = count;
+                    }
+                }
+            }
+            statistics.total.merge(&node);
+            statistics.nodes.insert(key, node);
+        }
+        Ok(statistics)
+    }
+}
+
+/// Options for [CopcReader::statistics] and [crate::CopcWriterBuilder::statistics]
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatisticsOptions {
+    /// The attributes, by default the elevation, intensity, return numbers and classification
+    pub attributes: Option<Vec<PointAttribute>>,
+    /// Number of bins of the histograms of the coordinates and of the 16 bit attributes,
+    /// at most 65536, the classification, user data and return numbers have a bin per value
+    pub bins: usize,
+}
+
+impl Default for StatisticsOptions {
+    fn default() -> Self {
+        StatisticsOptions {
+            attributes: None,
+            bins: 100,
+        }
+    }
+}
+
+/// Computes the statistics of the points added to the nodes
+pub(crate) struct StatisticsAccumulator {
+    #[cfg_attr(not(feature = "writer"), allow(dead_code))]
+    pub(crate) options: StatisticsOptions,
+    extra_bytes: ExtraBytes,
+    empty: Statistics,
+    nodes: BTreeMap<VoxelKey, Statistics>,
+}
+
+impl StatisticsAccumulator {
+    /// The histograms of the coordinates cover the `cube` of the octree
+    pub(crate) fn new(
+        options: &StatisticsOptions,
+        format: &Format,
+        extra_bytes: &ExtraBytes,
+        cube: &Bounds,
+    ) -> crate::Result<Self> {
+        use PointAttribute::*;
+
+        let attributes = options
+            .attributes
+            .clone()
+            .unwrap_or_else(|| vec![Z, Intensity, ReturnNumber, NumberOfReturns, Classification]);
+        let bins = options.bins;
+        let mut empty = Statistics::default();
+        for attribute in attributes {
+            let histogram = match &attribute {
+                X => Some(Histogram::new(cube.min.x, cube.max.x, bins)),
+                Y => Some(Histogram::new(cube.min.y, cube.max.y, bins)),
+                Z => Some(Histogram::new(cube.min.z, cube.max.z, bins)),
+                ReturnNumber | NumberOfReturns => {
+                    let returns = if format.is_extended { 16 } else { 8 };
+                    Some(Histogram::new(0., returns as f64, returns))
+                }
+                Classification | UserData => Some(Histogram::new(0., 256., 256)),
+                Intensity | PointSourceId | Red | Green | Blue | Nir => {
+                    Some(Histogram::new(0., 65536., bins))
+                }
+                ScanAngle => Some(Histogram::new(-180., 180., bins)),
+                GpsTime => None,
+                Extra(name) => {
+                    let descriptor = extra_bytes
+                        .descriptors()
+                        .iter()
+                        .find(|d| &d.name == name)
+                        .ok_or_else(|| crate::Error::UnknownAttribute(name.clone()))?;
+                    let scaled = |v: f64| {
+                        v * descriptor.scale.unwrap_or(1.) + descriptor.offset.unwrap_or(0.)
+                    };
+                    match (descriptor.min, descriptor.max) {
+                        (Some(min), Some(max)) if scaled(max) > scaled(min) => {
+                            Some(Histogram::new(scaled(min), scaled(max), bins))
+                        }
+                        _ => None,
+                    }
+                }
+            };
+            empty
+                .attributes
+                .push(AttributeStatistics::new(attribute, histogram));
+        }
+        Ok(StatisticsAccumulator {
+            options: options.clone(),
+            extra_bytes: extra_bytes.clone(),
+            empty,
+            nodes: BTreeMap::new(),
+        })
+    }
+
+    pub(crate) fn add(&mut self, key: &VoxelKey, point: &Point) {
+        if !self.nodes.contains_key(key) {
+            self.nodes.insert(key.clone(), self.empty.clone());
+        }
+        let node = self.nodes.get_mut(key).unwrap();
+        node.point_count += 1;
+        for attribute in &mut node.attributes {
+            if let Some(value) = attribute.attribute.value(point, &self.extra_bytes) {
+                attribute.add(value);
+            }
+        }
+    }
+
+    pub(crate) fn finish(self) -> FileStatistics {
+        let mut total = self.empty;
+        for node in self.nodes.values() {
+            total.merge(node);
+        }
+        FileStatistics {
+            total,
+            nodes: self.nodes,
+        }
+    }
+
+    /// The statistics EVLR of the points added so far
+    #[cfg_attr(not(feature = "writer"), allow(dead_code))]
+    pub(crate) fn to_evlr(&self) -> crate::Result<Vlr> {
+        FileStatistics {
+            total: self.empty.clone(),
+            nodes: self.nodes.clone(),
+        }
+        .to_vlr()
+    }
+}
+
+impl<R: Read + Seek> CopcReader<R> {
+    /// Computes the statistics of the points of a query, of all points and of each node
+    ///
+    /// The histograms of the coordinates cover the cube of the octree
+    pub fn statistics(
+        &mut self,
+        levels: LodSelection,
+        bounds: BoundsSelection,
+        options: &StatisticsOptions,
+    ) -> crate::Result<FileStatistics> {
+        let info = self.copc_info();
+        let cube = Bounds {
+            min: las::Vector {
+                x: info.center.x - info.halfsize,
+                y: info.center.y - info.halfsize,
+                z: info.center.z - info.halfsize,
+            },
+            max: las::Vector {
+                x: info.center.x + info.halfsize,
+                y: info.center.y + info.halfsize,
+                z: info.center.z + info.halfsize,
+            },
+        };
+        let mut accumulator = StatisticsAccumulator::new(
+            options,
+            self.header().point_format(),
+            self.extra_bytes(),
+            &cube,
+        )?;
+        for key in self.query_keys(levels, &bounds)? {
+            for point in self.node_points_within(&key, &bounds)? {
+                accumulator.add(&key, &point);
+            }
+        }
+        Ok(accumulator.finish())
+    }
+
+    /// The statistics of all points stored at write time
+    /// with [crate::CopcWriterBuilder::statistics], `None` if the file has none
+    pub fn stored_statistics(&self) -> crate::Result<Option<FileStatistics>> {
+        self.header()
+            .evlrs()
+            .iter()
+            .find(|evlr| {
+                evlr.user_id == STATISTICS_USER_ID && evlr.record_id == STATISTICS_RECORD_ID
+            })
+            .map(FileStatistics::from_vlr)
+            .transpose()
+    }
+}
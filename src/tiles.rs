@@ -0,0 +1,475 @@
+//! Export of a COPC octree to a Cesium 3D Tiles tileset.
+
+use crate::copc::VoxelKey;
+use crate::json::{object, Json};
+use crate::reader::CopcReader;
+#[cfg(feature = "reproject")]
+use crate::reproject::PointTransform;
+use byteorder::{LittleEndian, WriteBytesExt};
+use las::{Bounds, Vector};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Read, Seek};
+use std::path::Path;
+
+/// Directory of the tile contents in the tileset directory
+const CONTENT_DIRECTORY: &str = "tiles";
+
+/// Content format of the tiles
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileFormat {
+    /// Point cloud tiles of 3D Tiles 1.0, with the intensity and classification
+    /// as per-point properties of the batch table
+    #[default]
+    Pnts,
+    /// Binary glTF with a points primitive of 3D Tiles 1.1, with positions and colors
+    Glb,
+}
+
+impl TileFormat {
+    fn extension(&self) -> &str {
+        match self {
+            TileFormat::Pnts => "pnts",
+            TileFormat::Glb => "glb",
+        }
+    }
+}
+
+/// Options for [CopcReader::export_tiles]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TilesOptions {
+    /// The format of the tile contents, defaults to pnts
+    pub format: TileFormat,
+}
+
+/// Summary of [CopcReader::export_tiles]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TilesSummary {
+    /// Number of tiles with points
+    pub tiles_written: usize,
+    /// Number of points written
+    pub points_written: u64,
+    /// Deepest level of the octree
+    pub depth: i32,
+}
+
+/// The points of a tile, relative to the center of the tile
+#[derive(Default)]
+struct TilePoints {
+    center: Vector<f64>,
+    positions: Vec<[f32; 3]>,
+    colors: Option<Vec<[u8; 3]>>,
+    intensities: Vec<u16>,
+    classifications: Vec<u8>,
+}
+
+impl<R: Read + Seek> CopcReader<R> {
+    /// Writes the octree to a 3D Tiles tileset in `directory`, `tileset.json`
+    /// and a tile per node with points in `tiles/`, named by the key of the node like `1-0-1-0.pnts`
+    ///
+    /// The bounding volumes are the cubes of the nodes and the geometric error of a tile is
+    /// the spacing of its level, in the coordinates and unit of the CRS of the points.
+    /// The tiles refine additively, like the levels of the octree
+    pub fn export_tiles<P: AsRef<Path>>(
+        &mut self,
+        directory: P,
+        options: &TilesOptions,
+    ) -> crate::Result<TilesSummary> {
+        self.write_tiles(directory.as_ref(), options, |p| p, |bounds| *bounds)
+    }
+
+    /// Writes the octree to a 3D Tiles tileset in `directory` like [Self::export_tiles],
+    /// with the points and the bounding volumes transformed by `transform`
+    ///
+    /// CesiumJS expects ECEF coordinates, from a projected CRS like
+    /// `Chain(Inverse(TransverseMercator::utm(32, true)), Geocentric::WGS84)`
+    #[cfg(feature = "reproject")]
+    pub fn export_tiles_transformed<P: AsRef<Path>, T: PointTransform>(
+        &mut self,
+        directory: P,
+        options: &TilesOptions,
+        transform: T,
+    ) -> crate::Result<TilesSummary> {
+        self.write_tiles(
+            directory.as_ref(),
+            options,
+            |p| transform.forward(p),
+            |bounds| transform.forward_bounds(bounds),
+        )
+    }
+
+    fn write_tiles<F, B>(
+        &mut self,
+        directory: &Path,
+        options: &TilesOptions,
+        transform: F,
+        transform_bounds: B,
+    ) -> crate::Result<TilesSummary>
+    where
+        F: Fn(Vector<f64>) -> Vector<f64>,
+        B: Fn(&Bounds) -> Bounds,
+    {
+        std::fs::create_dir_all(directory.join(CONTENT_DIRECTORY))?;
+        let info = self.copc_info().clone();
+        let root_bounds = Bounds {
+            min: Vector {
+                x: info.center.x - info.halfsize,
+                y: info.center.y - info.halfsize,
+                z: info.center.z - info.halfsize,
+            },
+            max: Vector {
+                x: info.center.x + info.halfsize,
+                y: info.center.y + info.halfsize,
+                z: info.center.z + info.halfsize,
+            },
+        };
+        let has_color = self.header().point_format().has_color;
+
+        let keys = self.octree_keys();
+        let mut volumes = HashMap::new();
+        let mut contents = HashMap::new();
+        let mut summary = TilesSummary::default();
+        for key in self.keys_by_offset(&keys) {
+            let volume = transform_bounds(&key.bounds(&root_bounds));
+            let mut tile = TilePoints {
+                center: Vector {
+                    x: (volume.min.x + volume.max.x) / 2.,
+                    y: (volume.min.y + volume.max.y) / 2.,
+                    z: (volume.min.z + volume.max.z) / 2.,
+                },
+                colors: has_color.then(Vec::new),
+                ..Default::default()
+            };
+            for point in self.node_points(&key)? {
+                let p = transform(Vector {
+                    x: point.x,
+                    y: point.y,
+                    z: point.z,
+                });
+                tile.positions.push([
+                    (p.x - tile.center.x) as f32,
+                    (p.y - tile.center.y) as f32,
+                    (p.z - tile.center.z) as f32,
+                ]);
+                if let Some(colors) = &mut tile.colors {
+                    let color = point.color.unwrap_or_default();
+                    colors.push([
+                        (color.red >> 8) as u8,
+                        (color.green >> 8) as u8,
+                        (color.blue >> 8) as u8,
+                    ]);
+                }
+                tile.intensities.push(point.intensity);
+                tile.classifications.push(u8::from(point.classification));
+            }
+
+            if !tile.positions.is_empty() {
+                let uri = format!("{CONTENT_DIRECTORY}/{key}.{}", options.format.extension());
+                let bytes = match options.format {
+                    TileFormat::Pnts => tile.to_pnts(),
+                    TileFormat::Glb => tile.to_glb(),
+                };
+                std::fs::write(directory.join(&uri), bytes)?;
+                summary.tiles_written += 1;
+                summary.points_written += tile.positions.len() as u64;
+                contents.insert(key.clone(), uri);
+            }
+            summary.depth = summary.depth.max(key.level);
+            volumes.insert(key, volume);
+        }
+
+        let root = VoxelKey {
+            level: 0,
+            ..Default::default()
+        };
+        let version = match options.format {
+            TileFormat::Pnts => "1.0",
+            TileFormat::Glb => "1.1",
+        };
+        let tileset = object([
+            (
+                "asset",
+                object([
+                    ("version", version.into()),
+                    (
+                        "generator",
+                        format!("copc-rs {}", env!("CARGO_PKG_VERSION")).into(),
+                    ),
+                ]),
+            ),
+            ("geometricError", (info.spacing * 2.).into()),
+            (
+                "root",
+                tile_json(&root, &keys, &volumes, &contents, info.spacing),
+            ),
+        ]);
+        std::fs::write(directory.join("tileset.json"), format!("{tileset}\n"))?;
+        Ok(summary)
+    }
+}
+
+/// The tile of `key` with its children, the root refines additively
+fn tile_json(
+    key: &VoxelKey,
+    keys: &BTreeSet<VoxelKey>,
+    volumes: &HashMap<VoxelKey, Bounds>,
+    contents: &HashMap<VoxelKey, String>,
+    spacing: f64,
+) -> Json {
+    let volume = volumes.get(key).copied().unwrap_or_default();
+    let half = |min: f64, max: f64| (max - min) / 2.;
+    let bounding_box = vec![
+        (volume.min.x + volume.max.x) / 2.,
+        (volume.min.y + volume.max.y) / 2.,
+        (volume.min.z + volume.max.z) / 2.,
+        half(volume.min.x, volume.max.x),
+        0.,
+        0.,
+        0.,
+        half(volume.min.y, volume.max.y),
+        0.,
+        0.,
+        0.,
+        half(volume.min.z, volume.max.z),
+    ];
+    let mut members = vec![
+        (
+            "boundingVolume".to_string(),
+            object([("box", bounding_box.into())]),
+        ),
+        (
+            "geometricError".to_string(),
+            (spacing / 2_f64.powi(key.level)).into(),
+        ),
+    ];
+    if key.level == 0 {
+        members.push(("refine".to_string(), "ADD".into()));
+    }
+    if let Some(uri) = contents.get(key) {
+        members.push((
+            "content".to_string(),
+            object([("uri", uri.as_str().into())]),
+        ));
+    }
+    let children: Vec<Json> = key
+        .children()
+        .iter()
+        .filter(|child| keys.contains(child))
+        .map(|child| tile_json(child, keys, volumes, contents, spacing))
+        .collect();
+    if !children.is_empty() {
+        members.push(("children".to_string(), Json::Array(children)));
+    }
+    Json::Object(members)
+}
+
+/// Pads `bytes` with `padding` to a multiple of `alignment` after `start` bytes
+fn pad(bytes: &mut Vec<u8>, start: usize, alignment: usize, padding: u8) {
+    while !(start + bytes.len()).is_multiple_of(alignment) {
+        bytes.push(padding);
+    }
+}
+
+impl TilePoints {
+    fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// The tile in the point cloud format, the positions relative to `RTC_CENTER`
+    fn to_pnts(&self) -> Vec<u8> {
+        const HEADER_SIZE: usize = 28;
+
+        let mut feature_binary = Vec::new();
+        for position in &self.positions {
+            for value in position {
+                feature_binary.write_f32::<LittleEndian>(*value).unwrap();
+            }
+        }
+        let mut feature_json = vec![
+            ("POINTS_LENGTH".to_string(), self.len().into()),
+            (
+                "RTC_CENTER".to_string(),
+                vec![self.center.x, self.center.y, self.center.z].into(),
+            ),
+            ("POSITION".to_string(), object([("byteOffset", 0.into())])),
+        ];
+        if let Some(colors) = &self.colors {
+            feature_json.push((
+                "RGB".to_string(),
+                object([("byteOffset", feature_binary.len().into())]),
+            ));
+            feature_binary.extend(colors.iter().flatten());
+        }
+        pad(&mut feature_binary, 0, 8, 0);
+        let mut feature_json = Json::Object(feature_json).to_string().into_bytes();
+        pad(&mut feature_json, HEADER_SIZE, 8, b' ');
+
+        let mut batch_binary = Vec::new();
+        for intensity in &self.intensities {
+            batch_binary.write_u16::<LittleEndian>(*intensity).unwrap();
+        }
+        let batch_json = object([
+            (
+                "Intensity",
+                object([
+                    ("byteOffset", 0.into()),
+                    ("componentType", "UNSIGNED_SHORT".into()),
+                    ("type", "SCALAR".into()),
+                ]),
+            ),
+            (
+                "Classification",
+                object([
+                    ("byteOffset", batch_binary.len().into()),
+                    ("componentType", "UNSIGNED_BYTE".into()),
+                    ("type", "SCALAR".into()),
+                ]),
+            ),
+        ]);
+        batch_binary.extend(&self.classifications);
+        pad(&mut batch_binary, 0, 8, 0);
+        let mut batch_json = batch_json.to_string().into_bytes();
+        pad(&mut batch_json, 0, 8, b' ');
+
+        let length = HEADER_SIZE
+            + feature_json.len()
+            + feature_binary.len()
+            + batch_json.len()
+            + batch_binary.len();
+        let mut bytes = Vec::with_capacity(length);
+        bytes.extend(b"pnts");
+        for value in [
+            1,
+            length,
+            feature_json.len(),
+            feature_binary.len(),
+            batch_json.len(),
+            batch_binary.len(),
+        ] {
+            bytes.write_u32::<LittleEndian>(value as u32).unwrap();
+        }
+        bytes.extend(feature_json);
+        bytes.extend(feature_binary);
+        bytes.extend(batch_json);
+        bytes.extend(batch_binary);
+        bytes
+    }
+
+    /// The tile in binary glTF, glTF is y-up, the z-up coordinates are rotated
+    /// like 3D Tiles rotates them back
+    fn to_glb(&self) -> Vec<u8> {
+        const ARRAY_BUFFER: u32 = 34962;
+        const FLOAT: u32 = 5126;
+        const UNSIGNED_BYTE: u32 = 5121;
+
+        let y_up = |p: [f32; 3]| [p[0], p[2], -p[1]];
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        let mut binary = Vec::new();
+        for position in &self.positions {
+            for (i, value) in y_up(*position).into_iter().enumerate() {
+                binary.write_f32::<LittleEndian>(value).unwrap();
+                min[i] = min[i].min(value);
+                max[i] = max[i].max(value);
+            }
+        }
+        let mut attributes = vec![("POSITION".to_string(), 0.into())];
+        let mut accessors = vec![object([
+            ("bufferView", 0.into()),
+            ("componentType", FLOAT.into()),
+            ("count", self.len().into()),
+            ("type", "VEC3".into()),
+            ("min", min.map(|v| v as f64).to_vec().into()),
+            ("max", max.map(|v| v as f64).to_vec().into()),
+        ])];
+        let mut buffer_views = vec![object([
+            ("buffer", 0.into()),
+            ("byteOffset", 0.into()),
+            ("byteLength", binary.len().into()),
+            ("target", ARRAY_BUFFER.into()),
+        ])];
+        if let Some(colors) = &self.colors {
+            // vertex attributes are aligned to 4 bytes
+            let offset = binary.len();
+            for color in colors {
+                binary.extend(color);
+                binary.push(0);
+            }
+            attributes.push(("COLOR_0".to_string(), 1.into()));
+            accessors.push(object([
+                ("bufferView", 1.into()),
+                ("componentType", UNSIGNED_BYTE.into()),
+                ("normalized", Json::Bool(true)),
+                ("count", self.len().into()),
+                ("type", "VEC3".into()),
+            ]));
+            buffer_views.push(object([
+                ("buffer", 0.into()),
+                ("byteOffset", offset.into()),
+                ("byteLength", (binary.len() - offset).into()),
+                ("byteStride", 4.into()),
+                ("target", ARRAY_BUFFER.into()),
+            ]));
+        }
+        pad(&mut binary, 0, 4, 0);
+
+        let translation = vec![self.center.x, self.center.z, -self.center.y];
+        let gltf = object([
+            (
+                "asset",
+                object([
+                    ("version", "2.0".into()),
+                    (
+                        "generator",
+                        format!("copc-rs {}", env!("CARGO_PKG_VERSION")).into(),
+                    ),
+                ]),
+            ),
+            ("scene", 0.into()),
+            ("scenes", vec![object([("nodes", vec![0].into())])].into()),
+            (
+                "nodes",
+                vec![object([
+                    ("mesh", 0.into()),
+                    ("translation", translation.into()),
+                ])]
+                .into(),
+            ),
+            (
+                "meshes",
+                vec![object([(
+                    "primitives",
+                    vec![object([
+                        ("attributes", Json::Object(attributes)),
+                        // points
+                        ("mode", 0.into()),
+                    ])]
+                    .into(),
+                )])]
+                .into(),
+            ),
+            ("accessors", accessors.into()),
+            ("bufferViews", buffer_views.into()),
+            (
+                "buffers",
+                vec![object([("byteLength", binary.len().into())])].into(),
+            ),
+        ]);
+        let mut json = gltf.to_string().into_bytes();
+        pad(&mut json, 0, 4, b' ');
+
+        let length = 12 + 8 + json.len() + 8 + binary.len();
+        let mut bytes = Vec::with_capacity(length);
+        bytes.extend(b"glTF");
+        bytes.write_u32::<LittleEndian>(2).unwrap();
+        bytes.write_u32::<LittleEndian>(length as u32).unwrap();
+        bytes.write_u32::<LittleEndian>(json.len() as u32).unwrap();
+        bytes.extend(b"JSON");
+        bytes.extend(json);
+        bytes
+            .write_u32::<LittleEndian>(binary.len() as u32)
+            .unwrap();
+        bytes.extend(b"BIN\0");
+        bytes.extend(binary);
+        bytes
+    }
+}
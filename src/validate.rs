@@ -0,0 +1,804 @@
+//! COPC 1.0 conformance checks.
+
+use crate::copc::{CopcInfo, Entry, HierarchyPage, VoxelKey};
+use crate::decompressor::CopcDecompressor;
+use crate::json::{object, Json};
+use byteorder::{LittleEndian, ReadBytesExt};
+use las::{raw, Bounds, Vector};
+use laz::LazVlr;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Size of the LAS 1.4 header
+const HEADER_SIZE: u64 = 375;
+/// Size of the header of a VLR
+const VLR_HEADER_SIZE: u64 = 54;
+/// Size of the header of an EVLR
+const EVLR_HEADER_SIZE: u64 = 60;
+
+/// Message of the issue of a file without WKT CRS
+pub(crate) const MISSING_WKT: &str = "the WKT CRS (E)VLR is missing";
+
+/// Severity of a [ValidationIssue]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Deviates from the spec, but readers handle it
+    Warning,
+    /// Violates the spec, readers may fail or return wrong data
+    Error,
+}
+
+/// The part of the file a [ValidationIssue] is about
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ValidationCheck {
+    /// LAS header fields
+    Header,
+    /// Presence and order of the (e)vlrs
+    Vlrs,
+    /// Content of the COPC info VLR
+    CopcInfo,
+    /// Structure of the EPT hierarchy
+    Hierarchy,
+    /// Offsets and sizes of the point data chunks
+    Chunks,
+    /// Point counts of the nodes and the header
+    PointCount,
+    /// Points outside the bounds of their node
+    PointBounds,
+    /// Gps times outside the range of the COPC info VLR
+    GpsTime,
+}
+
+/// A problem found by [validate]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationIssue {
+    /// Whether the issue is an error or a warning
+    pub severity: Severity,
+    /// The part of the file the issue is about
+    pub check: ValidationCheck,
+    /// The octree node the issue is about, if any
+    pub key: Option<VoxelKey>,
+    /// Description of the issue
+    pub message: String,
+}
+
+/// Result of [validate]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValidationReport {
+    /// All issues found, in the order they were found
+    pub issues: Vec<ValidationIssue>,
+    /// Number of nodes whose points were decompressed and checked
+    pub nodes_checked: usize,
+    /// Number of points decompressed and checked
+    pub points_checked: u64,
+}
+
+impl ValidationReport {
+    /// Whether no errors were found, warnings are allowed
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    /// The issues with [Severity::Error]
+    pub fn errors(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Error)
+    }
+
+    /// The issues with [Severity::Warning]
+    pub fn warnings(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == Severity::Warning)
+    }
+
+    /// The report as a JSON document, as printed by `copc validate --json`
+    pub fn to_json(&self) -> String {
+        let issues: Vec<Json> = self
+            .issues
+            .iter()
+            .map(|issue| {
+                object([
+                    ("severity", format!("{:?}", issue.severity).into()),
+                    ("check", format!("{:?}", issue.check).into()),
+                    ("key", issue.key.as_ref().map(VoxelKey::to_string).into()),
+                    ("message", issue.message.as_str().into()),
+                ])
+            })
+            .collect();
+        let json = object([
+            ("valid", self.is_valid().into()),
+            ("nodes_checked", self.nodes_checked.into()),
+            ("points_checked", self.points_checked.into()),
+            ("issues", Json::Array(issues)),
+        ]);
+        format!("{json}\n")
+    }
+
+    fn error(&mut self, check: ValidationCheck, key: Option<&VoxelKey>, message: String) {
+        self.push(Severity::Error, check, key, message);
+    }
+
+    fn warning(&mut self, check: ValidationCheck, key: Option<&VoxelKey>, message: String) {
+        self.push(Severity::Warning, check, key, message);
+    }
+
+    fn push(
+        &mut self,
+        severity: Severity,
+        check: ValidationCheck,
+        key: Option<&VoxelKey>,
+        message: String,
+    ) {
+        self.issues.push(ValidationIssue {
+            severity,
+            check,
+            key: key.cloned(),
+            message,
+        });
+    }
+}
+
+/// Validates the COPC file at `path`, see [validate]
+pub fn validate_path<P: AsRef<Path>>(path: P) -> crate::Result<ValidationReport> {
+    validate(BufReader::new(File::open(path)?))
+}
+
+/// Checks the COPC file in `read` against the COPC 1.0 spec
+///
+/// Checked are the LAS header, the presence and order of the (e)vlrs, the COPC info VLR,
+/// the consistency of the hierarchy, the offsets and sizes of the chunks and,
+/// after decompressing all points, the point counts, the node bounds and the gps time range.
+///
+/// An `Err` is only returned if the file can not be read at all,
+/// all problems found are reported in the [ValidationReport]
+///
+/// ```no_run
+/// let report = copc_rs::validate_path("./lidar.copc.laz").unwrap();
+/// for issue in report.errors() {
+///     println!("{:?}: {}", issue.check, issue.message);
+/// }
+/// ```
+pub fn validate<R: Read + Seek>(read: R) -> crate::Result<ValidationReport> {
+    Ok(scan(read, None)?.report)
+}
+
+/// What [scan] found in a COPC file, besides the [ValidationReport]
+// only the repair of the writer uses the scan beyond the report
+#[cfg_attr(not(feature = "writer"), allow(dead_code))]
+#[derive(Debug, Default)]
+pub(crate) struct FileScan {
+    pub(crate) report: ValidationReport,
+    pub(crate) header: raw::Header,
+    /// Offset of the COPC info VLR data and its content
+    pub(crate) copc_info: Option<(u64, CopcInfo)>,
+    /// Whether the reserved fields of the COPC info VLR are not 0
+    pub(crate) copc_info_reserved: bool,
+    pub(crate) hierarchy_evlr: Option<HierarchyEvlr>,
+    /// The entries of all hierarchy pages, without the entries pointing to child pages
+    pub(crate) entries: HashMap<VoxelKey, Entry>,
+    /// Statistics of the decompressed points
+    pub(crate) points: PointStats,
+}
+
+/// Location of the EPT hierarchy EVLR
+// only the repair of the writer uses the scan beyond the report
+#[cfg_attr(not(feature = "writer"), allow(dead_code))]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct HierarchyEvlr {
+    pub(crate) offset: u64,
+    pub(crate) data_offset: u64,
+    pub(crate) data_len: u64,
+    /// Whether no data follows the EVLR
+    pub(crate) is_last: bool,
+}
+
+// only the repair of the writer uses the scan beyond the report
+#[cfg_attr(not(feature = "writer"), allow(dead_code))]
+#[derive(Debug, Default)]
+pub(crate) struct PointStats {
+    pub(crate) by_return: [u64; 15],
+    pub(crate) bounds: Bounds,
+    pub(crate) gps_time: Option<(f64, f64)>,
+}
+
+/// Validates the file and collects what is needed to repair it
+///
+/// `root_page` overrides the offset and size of the root hierarchy page of the COPC info VLR
+pub(crate) fn scan<R: Read + Seek>(
+    mut read: R,
+    root_page: Option<(u64, u64)>,
+) -> crate::Result<FileScan> {
+    use ValidationCheck::*;
+
+    let mut report = ValidationReport::default();
+    let start = read.stream_position()?;
+    let file_size = read.seek(SeekFrom::End(0))? - start;
+    read.seek(SeekFrom::Start(start))?;
+
+    let raw_header = raw::Header::read_from(&mut read)?;
+    let mut scan = FileScan {
+        header: raw_header.clone(),
+        ..Default::default()
+    };
+
+    // header
+    if raw_header.version != las::Version::new(1, 4) {
+        report.error(
+            Header,
+            None,
+            format!("the LAS version is {}, not 1.4", raw_header.version),
+        );
+    }
+    if raw_header.header_size as u64 != HEADER_SIZE {
+        report.error(
+            Header,
+            None,
+            format!(
+                "the header size is {}, not {HEADER_SIZE}",
+                raw_header.header_size
+            ),
+        );
+    }
+    let pdrf = raw_header.point_data_record_format & 0b00111111;
+    if !(6..=8).contains(&pdrf) {
+        report.error(
+            Header,
+            None,
+            format!("the point data record format is {pdrf}, not 6, 7 or 8"),
+        );
+    }
+    if raw_header.global_encoding & 0b10000 == 0 {
+        report.error(
+            Header,
+            None,
+            "the WKT bit of the global encoding is not set".to_string(),
+        );
+    }
+    let number_of_points = raw_header
+        .large_file
+        .map(|l| l.number_of_point_records)
+        .unwrap_or(raw_header.number_of_point_records as u64);
+    let transforms = Vector {
+        x: las::Transform {
+            scale: raw_header.x_scale_factor,
+            offset: raw_header.x_offset,
+        },
+        y: las::Transform {
+            scale: raw_header.y_scale_factor,
+            offset: raw_header.y_offset,
+        },
+        z: las::Transform {
+            scale: raw_header.z_scale_factor,
+            offset: raw_header.z_offset,
+        },
+    };
+    let header_bounds = Bounds {
+        min: Vector {
+            x: raw_header.min_x,
+            y: raw_header.min_y,
+            z: raw_header.min_z,
+        },
+        max: Vector {
+            x: raw_header.max_x,
+            y: raw_header.max_y,
+            z: raw_header.max_z,
+        },
+    };
+    let offset_to_point_data = raw_header.offset_to_point_data as u64;
+    let point_data_end = raw_header
+        .evlr
+        .map(|e| e.start_of_first_evlr)
+        .unwrap_or(file_size);
+
+    // vlrs with the offset of their data
+    read.seek(SeekFrom::Start(start + raw_header.header_size as u64))?;
+    let mut position = raw_header.header_size as u64;
+    let mut vlrs = Vec::new();
+    for _ in 0..raw_header.number_of_variable_length_records {
+        let vlr = raw::Vlr::read_from(&mut read, false)?;
+        vlrs.push((position + VLR_HEADER_SIZE, las::Vlr::new(vlr)));
+        position += VLR_HEADER_SIZE + vlrs.last().map_or(0, |(_, v)| v.data.len() as u64);
+    }
+    if position > offset_to_point_data {
+        report.error(
+            Vlrs,
+            None,
+            format!("the vlrs end at {position}, after the offset to the point data {offset_to_point_data}"),
+        );
+    }
+    let mut evlrs = Vec::new();
+    if let Some(evlr) = raw_header.evlr {
+        read.seek(SeekFrom::Start(start + evlr.start_of_first_evlr))?;
+        let mut position = evlr.start_of_first_evlr;
+        for _ in 0..evlr.number_of_evlrs {
+            let vlr = raw::Vlr::read_from(&mut read, true)?;
+            evlrs.push((position + EVLR_HEADER_SIZE, las::Vlr::new(vlr)));
+            position += EVLR_HEADER_SIZE + evlrs.last().map_or(0, |(_, v)| v.data.len() as u64);
+        }
+        if position > file_size {
+            report.error(
+                Vlrs,
+                None,
+                format!("the evlrs end at {position}, after the end of the file at {file_size}"),
+            );
+        }
+    }
+
+    let find = |vlrs: &[(u64, las::Vlr)], user_id: &str, record_id: u16| {
+        vlrs.iter()
+            .position(|(_, v)| v.user_id.eq_ignore_ascii_case(user_id) && v.record_id == record_id)
+    };
+
+    // copc info vlr
+    let Some(copc_info_index) = find(&vlrs, "copc", 1) else {
+        report.error(Vlrs, None, "the COPC info VLR is missing".to_string());
+        scan.report = report;
+        return Ok(scan);
+    };
+    if copc_info_index != 0 || vlrs[0].0 != HEADER_SIZE + VLR_HEADER_SIZE {
+        report.error(
+            Vlrs,
+            None,
+            "the COPC info VLR is not the first VLR directly following the header".to_string(),
+        );
+    }
+    let copc_info_data = &vlrs[copc_info_index].1.data;
+    if copc_info_data.len() != 160 {
+        report.error(
+            CopcInfo,
+            None,
+            format!(
+                "the COPC info VLR has {} bytes, not 160",
+                copc_info_data.len()
+            ),
+        );
+    }
+    let Ok(copc_info) = crate::copc::CopcInfo::read_from(copc_info_data.as_slice()) else {
+        // the size was reported above
+        scan.report = report;
+        return Ok(scan);
+    };
+    scan.copc_info = Some((vlrs[copc_info_index].0, copc_info.clone()));
+    let mut reserved = Cursor::new(copc_info_data.get(72..).unwrap_or_default());
+    while let Ok(value) = reserved.read_u64::<LittleEndian>() {
+        if value != 0 {
+            report.warning(
+                CopcInfo,
+                None,
+                "the reserved fields of the COPC info VLR are not 0".to_string(),
+            );
+            scan.copc_info_reserved = true;
+            break;
+        }
+    }
+    check_copc_info(&mut report, &copc_info, &header_bounds, &transforms);
+
+    // laszip vlr
+    let laz_vlr = match find(&vlrs, "laszip encoded", 22204) {
+        Some(i) => match LazVlr::read_from(vlrs[i].1.data.as_slice()) {
+            Ok(laz_vlr) => Some(laz_vlr),
+            Err(e) => {
+                report.error(Vlrs, None, format!("the laszip VLR is invalid: {e}"));
+                None
+            }
+        },
+        None => {
+            report.error(Vlrs, None, "the laszip VLR is missing".to_string());
+            None
+        }
+    };
+
+    // crs
+    if find(&vlrs, "lasf_projection", 2112).is_none()
+        && find(&evlrs, "lasf_projection", 2112).is_none()
+    {
+        report.error(Vlrs, None, MISSING_WKT.to_string());
+    }
+
+    // hierarchy evlr
+    match find(&evlrs, "copc", 1000) {
+        Some(i) => {
+            let (data_offset, vlr) = &evlrs[i];
+            scan.hierarchy_evlr = Some(HierarchyEvlr {
+                offset: data_offset - EVLR_HEADER_SIZE,
+                data_offset: *data_offset,
+                data_len: vlr.data.len() as u64,
+                is_last: data_offset + vlr.data.len() as u64 == file_size,
+            });
+            if copc_info.root_hier_offset != *data_offset {
+                report.error(
+                    CopcInfo,
+                    None,
+                    format!(
+                        "the root hierarchy offset is {}, but the hierarchy EVLR data starts at {data_offset}",
+                        copc_info.root_hier_offset
+                    ),
+                );
+            }
+            if copc_info.root_hier_size > vlr.data.len() as u64 {
+                report.error(
+                    CopcInfo,
+                    None,
+                    format!(
+                        "the root hierarchy size {} is larger than the hierarchy EVLR of {} bytes",
+                        copc_info.root_hier_size,
+                        vlr.data.len()
+                    ),
+                );
+            }
+        }
+        None if find(&vlrs, "copc", 1000).is_some() => report.error(
+            Vlrs,
+            None,
+            "the EPT hierarchy is stored in a VLR instead of an EVLR".to_string(),
+        ),
+        None => report.error(Vlrs, None, "the EPT hierarchy EVLR is missing".to_string()),
+    }
+
+    // hierarchy pages, read from the file at their offsets
+    let mut entries: HashMap<VoxelKey, Entry> = HashMap::new();
+    let (root_offset, root_size) =
+        root_page.unwrap_or((copc_info.root_hier_offset, copc_info.root_hier_size));
+    let root_key = VoxelKey {
+        level: 0,
+        ..Default::default()
+    };
+    let mut pages = vec![(root_key, root_offset, root_size)];
+    let mut visited = HashSet::new();
+    while let Some((page_key, offset, size)) = pages.pop() {
+        if !visited.insert((offset, size)) {
+            report.error(
+                Hierarchy,
+                Some(&page_key),
+                format!("the hierarchy page at {offset} with {size} bytes is referenced more than once, the hierarchy has a cycle"),
+            );
+            continue;
+        }
+        if size % 32 != 0 {
+            report.error(
+                Hierarchy,
+                Some(&page_key),
+                format!("the hierarchy page size {size} is not a multiple of 32"),
+            );
+        }
+        if offset.saturating_add(size) > file_size {
+            report.error(
+                Hierarchy,
+                Some(&page_key),
+                format!("the hierarchy page at {offset} with {size} bytes ends after the end of the file"),
+            );
+            continue;
+        }
+        read.seek(SeekFrom::Start(start + offset))?;
+        for entry in HierarchyPage::read_from(&mut read, size)?.entries {
+            let key = entry.key.clone();
+            if entry.point_count == -1 {
+                pages.push((key, entry.offset, entry.byte_size.max(0) as u64));
+            } else if entries.insert(key.clone(), entry).is_some() {
+                report.error(
+                    Hierarchy,
+                    Some(&key),
+                    "the node has more than one hierarchy entry".to_string(),
+                );
+            }
+        }
+    }
+    check_hierarchy(&mut report, &entries, number_of_points);
+    check_chunks(&mut report, &entries, offset_to_point_data, point_data_end);
+
+    scan.entries = entries;
+
+    // points
+    let Some(laz_vlr) = laz_vlr else {
+        scan.report = report;
+        return Ok(scan);
+    };
+    let Ok(mut format) = las::point::Format::new(pdrf) else {
+        scan.report = report;
+        return Ok(scan);
+    };
+    format.extra_bytes = raw_header
+        .point_data_record_length
+        .saturating_sub(format.len());
+    let root_bounds = Bounds {
+        min: Vector {
+            x: copc_info.center.x - copc_info.halfsize,
+            y: copc_info.center.y - copc_info.halfsize,
+            z: copc_info.center.z - copc_info.halfsize,
+        },
+        max: Vector {
+            x: copc_info.center.x + copc_info.halfsize,
+            y: copc_info.center.y + copc_info.halfsize,
+            z: copc_info.center.z + copc_info.halfsize,
+        },
+    };
+
+    let mut nodes: Vec<&Entry> = scan
+        .entries
+        .values()
+        .filter(|e| {
+            e.point_count > 0
+                && e.byte_size > 0
+                && e.offset.saturating_add(e.byte_size as u64) <= file_size
+                && key_in_octree(&e.key)
+        })
+        .collect();
+    nodes.sort_by_key(|e| e.offset);
+
+    read.seek(SeekFrom::Start(start))?;
+    let mut decompressor = match CopcDecompressor::new(&mut read, &laz_vlr) {
+        Ok(decompressor) => decompressor,
+        Err(e) => {
+            report.error(
+                Vlrs,
+                None,
+                format!("the points can not be decompressed: {e}"),
+            );
+            scan.report = report;
+            return Ok(scan);
+        }
+    };
+    let mut buffer = vec![0u8; (format.len() + format.extra_bytes) as usize];
+    for entry in nodes {
+        let key = &entry.key;
+        let bounds = key.bounds(&root_bounds);
+        // points on the boundary may be rounded to just outside of it
+        let tolerance = Vector {
+            x: transforms.x.scale,
+            y: transforms.y.scale,
+            z: transforms.z.scale,
+        };
+        let mut outside = 0;
+        let mut outside_gps_time = 0;
+        let mut undecodable = 0;
+        let mut error = None;
+
+        if let Err(e) = decompressor.source_seek(start + entry.offset) {
+            error = Some(e);
+        }
+        for _ in 0..entry.point_count {
+            if error.is_some() {
+                break;
+            }
+            if let Err(e) = decompressor.decompress_one(&mut buffer) {
+                error = Some(e);
+                break;
+            }
+            let point = match raw::Point::read_from(buffer.as_slice(), &format) {
+                Ok(point) => las::Point::new(point, &transforms),
+                Err(_) => {
+                    undecodable += 1;
+                    continue;
+                }
+            };
+            report.points_checked += 1;
+            let stats = &mut scan.points;
+            if (1..=15).contains(&point.return_number) {
+                stats.by_return[point.return_number as usize - 1] += 1;
+            }
+            stats.bounds.grow(&point);
+            if point.x < bounds.min.x - tolerance.x
+                || point.y < bounds.min.y - tolerance.y
+                || point.z < bounds.min.z - tolerance.z
+                || point.x > bounds.max.x + tolerance.x
+                || point.y > bounds.max.y + tolerance.y
+                || point.z > bounds.max.z + tolerance.z
+            {
+                outside += 1;
+            }
+            if let Some(gps_time) = point.gps_time {
+                stats.gps_time = Some(match stats.gps_time {
+                    Some((min, max)) => (min.min(gps_time), max.max(gps_time)),
+                    None => (gps_time, gps_time),
+                });
+                if gps_time < copc_info.gpstime_minimum || gps_time > copc_info.gpstime_maximum {
+                    outside_gps_time += 1;
+                }
+            }
+        }
+        report.nodes_checked += 1;
+
+        if let Some(e) = error {
+            report.error(
+                PointCount,
+                Some(key),
+                format!(
+                    "the {} points of the node can not be decompressed: {e}",
+                    entry.point_count
+                ),
+            );
+        }
+        if undecodable > 0 {
+            report.error(
+                PointCount,
+                Some(key),
+                format!("{undecodable} points of the node can not be decoded"),
+            );
+        }
+        if outside > 0 {
+            report.error(
+                PointBounds,
+                Some(key),
+                format!("{outside} points are outside of the node bounds"),
+            );
+        }
+        if outside_gps_time > 0 {
+            report.error(
+                GpsTime,
+                Some(key),
+                format!(
+                    "{outside_gps_time} points have a gps time outside of the range {} to {}",
+                    copc_info.gpstime_minimum, copc_info.gpstime_maximum
+                ),
+            );
+        }
+    }
+
+    scan.report = report;
+    Ok(scan)
+}
+
+fn check_copc_info(
+    report: &mut ValidationReport,
+    copc_info: &CopcInfo,
+    header_bounds: &Bounds,
+    transforms: &Vector<las::Transform>,
+) {
+    use ValidationCheck::CopcInfo;
+
+    if !copc_info.halfsize.is_normal() || copc_info.halfsize < 0. {
+        report.error(
+            CopcInfo,
+            None,
+            format!("the halfsize {} is not positive", copc_info.halfsize),
+        );
+    }
+    if copc_info.spacing.is_nan() || copc_info.spacing <= 0. {
+        report.warning(
+            CopcInfo,
+            None,
+            format!("the spacing {} is not positive", copc_info.spacing),
+        );
+    }
+    if copc_info.gpstime_minimum > copc_info.gpstime_maximum {
+        report.warning(
+            CopcInfo,
+            None,
+            format!(
+                "the gps time minimum {} is larger than the maximum {}",
+                copc_info.gpstime_minimum, copc_info.gpstime_maximum
+            ),
+        );
+    }
+    let c = &copc_info.center;
+    let h = copc_info.halfsize;
+    // the cube may be rounded to just inside of bounds on the grid of the transforms
+    let t = transforms;
+    if header_bounds.min.x < c.x - h - t.x.scale
+        || header_bounds.min.y < c.y - h - t.y.scale
+        || header_bounds.min.z < c.z - h - t.z.scale
+        || header_bounds.max.x > c.x + h + t.x.scale
+        || header_bounds.max.y > c.y + h + t.y.scale
+        || header_bounds.max.z > c.z + h + t.z.scale
+    {
+        report.warning(
+            CopcInfo,
+            None,
+            "the octree cube does not contain the header bounds".to_string(),
+        );
+    }
+}
+
+fn check_hierarchy(
+    report: &mut ValidationReport,
+    entries: &HashMap<VoxelKey, Entry>,
+    number_of_points: u64,
+) {
+    use ValidationCheck::{Hierarchy, PointCount};
+
+    let mut keys: Vec<&VoxelKey> = entries.keys().collect();
+    keys.sort();
+    let mut total = 0;
+    for key in keys {
+        let entry = &entries[key];
+        if !key_in_octree(key) {
+            report.error(
+                Hierarchy,
+                Some(key),
+                "the key is outside of the octree".to_string(),
+            );
+            continue;
+        }
+        if key.level > 0 {
+            let parent = VoxelKey {
+                level: key.level - 1,
+                x: key.x >> 1,
+                y: key.y >> 1,
+                z: key.z >> 1,
+            };
+            if !entries.contains_key(&parent) {
+                report.error(
+                    Hierarchy,
+                    Some(key),
+                    "the parent of the node has no hierarchy entry".to_string(),
+                );
+            }
+        }
+        match entry.point_count {
+            count if count < -1 => report.error(
+                Hierarchy,
+                Some(key),
+                format!("the point count {count} is invalid"),
+            ),
+            0 if entry.offset != 0 || entry.byte_size != 0 => report.warning(
+                Hierarchy,
+                Some(key),
+                "the node has no points but a non-zero offset or byte size".to_string(),
+            ),
+            count => total += count.max(0) as u64,
+        }
+    }
+    if total != number_of_points {
+        report.error(
+            PointCount,
+            None,
+            format!("the nodes hold {total} points, but the header has {number_of_points}"),
+        );
+    }
+}
+
+fn check_chunks(
+    report: &mut ValidationReport,
+    entries: &HashMap<VoxelKey, Entry>,
+    offset_to_point_data: u64,
+    point_data_end: u64,
+) {
+    use ValidationCheck::Chunks;
+
+    let mut chunks: Vec<&Entry> = entries.values().filter(|e| e.point_count > 0).collect();
+    chunks.sort_by_key(|e| (e.offset, e.key.clone()));
+    let mut previous: Option<&Entry> = None;
+    for entry in chunks {
+        let key = &entry.key;
+        if entry.byte_size <= 0 {
+            report.error(
+                Chunks,
+                Some(key),
+                format!("the chunk size {} is not positive", entry.byte_size),
+            );
+            continue;
+        }
+        let end = entry.offset.saturating_add(entry.byte_size as u64);
+        if entry.offset < offset_to_point_data || end > point_data_end {
+            report.error(
+                Chunks,
+                Some(key),
+                format!(
+                    "the chunk from {} to {end} is outside of the point data from {offset_to_point_data} to {point_data_end}",
+                    entry.offset
+                ),
+            );
+        }
+        if let Some(previous) = previous {
+            if previous.offset.saturating_add(previous.byte_size as u64) > entry.offset {
+                report.error(
+                    Chunks,
+                    Some(key),
+                    format!("the chunk overlaps the chunk of node {:?}", previous.key),
+                );
+            }
+        }
+        previous = Some(entry);
+    }
+}
+
+/// Whether the level and the coordinates of `key` are valid
+pub(crate) fn key_in_octree(key: &VoxelKey) -> bool {
+    let cells = 1_i64 << key.level.clamp(0, 62);
+    key.level >= 0
+        && [key.x, key.y, key.z]
+            .iter()
+            .all(|c| *c >= 0 && (*c as i64) < cells)
+}
@@ -1,9 +1,13 @@
 //! COPC file writer.
 
+use crate::builder::{CopcWriterBuilder, CrsOverride, GpsTimePolicy, SamplingStrategy};
 use crate::compressor::CopcCompressor;
 use crate::copc::{CopcInfo, Entry, HierarchyPage, OctreeNode, VoxelKey};
+use crate::crs;
+use crate::extra_bytes::{ExtraBytes, ExtraBytesDescriptor, ExtraBytesType};
+use crate::statistics::{StatisticsAccumulator, STATISTICS_RECORD_ID, STATISTICS_USER_ID};
 
-use las::{Builder, Header};
+use las::{Bounds, Builder, Header, Transform, Vector};
 
 use std::collections::HashMap;
 use std::fs::File;
@@ -12,21 +16,87 @@ use std::path::Path;
 
 // enum for point data record format upgrades
 enum UpgradePdrf {
+    From0to6,  // upgrades (0=>6), synthesizes gps time
     From1to6,  // upgrades (1=>6)
+    From2to7,  // upgrades (2=>7), synthesizes gps time
     From3to7,  // upgrades (3=>7)
+    From4to6,  // upgrades (4=>6), drops waveform packets
+    From5to7,  // upgrades (5=>7), drops waveform packets
+    From9to6,  // converts (9=>6), drops waveform packets
+    From10to8, // converts (10=>8), drops waveform packets
     NoUpgrade, // 6, 7 and 8
 }
 
 impl UpgradePdrf {
+    fn from_pdrf(pdrf: u8) -> Option<Self> {
+        match pdrf {
+            0 => Some(UpgradePdrf::From0to6),
+            1 => Some(UpgradePdrf::From1to6),
+            2 => Some(UpgradePdrf::From2to7),
+            3 => Some(UpgradePdrf::From3to7),
+            4 => Some(UpgradePdrf::From4to6),
+            5 => Some(UpgradePdrf::From5to7),
+            6..=8 => Some(UpgradePdrf::NoUpgrade),
+            9 => Some(UpgradePdrf::From9to6),
+            10 => Some(UpgradePdrf::From10to8),
+            _ => None,
+        }
+    }
+
+    fn target_pdrf(&self, pdrf: u8) -> u8 {
+        match self {
+            UpgradePdrf::From0to6 | UpgradePdrf::From1to6 => 6,
+            UpgradePdrf::From2to7 | UpgradePdrf::From3to7 => 7,
+            UpgradePdrf::From4to6 | UpgradePdrf::From9to6 => 6,
+            UpgradePdrf::From5to7 => 7,
+            UpgradePdrf::From10to8 => 8,
+            UpgradePdrf::NoUpgrade => pdrf,
+        }
+    }
+
+    fn drops_waveform(&self) -> bool {
+        matches!(
+            self,
+            UpgradePdrf::From4to6
+                | UpgradePdrf::From5to7
+                | UpgradePdrf::From9to6
+                | UpgradePdrf::From10to8
+        )
+    }
+
     fn log_string(&self) -> &str {
         match self {
+            UpgradePdrf::From0to6 => "Upgrading LAS PDRF from 0 to 6, synthesizing GPS time",
             UpgradePdrf::From1to6 => "Upgrading LAS PDRF from 1 to 6",
+            UpgradePdrf::From2to7 => "Upgrading LAS PDRF from 2 to 7, synthesizing GPS time",
             UpgradePdrf::From3to7 => "Upgrading LAS PDRF from 3 to 7",
+            UpgradePdrf::From4to6 => "Upgrading LAS PDRF from 4 to 6",
+            UpgradePdrf::From5to7 => "Upgrading LAS PDRF from 5 to 7",
+            UpgradePdrf::From9to6 => "Converting LAS PDRF from 9 to 6",
+            UpgradePdrf::From10to8 => "Converting LAS PDRF from 10 to 8",
             UpgradePdrf::NoUpgrade => "COPC supports the given PDRF",
         }
     }
 }
 
+/// Number of points rejected by a [CopcWriter], by reason
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RejectedPoints {
+    /// See [crate::PointAddError::PointAttributesDoNotMatch]
+    pub attributes_do_not_match: u64,
+    /// See [crate::PointAddError::PointNotInBounds]
+    pub not_in_bounds: u64,
+    /// See [crate::PointAddError::MissingGpsTime]
+    pub missing_gps_time: u64,
+}
+
+impl RejectedPoints {
+    /// Total number of rejected points
+    pub fn total(&self) -> u64 {
+        self.attributes_do_not_match + self.not_in_bounds + self.missing_gps_time
+    }
+}
+
 /// COPC file writer
 pub struct CopcWriter<'a, W: 'a + Write + Seek> {
     is_closed: bool,
@@ -38,11 +108,31 @@ pub struct CopcWriter<'a, W: 'a + Write + Seek> {
     hierarchy: HierarchyPage,
     min_node_size: i32,
     max_node_size: i32,
+    // deepest level points are added to
+    max_depth: i32,
+    sampling: SamplingStrategy,
+    // random number generator for the stochastic sampling
+    rng: fastrand::Rng,
+    // max number of entries in a hierarchy page
+    hierarchy_page_size: Option<usize>,
+    // precision for computing bounds, scales and offsets from the written points
+    auto_bounds: Option<f64>,
+    // how to handle points without gps time
+    gps_time_policy: GpsTimePolicy,
+    // the points are converted from a PDRF with waveform packets
+    drop_waveform: bool,
+    // index of the next point passed to write
+    point_index: u64,
+    rejected_points: RejectedPoints,
     copc_info: CopcInfo,
+    // spacing of the root node, computed from its number of points if not given
+    spacing: Option<f64>,
     // root node in octree, access point for the tree
     root_node: OctreeNode,
     // a hashmap to store chunks that are not full yet
     open_chunks: HashMap<VoxelKey, Cursor<Vec<u8>>>,
+    // statistics of the written points, stored in an evlr on close
+    statistics: Option<StatisticsAccumulator>,
 }
 
 impl CopcWriter<'_, BufWriter<File>> {
@@ -59,27 +149,7 @@ impl CopcWriter<'_, BufWriter<File>> {
         min_size: i32,
         max_size: i32,
     ) -> crate::Result<Self> {
-        let copc_ext = Path::new(match path.as_ref().file_stem() {
-            Some(copc) => copc,
-            None => return Err(crate::Error::WrongCopcExtension),
-        })
-        .extension();
-
-        match (copc_ext, path.as_ref().extension()) {
-            (Some(copc), Some(laz)) => match (&copc.to_str(), &laz.to_str()) {
-                (Some(copc_str), Some(laz_str)) => {
-                    if &copc_str.to_lowercase() != "copc" || &laz_str.to_lowercase() != "laz" {
-                        return Err(crate::Error::WrongCopcExtension);
-                    }
-                }
-                _ => return Err(crate::Error::WrongCopcExtension),
-            },
-            _ => return Err(crate::Error::WrongCopcExtension),
-        }
-
-        File::create(path)
-            .map_err(crate::Error::from)
-            .and_then(|file| CopcWriter::new(BufWriter::new(file), header, min_size, max_size))
+        builder_with_sizes(header, min_size, max_size).build_path(path)
     }
 }
 
@@ -88,6 +158,7 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
     /// Create a COPC file writer for the write- and seekable `write`
     /// configured with the provided [las::Header]
     /// recommended to use [from_path] for writing to file
+    /// use [crate::CopcWriterBuilder] for more configuration options
     ///
     /// The `bounds` field in the `header` is used as the bounds for the octree
     /// the bounds are checked for being normal
@@ -101,47 +172,73 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
     /// this is a hard limit
     ///
     /// `min_size` greater or equal to `max_size` after checking values < 1
-    /// results in a [crate::Error::InvalidNodeSize] error
+    /// results in a [crate::WriterConfigError::InvalidMaxNodeSize] error
     ///
     ///
     /// This writer is strictly following the LAS 1.4 spec and the COPC spec
     /// which means that any provided header not compatible with those will lead
     /// to an Err
-    /// That being said, LAS 1.2 headers and PDRFs 1 and 3 are accepted and upgraded to
-    /// their matching LAS 1.4 versions
-    /// GeoTiff CRS VLR's are parsed and written to WKT CRS VLR's
-    /// A CRS VLR is __MANDATORY__ and without one
+    /// That being said, older LAS headers and all PDRFs are accepted and converted to
+    /// their matching LAS 1.4 versions: 0 and 1 to 6, 2 and 3 to 7, the waveform PDRFs
+    /// 4 and 9 to 6, 5 to 7 and 10 to 8, dropping the waveform packets.
+    /// GPS time is set according to [crate::GpsTimePolicy] for PDRFs 0 and 2
+    /// GeoTiff CRS VLR's are parsed and written to WKT CRS VLR's,
+    /// projected or geographic, with a vertical CRS as compound CRS
+    /// A CRS VLR is __MANDATORY__ and without one [crate::Error::NoCRSDefined] is returned,
+    /// unless a fallback is set with [crate::CopcWriterBuilder::fallback_crs]
     ///
     /// [from_path]: Self::from_path
-    pub fn new(mut write: W, header: Header, min_size: i32, max_size: i32) -> crate::Result<Self> {
-        let start = write.stream_position()?;
-
-        let min_node_size = if min_size < 1 {
-            crate::MIN_NODE_SIZE_DEFAULT
-        } else {
-            min_size
-        };
-
-        let max_node_size = if max_size < 1 {
-            crate::MAX_NODE_SIZE_DEFAULT
-        } else {
-            max_size
-        };
+    pub fn new(write: W, header: Header, min_size: i32, max_size: i32) -> crate::Result<Self> {
+        builder_with_sizes(header, min_size, max_size).build(write)
+    }
 
-        if min_node_size >= max_node_size {
-            return Err(crate::Error::InvalidNodeSize);
-        }
+    /// Create a COPC file writer from a validated [CopcWriterBuilder]
+    pub(crate) fn from_builder(mut write: W, config: CopcWriterBuilder) -> crate::Result<Self> {
+        let start = write.stream_position()?;
+        let header = config.header;
 
         if header.version() != las::Version::new(1, 4) {
             log::log!(log::Level::Info, "Old Las version. Upgrading");
         }
 
+        let crs_override = config.crs.as_ref().map(CrsOverride::wkt).transpose()?;
+
+        // the declared extra attributes follow the extra bytes of the header
+        let base_extra_bytes = config
+            .extra_bytes
+            .unwrap_or(header.point_format().extra_bytes);
+        let extra_bytes_vlr = if config.extra_attributes.is_empty() {
+            None
+        } else {
+            let mut descriptors = match config.extra_bytes {
+                Some(_) => vec![],
+                None => ExtraBytes::from_header(&header)?.descriptors().to_vec(),
+            };
+            // keep the layout of undescribed extra bytes of the header
+            let mut undescribed = (base_extra_bytes as usize)
+                .saturating_sub(ExtraBytes::new(descriptors.clone()).size());
+            while undescribed > 0 {
+                let size = undescribed.min(u8::MAX as usize);
+                descriptors.push(ExtraBytesDescriptor::new(
+                    "",
+                    ExtraBytesType::Undocumented(size as u8),
+                ));
+                undescribed -= size;
+            }
+            descriptors.extend(config.extra_attributes.iter().cloned());
+            Some(ExtraBytes::new(descriptors))
+        };
+        let replace_extra_bytes_vlr = config.extra_bytes.is_some() || extra_bytes_vlr.is_some();
+
         let mut has_wkt_vlr = false;
 
         // store the vlrs contained in the header for forwarding
         let mut forward_vlrs = Vec::with_capacity(header.vlrs().len());
         for vlr in header.vlrs() {
             match (vlr.user_id.to_lowercase().as_str(), vlr.record_id) {
+                // replaced by the overrides
+                ("lasf_projection", 2112) if crs_override.is_some() => (),
+                ("lasf_spec", 4) if replace_extra_bytes_vlr => (), // extra bytes descriptors
                 ("lasf_projection", 2112) => {
                     has_wkt_vlr = true;
                     forward_vlrs.push(vlr.clone());
@@ -151,6 +248,9 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
                 ("copc", 1 | 1000) => (),
                 ("laszip encoded", 22204) => (),
                 ("lasf_spec", 100..355 | 65535) => (), // wave form packet descriptors
+                // the statistics of the nodes of the source octree
+                (user_id, record_id)
+                    if user_id == STATISTICS_USER_ID && record_id == STATISTICS_RECORD_ID => {}
                 // forwarding all other vlrs
                 _ => forward_vlrs.push(vlr.clone()),
             }
@@ -160,6 +260,9 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
         let mut forward_evlrs = Vec::with_capacity(header.evlrs().len());
         for evlr in header.evlrs() {
             match (evlr.user_id.to_lowercase().as_str(), evlr.record_id) {
+                // replaced by the overrides
+                ("lasf_projection", 2112) if crs_override.is_some() => (),
+                ("lasf_spec", 4) if replace_extra_bytes_vlr => (), // extra bytes descriptors
                 ("lasf_projection", 2112) => {
                     has_wkt_vlr = true;
                     forward_evlrs.push(evlr.clone());
@@ -169,6 +272,9 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
                 ("copc", 1 | 1000) => (),                 // 1 should never be a evlr
                 ("laszip encoded", 22204) => (),          // should never be a evlr
                 ("lasf_spec", 100..355 | 65535) => (),    // waveform data packets
+                // the statistics of the nodes of the source octree
+                (user_id, record_id)
+                    if user_id == STATISTICS_USER_ID && record_id == STATISTICS_RECORD_ID => {}
                 // forwarding all other evlrs
                 _ => forward_evlrs.push(evlr.clone()),
             }
@@ -182,91 +288,96 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
         // could just use header.has_wkt_vlr(), but so many las files are wrongly written
         // so I don't trust it
         //
-        // ignores any vertical crs that might stored in geotiff
-        if !has_wkt_vlr {
-            let epsg = header
-                .get_geotiff_crs()
-                .ok()
-                .flatten()
-                .and_then(|g| g.get_projected_crs_geo_key_value())
-                .ok_or(crate::Error::InvalidEPSGCode(0))?;
-
-            let wkt_data = match crs_definitions::from_code(epsg) {
-                Some(wkt) => wkt,
-                None => return Err(crate::Error::InvalidEPSGCode(epsg)),
-            }
-            .wkt
-            .as_bytes()
-            .to_owned();
-
-            let mut user_id = [0; 16];
-            for (i, c) in "LASF_Projection".as_bytes().iter().enumerate() {
-                user_id[i] = *c;
-            }
-
-            let crs_vlr = las::raw::Vlr {
-                reserved: 0,
-                user_id,
-                record_id: 2112,
-                record_length_after_header: las::raw::vlr::RecordLength::Vlr(wkt_data.len() as u16),
-                description: [0; 32],
-                data: wkt_data,
+        // a vertical crs in the geotiff keys is added as a compound crs
+        if let Some(wkt_data) = crs_override {
+            forward_vlrs.push(wkt_crs_vlr(wkt_data));
+        } else if !has_wkt_vlr {
+            let wkt_data = match (crs::header_wkt(&header)?, &config.fallback_crs) {
+                (Some(wkt_data), _) => wkt_data,
+                (None, Some(fallback)) => fallback.wkt()?,
+                (None, None) => return Err(crate::Error::NoCRSDefined),
             };
+            forward_vlrs.push(wkt_crs_vlr(wkt_data));
+        }
 
-            forward_vlrs.push(las::Vlr::new(crs_vlr));
+        if let Some(extra_bytes) = &extra_bytes_vlr {
+            forward_vlrs.push(extra_bytes.to_vlr()?);
         }
 
         // check bounds are normal
-        let bounds = header.bounds();
-        if !(bounds.max.x - bounds.min.x).is_normal()
-            || !(bounds.max.y - bounds.min.y).is_normal()
-            || !(bounds.max.z - bounds.min.z).is_normal()
+        // with automatic bounds they are only known once the points are written
+        let bounds = config.bounds.unwrap_or_else(|| header.bounds());
+        if config.auto_bounds.is_none()
+            && (!(bounds.max.x - bounds.min.x).is_normal()
+                || !(bounds.max.y - bounds.min.y).is_normal()
+                || !(bounds.max.z - bounds.min.z).is_normal())
         {
             return Err(crate::Error::InvalidBounds(bounds));
         }
 
         let mut raw_head = header.into_raw()?;
 
+        if config.auto_bounds.is_some() {
+            // empty bounds, grown to the tight bounds when adding the points
+            raw_head.min_x = f64::INFINITY;
+            raw_head.min_y = f64::INFINITY;
+            raw_head.min_z = f64::INFINITY;
+            raw_head.max_x = f64::NEG_INFINITY;
+            raw_head.max_y = f64::NEG_INFINITY;
+            raw_head.max_z = f64::NEG_INFINITY;
+        } else if let Some(bounds) = config.bounds {
+            raw_head.min_x = bounds.min.x;
+            raw_head.min_y = bounds.min.y;
+            raw_head.min_z = bounds.min.z;
+            raw_head.max_x = bounds.max.x;
+            raw_head.max_y = bounds.max.y;
+            raw_head.max_z = bounds.max.z;
+        }
+
         // mask off the two leftmost bits corresponding to compression of pdrf
         let pdrf = raw_head.point_data_record_format & 0b00111111;
-        let upgrade_pdrf = match pdrf {
-            1 => {
-                let upgrade = UpgradePdrf::From1to6;
-
-                log::log!(log::Level::Info, "{}", upgrade.log_string());
-                upgrade
-            }
-            3 => {
-                let upgrade = UpgradePdrf::From3to7;
-
+        let upgrade_pdrf = match UpgradePdrf::from_pdrf(pdrf) {
+            Some(UpgradePdrf::NoUpgrade) => UpgradePdrf::NoUpgrade,
+            Some(upgrade) => {
                 log::log!(log::Level::Info, "{}", upgrade.log_string());
+                if upgrade.drops_waveform() {
+                    log::log!(
+                        log::Level::Warn,
+                        "Dropping the waveform packets of all points"
+                    );
+                }
                 upgrade
             }
-            0 | 2 => {
+            None => {
                 return Err(las::Error::InvalidPointFormat(las::point::Format::new(
                     raw_head.point_data_record_format,
                 )?))?;
             }
-            4..=5 | 9.. => {
-                return Err(las::Error::InvalidPointFormat(las::point::Format::new(
-                    raw_head.point_data_record_format,
-                )?))?;
-            }
-            6..=8 => UpgradePdrf::NoUpgrade,
         };
+        let extra_bytes = match config.extra_bytes {
+            Some(extra_bytes) => extra_bytes,
+            None => raw_head.point_data_record_length - las::point::Format::new(pdrf)?.len(),
+        } + config
+            .extra_attributes
+            .iter()
+            .map(|d| d.size() as u16)
+            .sum::<u16>();
 
         // adjust and clear some fields
         raw_head.version = las::Version::new(1, 4);
-        raw_head.point_data_record_format += match upgrade_pdrf {
-            UpgradePdrf::NoUpgrade => 0,
-            UpgradePdrf::From1to6 => 5,
-            UpgradePdrf::From3to7 => 4,
-        };
+        raw_head.point_data_record_format = upgrade_pdrf.target_pdrf(pdrf);
+        raw_head.point_data_record_length =
+            las::point::Format::new(raw_head.point_data_record_format)?.len() + extra_bytes;
         raw_head.point_data_record_format |= 0b11000000; // make sure the compress bits are set
-        raw_head.point_data_record_length += match upgrade_pdrf {
-            UpgradePdrf::NoUpgrade => 0,
-            _ => 2,
-        };
+        raw_head.global_encoding &= !0b110; // no waveform data packets
+        if let Some(transforms) = config.transforms {
+            raw_head.x_scale_factor = transforms.x.scale;
+            raw_head.y_scale_factor = transforms.y.scale;
+            raw_head.z_scale_factor = transforms.z.scale;
+            raw_head.x_offset = transforms.x.offset;
+            raw_head.y_offset = transforms.y.offset;
+            raw_head.z_offset = transforms.z.offset;
+        }
         raw_head.global_encoding |= 0b10000; // make sure wkt crs bit is set
         raw_head.number_of_point_records = 0;
         raw_head.number_of_points_by_return = [0; 5];
@@ -324,28 +435,23 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
         // this is just to reserve the space
         header.write_to(&mut write)?;
 
-        let center_point = las::Vector {
-            x: (bounds.min.x + bounds.max.x) / 2.,
-            y: (bounds.min.y + bounds.max.y) / 2.,
-            z: (bounds.min.z + bounds.max.z) / 2.,
-        };
-        let halfsize = (center_point.x - bounds.min.x)
-            .max((center_point.y - bounds.min.y).max(center_point.z - bounds.min.z));
+        let (center_point, halfsize, root_bounds) = octree_cube(&bounds);
+
+        let statistics = config
+            .statistics
+            .map(|options| {
+                StatisticsAccumulator::new(
+                    &options,
+                    header.point_format(),
+                    &ExtraBytes::from_header(&header)?,
+                    &root_bounds,
+                )
+            })
+            .transpose()?;
 
         let mut root_node = OctreeNode::new();
 
-        root_node.bounds = las::Bounds {
-            min: las::Vector {
-                x: center_point.x - halfsize,
-                y: center_point.y - halfsize,
-                z: center_point.z - halfsize,
-            },
-            max: las::Vector {
-                x: center_point.x + halfsize,
-                y: center_point.y + halfsize,
-                z: center_point.z + halfsize,
-            },
-        };
+        root_node.bounds = root_bounds;
         root_node.entry.key.level = 0;
         root_node.entry.offset = write.stream_position()?;
 
@@ -365,11 +471,25 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
             compressor: CopcCompressor::new(write, header.laz_vlr()?)?,
             header,
             hierarchy: HierarchyPage { entries: vec![] },
-            min_node_size,
-            max_node_size,
+            min_node_size: config.min_node_size,
+            max_node_size: config.max_node_size,
+            max_depth: config.max_depth.unwrap_or(i32::MAX),
+            sampling: config.sampling,
+            rng: config
+                .seed
+                .map(fastrand::Rng::with_seed)
+                .unwrap_or_default(),
+            hierarchy_page_size: config.hierarchy_page_size,
+            auto_bounds: config.auto_bounds,
+            gps_time_policy: config.gps_time_policy,
+            drop_waveform: upgrade_pdrf.drops_waveform(),
+            point_index: 0,
+            rejected_points: RejectedPoints::default(),
             copc_info,
+            spacing: None,
             root_node,
             open_chunks: HashMap::default(),
+            statistics,
         })
     }
 
@@ -393,7 +513,8 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
     /// If a point is outside the copc `bounds` or not matching the
     /// [las::point::Format] of the writer's header `Err` is returned
     /// [crate::PointAddError::PointAttributesDoNotMatch] take precedence over
-    /// [crate::PointAddError::PointNotInBounds]
+    /// [crate::PointAddError::PointNotInBounds] and [crate::PointAddError::MissingGpsTime]
+    /// Points without gps time are handled according to [crate::GpsTimePolicy]
     /// All the points inside the bounds and matching the point format are written regardless
     ///
     /// All points which both match the point format and are inside the bounds are added
@@ -402,6 +523,9 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
     /// the state of the [Write] is undefined
     ///
     /// If all points match the format, are inside the bounds and [Self::close] is successfull `Ok(())` is returned
+    ///
+    /// With [crate::CopcWriterBuilder::auto_bounds] all points are first collected in memory
+    /// to compute the bounds, scales and offsets, `num_points` is then ignored
     pub fn write<D: IntoIterator<Item = las::Point>>(
         &mut self,
         data: D,
@@ -411,16 +535,19 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
             return Err(crate::Error::ClosedWriter);
         }
 
-        let result = if num_points < self.max_node_size + self.min_node_size {
-            // greedy filling strategy
-            self.write_greedy(data)
-        } else {
-            // stochastic filling strategy
-            self.write_stochastic(data, num_points as usize)
-        };
+        if let Some(precision) = self.auto_bounds.take() {
+            let points: Vec<las::Point> = data.into_iter().collect();
+            let Some(bounds) = points_bounds(&points) else {
+                // nothing to write, errors with EmptyCopcFile
+                return self.close();
+            };
+            self.set_transforms_and_bounds(&bounds, precision)?;
 
-        self.close()?;
-        result
+            let num_points = points.len().min(i32::MAX as usize) as i32;
+            return self.write_points(points, num_points);
+        }
+
+        self.write_points(data, num_points)
     }
 
     /// Whether this writer is closed or not
@@ -438,6 +565,11 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
         self.min_node_size
     }
 
+    /// deepest octree level points are added to
+    pub fn max_depth(&self) -> i32 {
+        self.max_depth
+    }
+
     /// This writer's header, some fields are updated on closing of the writer
     pub fn header(&self) -> &Header {
         &self.header
@@ -452,29 +584,56 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
     pub fn copc_info(&self) -> &CopcInfo {
         &self.copc_info
     }
+
+    /// The number of points passed to [Self::write] that were not written, by reason
+    pub fn rejected_points(&self) -> &RejectedPoints {
+        &self.rejected_points
+    }
 }
 
 /// private functions
 impl<W: Write + Seek> CopcWriter<'_, W> {
+    /// Writes the points with the configured filling strategy and closes the writer
+    fn write_points<D: IntoIterator<Item = las::Point>>(
+        &mut self,
+        data: D,
+        num_points: i32,
+    ) -> crate::Result<()> {
+        let stochastic = match self.sampling {
+            SamplingStrategy::Auto => num_points >= self.max_node_size + self.min_node_size,
+            SamplingStrategy::Greedy => false,
+            SamplingStrategy::Stochastic => num_points > 0,
+        };
+
+        let result = if stochastic {
+            // stochastic filling strategy
+            self.write_stochastic(data, num_points as usize)
+        } else {
+            // greedy filling strategy
+            self.write_greedy(data)
+        };
+
+        self.close()?;
+        result
+    }
+
     /// Greedy strategy for writing points
     fn write_greedy<D: IntoIterator<Item = las::Point>>(&mut self, data: D) -> crate::Result<()> {
         let mut invalid_points = Ok(());
 
         for p in data.into_iter() {
-            if !p.matches(self.header.point_format()) {
-                invalid_points = Err(crate::Error::InvalidPoint(
-                    crate::PointAddError::PointAttributesDoNotMatch(*self.header.point_format()),
-                ));
-                continue;
-            }
-            if !bounds_contains_point(&self.root_node.bounds, &p) {
-                if invalid_points.is_ok() {
-                    invalid_points = Err(crate::Error::InvalidPoint(
-                        crate::PointAddError::PointNotInBounds,
-                    ));
+            let p = match self.validate_point(p) {
+                Ok(p) => p,
+                Err(e) => {
+                    // a mismatching point format takes precedence
+                    if invalid_points.is_ok()
+                        || matches!(e, crate::PointAddError::PointAttributesDoNotMatch(_))
+                    {
+                        invalid_points = Err(crate::Error::InvalidPoint(e));
+                    }
+                    continue;
                 }
-                continue;
-            }
+            };
 
             self.add_point_greedy(p)?;
         }
@@ -505,22 +664,21 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
         let expected_levels =
             ((((3 * num_points) as f64 / self.max_node_size as f64 + 1.).log2() - 2.) / 2.).ceil()
                 as usize;
+        let expected_levels = expected_levels.min(self.max_depth as usize);
 
         for (i, p) in data.into_iter().enumerate() {
-            if !p.matches(self.header.point_format()) {
-                invalid_points = Err(crate::Error::InvalidPoint(
-                    crate::PointAddError::PointAttributesDoNotMatch(*self.header.point_format()),
-                ));
-                continue;
-            }
-            if !bounds_contains_point(&self.root_node.bounds, &p) {
-                if invalid_points.is_ok() {
-                    invalid_points = Err(crate::Error::InvalidPoint(
-                        crate::PointAddError::PointNotInBounds,
-                    ));
+            let p = match self.validate_point(p) {
+                Ok(p) => p,
+                Err(e) => {
+                    // a mismatching point format takes precedence
+                    if invalid_points.is_ok()
+                        || matches!(e, crate::PointAddError::PointAttributesDoNotMatch(_))
+                    {
+                        invalid_points = Err(crate::Error::InvalidPoint(e));
+                    }
+                    continue;
                 }
-                continue;
-            }
+            };
 
             // if the given num_points was smaller than the actual number of points
             // and we have passed that number revert to the greedy strategy
@@ -533,8 +691,162 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
         invalid_points
     }
 
+    /// Converts the point and checks that it can be added to the octree
+    ///
+    /// Rejected points are counted by reason
+    fn validate_point(&mut self, p: las::Point) -> Result<las::Point, crate::PointAddError> {
+        let (p, synthesized_gps_time) = match self.convert_point(p) {
+            Ok(converted) => converted,
+            Err(e) => {
+                self.rejected_points.missing_gps_time += 1;
+                return Err(e);
+            }
+        };
+        if !p.matches(self.header.point_format()) {
+            self.rejected_points.attributes_do_not_match += 1;
+            return Err(crate::PointAddError::PointAttributesDoNotMatch(
+                *self.header.point_format(),
+            ));
+        }
+        if !bounds_contains_point(&self.root_node.bounds, &p) {
+            self.rejected_points.not_in_bounds += 1;
+            return Err(crate::PointAddError::PointNotInBounds);
+        }
+        if !synthesized_gps_time {
+            self.update_gps_time_range(&p);
+        }
+        Ok(p)
+    }
+
+    /// Converts a point of the header's PDRF to the PDRF of the writer
+    ///
+    /// Returns the point and whether its gps time was synthesized
+    fn convert_point(
+        &mut self,
+        mut point: las::Point,
+    ) -> Result<(las::Point, bool), crate::PointAddError> {
+        let index = self.point_index;
+        self.point_index += 1;
+
+        if self.drop_waveform {
+            point.waveform = None;
+        }
+        if point.gps_time.is_some() || !self.header.point_format().has_gps_time {
+            return Ok((point, false));
+        }
+        point.gps_time = match self.gps_time_policy {
+            // the index keeps the acquisition order of the points
+            GpsTimePolicy::FromPointIndex => Some(index as f64),
+            GpsTimePolicy::Constant(gps_time) => Some(gps_time),
+            GpsTimePolicy::Error => return Err(crate::PointAddError::MissingGpsTime),
+        };
+        Ok((point, true))
+    }
+
+    /// Grows the gps time range of the copc info to include the point's gps time
+    fn update_gps_time_range(&mut self, point: &las::Point) {
+        if let Some(gps_time) = point.gps_time {
+            self.copc_info.gpstime_minimum = self.copc_info.gpstime_minimum.min(gps_time);
+            self.copc_info.gpstime_maximum = self.copc_info.gpstime_maximum.max(gps_time);
+        }
+    }
+
+    /// Replaces the header transforms with ones suitable for `bounds` and `precision`
+    /// and sets up the octree for `bounds`
+    fn set_transforms_and_bounds(&mut self, bounds: &Bounds, precision: f64) -> crate::Result<()> {
+        let transforms = transforms_for_bounds(bounds, precision);
+
+        let mut builder = Builder::new(self.header.clone().into_raw()?)?;
+        builder.transforms = transforms;
+        builder.vlrs = self.header.vlrs().clone();
+        builder.evlrs = self.header.evlrs().clone();
+        self.header = builder.into_header()?;
+
+        // snap the bounds to the grid of the transforms
+        let bounds = pad_flat_bounds(bounds.adapt(&transforms)?, &transforms);
+        let (center, halfsize, root_bounds) = octree_cube(&bounds);
+        self.root_node.bounds = root_bounds;
+        self.copc_info.center = center;
+        self.copc_info.halfsize = halfsize;
+        if let Some(statistics) = self.statistics.take() {
+            self.statistics = Some(StatisticsAccumulator::new(
+                &statistics.options,
+                self.header.point_format(),
+                &ExtraBytes::from_header(&self.header)?,
+                &root_bounds,
+            )?);
+        }
+        Ok(())
+    }
+
+    /// Writes the points of the node `key` of an existing octree, bypassing the octree of the writer
+    ///
+    /// `chunk` are the points already compressed with [Self::laz_vlr]
+    /// and in the PDRF and transforms of the writer
+    pub(crate) fn write_node(
+        &mut self,
+        key: VoxelKey,
+        points: Vec<las::Point>,
+        chunk: Option<&[u8]>,
+    ) -> crate::Result<()> {
+        if self.is_closed {
+            return Err(crate::Error::ClosedWriter);
+        }
+        let point_count = points.len() as u64;
+        let mut buffer = Vec::new();
+        for p in points {
+            let p = self.validate_point(p).map_err(crate::Error::InvalidPoint)?;
+            self.header.add_point(&p);
+            if let Some(statistics) = &mut self.statistics {
+                statistics.add(&key, &p);
+            }
+            if chunk.is_none() {
+                p.into_raw(self.header.transforms())?
+                    .write_to(&mut buffer, self.header.point_format())?;
+            }
+        }
+        let (chunk_table_entry, chunk_offset) = match chunk {
+            Some(chunk) => self.compressor.copy_chunk(chunk, point_count)?,
+            None => self.compressor.compress_chunk(buffer)?,
+        };
+        if key.level == 0 {
+            self.root_node.entry.point_count = chunk_table_entry.point_count as i32;
+        }
+        self.hierarchy.entries.push(Entry {
+            key,
+            offset: chunk_offset,
+            byte_size: chunk_table_entry.byte_count as i32,
+            point_count: chunk_table_entry.point_count as i32,
+        });
+        Ok(())
+    }
+
+    /// Adds the hierarchy entry of the node `key` of an existing octree that has no points
+    pub(crate) fn write_empty_node(&mut self, key: VoxelKey) -> crate::Result<()> {
+        if self.is_closed {
+            return Err(crate::Error::ClosedWriter);
+        }
+        self.hierarchy.entries.push(Entry {
+            key,
+            offset: 0,
+            byte_size: 0,
+            point_count: 0,
+        });
+        Ok(())
+    }
+
+    /// The laszip vlr the chunks are compressed with
+    pub(crate) fn laz_vlr(&self) -> &laz::LazVlr {
+        self.compressor.vlr()
+    }
+
+    /// Sets the spacing of the root node instead of computing it from its number of points
+    pub(crate) fn set_spacing(&mut self, spacing: f64) {
+        self.spacing = Some(spacing);
+    }
+
     /// Close is called after the last point is written
-    fn close(&mut self) -> crate::Result<()> {
+    pub(crate) fn close(&mut self) -> crate::Result<()> {
         if self.is_closed {
             return Err(crate::Error::ClosedWriter);
         }
@@ -542,8 +854,10 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
             return Err(crate::Error::EmptyCopcFile);
         }
 
-        // write the unclosed chunks, order does not matter
-        for (key, chunk) in self.open_chunks.drain() {
+        // write the unclosed chunks, sorted by key so identical input gives identical output
+        let mut open_chunks: Vec<_> = self.open_chunks.drain().collect();
+        open_chunks.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (key, chunk) in open_chunks {
             let inner = chunk.into_inner();
             if inner.is_empty() {
                 continue;
@@ -561,17 +875,33 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
 
         let start_of_first_evlr = self.compressor.get_mut().stream_position()?;
 
-        let raw_evlrs: Vec<las::Result<las::raw::Vlr>> = self
+        let mut raw_evlrs: Vec<las::Result<las::raw::Vlr>> = self
             .header
             .evlrs()
             .iter()
             .map(|evlr| evlr.clone().into_raw(true))
             .collect();
+        if let Some(statistics) = &self.statistics {
+            raw_evlrs.push(statistics.to_evlr()?.into_raw(true));
+        }
+        // the hierarchy and statistics evlrs are not in the header
+        let added_evlrs = 1 + self.statistics.is_some() as u32;
+
+        // the copc-evlr is the first evlr, its data starts after the 60 byte evlr header
+        let root_hier_offset = start_of_first_evlr + 60;
+        let (hierarchy_evlr, root_hier_size) = match self.hierarchy_page_size {
+            Some(page_size) => self
+                .hierarchy
+                .clone()
+                .into_paged_evlr(page_size, root_hier_offset)?,
+            None => (
+                self.hierarchy.clone().into_evlr()?,
+                self.hierarchy.byte_size(),
+            ),
+        };
 
         // write copc-evlr
-        self.hierarchy
-            .clone()
-            .into_evlr()?
+        hierarchy_evlr
             .into_raw(true)?
             .write_to(self.compressor.get_mut())?;
         // write the rest of the evlrs
@@ -585,21 +915,27 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
         self.header.clone().into_raw().and_then(|mut raw_header| {
             if let Some(e) = &mut raw_header.evlr {
                 e.start_of_first_evlr = start_of_first_evlr;
-                e.number_of_evlrs += 1;
+                e.number_of_evlrs += added_evlrs;
             } else {
                 raw_header.evlr = Some(las::raw::header::Evlr {
                     start_of_first_evlr,
-                    number_of_evlrs: 1,
+                    number_of_evlrs: added_evlrs,
                 });
             }
             raw_header.write_to(self.compressor.get_mut())
         })?;
 
         // update the copc info vlr and write it
-        self.copc_info.spacing =
-            2. * self.copc_info.halfsize / (self.root_node.entry.point_count as f64);
-        self.copc_info.root_hier_offset = start_of_first_evlr + 60; // the header is 60bytes
-        self.copc_info.root_hier_size = self.hierarchy.byte_size();
+        self.copc_info.spacing = self
+            .spacing
+            .unwrap_or(2. * self.copc_info.halfsize / (self.root_node.entry.point_count as f64));
+        if self.copc_info.gpstime_minimum > self.copc_info.gpstime_maximum {
+            // no point with a gps time that was not synthesized
+            self.copc_info.gpstime_minimum = 0.;
+            self.copc_info.gpstime_maximum = 0.;
+        }
+        self.copc_info.root_hier_offset = root_hier_offset;
+        self.copc_info.root_hier_size = root_hier_size;
 
         self.copc_info
             .clone()
@@ -621,12 +957,6 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
     fn add_point_greedy(&mut self, point: las::Point) -> crate::Result<()> {
         self.header.add_point(&point);
 
-        if point.gps_time.unwrap() < self.copc_info.gpstime_minimum {
-            self.copc_info.gpstime_minimum = point.gps_time.unwrap();
-        } else if point.gps_time.unwrap() > self.copc_info.gpstime_maximum {
-            self.copc_info.gpstime_maximum = point.gps_time.unwrap();
-        }
-
         let mut node_key = None;
         let mut write_chunk = false;
 
@@ -640,7 +970,7 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
                 // the point does not belong to this subtree
                 continue;
             }
-            if node.is_full(self.max_node_size) {
+            if node.is_full(self.max_node_size) && node.entry.key.level < self.max_depth {
                 // the point belongs to the subtree, but this node is full
                 // need to push the node's children to the nodes_to_check stack
                 if node.children.is_empty() {
@@ -671,7 +1001,9 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
                 node.entry.point_count += 1;
 
                 // check if the node now is full
-                write_chunk = node.is_full(self.max_node_size);
+                // nodes at max depth are never full and are written on close
+                write_chunk =
+                    node.is_full(self.max_node_size) && node.entry.key.level < self.max_depth;
                 break;
             }
         }
@@ -680,6 +1012,11 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
         };
 
         let raw_point = point.into_raw(self.header.transforms())?;
+        if let Some(statistics) = &mut self.statistics {
+            // the statistics of the stored point, with quantized coordinates
+            let point = las::Point::new(raw_point.clone(), self.header.transforms());
+            statistics.add(&node_key, &point);
+        }
 
         if !self.open_chunks.contains_key(&node_key) {
             let mut val = Cursor::new(vec![]);
@@ -743,7 +1080,7 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
                     })
                 }
             }
-            if !node.is_full(self.max_node_size) {
+            if !node.is_full(self.max_node_size) || node.entry.key.level >= self.max_depth {
                 node_candidates.push(&mut node.entry);
             }
             // push the children to the stack
@@ -758,25 +1095,25 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
         }
 
         // weighted by the inverse of the area (should volume be used?) the nodes cover
-        let chosen_index = get_random_weighted_index(&node_candidates);
+        let chosen_index = get_random_weighted_index(&mut self.rng, &node_candidates);
 
         let chosen_entry = &mut node_candidates[chosen_index];
 
         chosen_entry.point_count += 1;
 
-        let write_chunk = chosen_entry.point_count > self.max_node_size;
+        let write_chunk = chosen_entry.point_count > self.max_node_size
+            && chosen_entry.key.level < self.max_depth;
 
         let node_key = chosen_entry.key.clone();
 
         self.header.add_point(&point);
 
-        if point.gps_time.unwrap() < self.copc_info.gpstime_minimum {
-            self.copc_info.gpstime_minimum = point.gps_time.unwrap();
-        } else if point.gps_time.unwrap() > self.copc_info.gpstime_maximum {
-            self.copc_info.gpstime_maximum = point.gps_time.unwrap();
-        }
-
         let raw_point = point.into_raw(self.header.transforms())?;
+        if let Some(statistics) = &mut self.statistics {
+            // the statistics of the stored point, with quantized coordinates
+            let point = las::Point::new(raw_point.clone(), self.header.transforms());
+            statistics.add(&node_key, &point);
+        }
 
         if !self.open_chunks.contains_key(&node_key) {
             let mut val = Cursor::new(vec![]);
@@ -803,7 +1140,7 @@ impl<W: Write + Seek> CopcWriter<'_, W> {
     }
 }
 
-fn get_random_weighted_index(entries: &Vec<&mut Entry>) -> usize {
+fn get_random_weighted_index(rng: &mut fastrand::Rng, entries: &Vec<&mut Entry>) -> usize {
     // calculate weights
     let levels: Vec<i32> = entries.iter().map(|e| e.key.level).collect();
     let zero_level = levels[0];
@@ -819,7 +1156,7 @@ fn get_random_weighted_index(entries: &Vec<&mut Entry>) -> usize {
     let weights: Vec<f64> = areas.iter().map(|a| (1. / a) / inv_sum).collect();
 
     // get random index
-    let random = fastrand::f64();
+    let random = rng.f64();
     let mut chosen_index = weights.len() - 1;
 
     for i in 0..weights.len() - 1 {
@@ -853,3 +1190,129 @@ fn bounds_contains_point(b: &las::Bounds, p: &las::Point) -> bool {
         || b.min.y > p.y
         || b.min.z > p.z)
 }
+
+/// Checks that the file to write ends in .copc.laz
+pub(crate) fn check_copc_extension(path: &Path) -> crate::Result<()> {
+    let copc_ext = Path::new(match path.file_stem() {
+        Some(copc) => copc,
+        None => return Err(crate::Error::WrongCopcExtension),
+    })
+    .extension();
+
+    match (copc_ext, path.extension()) {
+        (Some(copc), Some(laz)) => match (&copc.to_str(), &laz.to_str()) {
+            (Some(copc_str), Some(laz_str)) => {
+                if &copc_str.to_lowercase() != "copc" || &laz_str.to_lowercase() != "laz" {
+                    return Err(crate::Error::WrongCopcExtension);
+                }
+            }
+            _ => return Err(crate::Error::WrongCopcExtension),
+        },
+        _ => return Err(crate::Error::WrongCopcExtension),
+    }
+    Ok(())
+}
+
+/// Builder for [CopcWriter::new] and [CopcWriter::from_path]
+/// where sizes < 1 select the defaults
+fn builder_with_sizes(header: Header, min_size: i32, max_size: i32) -> CopcWriterBuilder {
+    let mut builder = CopcWriterBuilder::new(header);
+    if min_size >= 1 {
+        builder = builder.min_node_size(min_size);
+    }
+    if max_size >= 1 {
+        builder = builder.max_node_size(max_size);
+    }
+    builder
+}
+
+/// WKT CRS VLR holding `wkt_data`
+fn wkt_crs_vlr(wkt_data: Vec<u8>) -> las::Vlr {
+    las::Vlr {
+        user_id: "LASF_Projection".to_owned(),
+        record_id: 2112,
+        description: String::new(),
+        data: wkt_data,
+    }
+}
+
+/// Center, halfsize and bounds of the cube enclosing `bounds`
+fn octree_cube(bounds: &Bounds) -> (Vector<f64>, f64, Bounds) {
+    let center_point = Vector {
+        x: (bounds.min.x + bounds.max.x) / 2.,
+        y: (bounds.min.y + bounds.max.y) / 2.,
+        z: (bounds.min.z + bounds.max.z) / 2.,
+    };
+    let halfsize = (center_point.x - bounds.min.x)
+        .max((center_point.y - bounds.min.y).max(center_point.z - bounds.min.z));
+
+    let cube = Bounds {
+        min: Vector {
+            x: center_point.x - halfsize,
+            y: center_point.y - halfsize,
+            z: center_point.z - halfsize,
+        },
+        max: Vector {
+            x: center_point.x + halfsize,
+            y: center_point.y + halfsize,
+            z: center_point.z + halfsize,
+        },
+    };
+    (center_point, halfsize, cube)
+}
+
+/// Tight bounds of the points, `None` if there are no points
+pub(crate) fn points_bounds<'p, P: IntoIterator<Item = &'p las::Point>>(
+    points: P,
+) -> Option<Bounds> {
+    let mut bounds = Bounds::default();
+    let mut is_empty = true;
+    for p in points {
+        bounds.grow(p);
+        is_empty = false;
+    }
+    (!is_empty).then_some(bounds)
+}
+
+/// Grows the bounds by one scale unit along axes without extent,
+/// the octree needs a non-zero extent along every axis
+pub(crate) fn pad_flat_bounds(mut bounds: Bounds, transforms: &Vector<Transform>) -> Bounds {
+    if !(bounds.max.x - bounds.min.x).is_normal() {
+        bounds.max.x = bounds.min.x + transforms.x.scale;
+    }
+    if !(bounds.max.y - bounds.min.y).is_normal() {
+        bounds.max.y = bounds.min.y + transforms.y.scale;
+    }
+    if !(bounds.max.z - bounds.min.z).is_normal() {
+        bounds.max.z = bounds.min.z + transforms.z.scale;
+    }
+    bounds
+}
+
+/// Transforms with `precision` as scale and the center of `bounds` as offset
+///
+/// The offset is rounded to a multiple of the scale. If the bounds can not be represented
+/// with that precision the scale is coarsened by factors of 10 until they can.
+pub(crate) fn transforms_for_bounds(bounds: &Bounds, precision: f64) -> Vector<Transform> {
+    let transform = |min: f64, max: f64| {
+        let mut scale = precision;
+        while (max - min) / 2. / scale >= i32::MAX as f64 {
+            scale *= 10.;
+        }
+        if scale != precision {
+            log::warn!(
+                "Precision {precision} can not represent the extent {}, using {scale}",
+                max - min
+            );
+        }
+        Transform {
+            scale,
+            offset: ((min + max) / 2. / scale).round() * scale,
+        }
+    };
+    Vector {
+        x: transform(bounds.min.x, bounds.max.x),
+        y: transform(bounds.min.y, bounds.max.y),
+        z: transform(bounds.min.z, bounds.max.z),
+    }
+}
@@ -0,0 +1,78 @@
+#![cfg(feature = "writer")]
+
+//! Writing with automatic bounds: the header bounds are not trusted, tight bounds
+//! are computed from the points and the scales and offsets are chosen for the
+//! requested precision.
+
+use std::io::Cursor;
+
+use copc_rs::{BoundsSelection, CopcReader, CopcWriterBuilder, LodSelection};
+use las::point::Format;
+use las::{Builder, Point, Transform, Vector, Vlr};
+
+const WKT: &[u8] = b"GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",SPHEROID[\"WGS 84\",6378137,298.257223563]],PRIMEM[\"Greenwich\",0],UNIT[\"degree\",0.0174532925199433]]";
+
+// a header without bounds and with a scale far too coarse for the points
+fn header_without_bounds() -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(6).unwrap();
+    let t = Transform {
+        scale: 1.0,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    b.vlrs.push(Vlr {
+        user_id: "LASF_Projection".into(),
+        record_id: 2112,
+        description: String::new(),
+        data: WKT.to_vec(),
+    });
+    b.into_header().unwrap()
+}
+
+#[test]
+fn bounds_and_transforms_are_computed_from_the_points() {
+    let pts: Vec<Point> = (0..2000)
+        .map(|i| Point {
+            x: 698_100. + (i % 100) as f64 * 1.3 + 0.0004,
+            y: 6_508_100. + ((i / 100) % 20) as f64 * 4.45,
+            z: 10. + (i % 7) as f64 * 0.125,
+            gps_time: Some(1000.0 + i as f64),
+            ..Default::default()
+        })
+        .collect();
+    let n = pts.len();
+
+    let mut buf = Cursor::new(Vec::<u8>::new());
+    {
+        let mut w = CopcWriterBuilder::new(header_without_bounds())
+            .min_node_size(10)
+            .max_node_size(100)
+            .auto_bounds(0.001)
+            .build(&mut buf)
+            .unwrap();
+        w.write(pts.clone(), -1).unwrap();
+
+        let transforms = w.header().transforms();
+        assert_eq!(transforms.x.scale, 0.001);
+        assert!((transforms.x.offset - 698_164.35).abs() < 0.001);
+        let bounds = w.header().bounds();
+        assert!((bounds.min.x - 698_100.000_4).abs() < 0.001);
+        assert!((bounds.max.z - 10.75).abs() < 0.001);
+    }
+
+    buf.set_position(0);
+    let mut r = CopcReader::new(buf).unwrap();
+    let mut read: Vec<Point> = r
+        .points(LodSelection::All, BoundsSelection::All)
+        .unwrap()
+        .collect();
+    assert_eq!(read.len(), n, "every written point must round-trip");
+
+    read.sort_by(|a, b| a.gps_time.partial_cmp(&b.gps_time).unwrap());
+    for (a, b) in read.iter().zip(&pts) {
+        assert!((a.x - b.x).abs() <= 0.0005);
+        assert!((a.y - b.y).abs() <= 0.0005);
+        assert!((a.z - b.z).abs() <= 0.0005);
+    }
+}
@@ -0,0 +1,138 @@
+#![cfg(feature = "cli")]
+
+//! The `copc` binary.
+
+use std::path::Path;
+use std::process::Command;
+
+use copc_rs::{CopcWriterBuilder, CrsOverride};
+use las::point::Format;
+use las::{Builder, Point, Transform, Vector};
+
+fn header() -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(6).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 0.0;
+    raw.max_x = 100.0;
+    raw.min_y = 0.0;
+    raw.max_y = 100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    Builder::new(raw).unwrap().into_header().unwrap()
+}
+
+fn copc(args: &[&str]) -> (i32, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_copc"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn info_validate_and_query() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.copc.laz");
+    {
+        let mut w = CopcWriterBuilder::new(header())
+            .crs(CrsOverride::Epsg(2056))
+            .min_node_size(10)
+            .max_node_size(100)
+            .seed(1)
+            .build_path(&input)
+            .unwrap();
+        let points = (0..1000).map(|i| Point {
+            x: (i % 100) as f64,
+            y: (i / 10) as f64,
+            z: (i % 7) as f64,
+            gps_time: Some(i as f64),
+            ..Default::default()
+        });
+        w.write(points, 1000).unwrap();
+    }
+    let input = input.to_str().unwrap();
+
+    let (code, json) = copc(&["info", input, "--json"]);
+    assert_eq!(code, 0);
+    assert!(json.starts_with('{') && json.trim_end().ends_with('}'));
+    assert!(json.contains(r#""number_of_points": 1000"#));
+    assert!(json.contains(r#""epsg": 2056"#));
+    assert!(json.contains(r#""gpstime_maximum": 999"#));
+
+    let (code, json) = copc(&["validate", input, "--json"]);
+    assert_eq!(code, 0);
+    assert!(json.starts_with(r#"{"valid": true, "nodes_checked": "#));
+
+    let csv = dir.join("query.csv");
+    let (code, _) = copc(&[
+        "query",
+        input,
+        csv.to_str().unwrap(),
+        "--bounds",
+        "0,0,49.5,100",
+    ]);
+    assert_eq!(code, 0);
+    let lines = std::fs::read_to_string(&csv).unwrap();
+    assert_eq!(lines.lines().count(), 1 + 500);
+
+    let tsv = dir.join("query.tsv");
+    let (code, _) = copc(&[
+        "query",
+        input,
+        tsv.to_str().unwrap(),
+        "--columns",
+        "x,y,z",
+        "--precision",
+        "1",
+    ]);
+    assert_eq!(code, 0);
+    let lines = std::fs::read_to_string(&tsv).unwrap();
+    assert_eq!(lines.lines().next(), Some("x\ty\tz"));
+    assert!(lines.lines().nth(1).unwrap().ends_with(".0"));
+
+    let ply = dir.join("query.ply");
+    let (code, out) = copc(&["query", input, ply.to_str().unwrap(), "--ascii"]);
+    assert_eq!(code, 0);
+    assert!(out.starts_with("Wrote "));
+    assert!(std::fs::read_to_string(&ply)
+        .unwrap()
+        .starts_with("ply\nformat ascii 1.0\n"));
+
+    let levels = dir.join("levels.csv");
+    let levels = levels.to_str().unwrap();
+    let (code, _) = copc(&["query", input, levels, "--levels", "0,1"]);
+    assert_eq!(code, 0);
+    let (code, _) = copc(&["query", input, levels, "--levels", "0,1.5"]);
+    assert_eq!(code, 2);
+
+    let (code, json) = copc(&["stats", input, "--attributes", "z", "--json"]);
+    assert_eq!(code, 0);
+    assert!(json.starts_with(
+        r#"{"points": 1000, "attributes": [{"attribute": "z", "count": 1000, "min": 0"#
+    ));
+    assert!(json.contains(r#""max": 6"#));
+
+    let (code, tree) = copc(&["tree", input, "--max-level", "0"]);
+    assert_eq!(code, 0);
+    assert!(tree.starts_with("0-0-0-0  points: "));
+    assert_eq!(tree.lines().count(), 1);
+
+    let (code, dot) = copc(&["tree", input, "--dot"]);
+    assert_eq!(code, 0);
+    assert!(dot.starts_with("digraph hierarchy {"));
+
+    let (code, _) = copc(&["info", Path::new("missing.copc.laz").to_str().unwrap()]);
+    assert_eq!(code, 2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
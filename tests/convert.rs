@@ -0,0 +1,72 @@
+#![cfg(feature = "writer")]
+
+//! One-call conversion of LAS files to COPC with `convert`.
+
+use copc_rs::{convert, BoundsSelection, ConvertOptions, CopcReader, CrsOverride, LodSelection};
+use las::point::Format;
+use las::{Builder, Point, Transform, Vector};
+
+fn header() -> las::Header {
+    let mut b = Builder::from((1u8, 2u8));
+    b.point_format = Format::new(1).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    b.into_header().unwrap()
+}
+
+fn points(n: usize) -> Vec<Point> {
+    (0..n)
+        .map(|i| Point {
+            x: 1000.0 + (i % 100) as f64,
+            y: 2000.0 + ((i / 100) % 100) as f64,
+            // flat input, the octree still needs a z extent
+            z: 5.0,
+            gps_time: Some(1000.0 + i as f64),
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[test]
+fn converts_a_las_file() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-convert-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.las");
+    let output = dir.join("output.copc.laz");
+
+    let n = 2000;
+    {
+        let mut w = las::Writer::from_path(&input, header()).unwrap();
+        for p in points(n) {
+            w.write_point(p).unwrap();
+        }
+        w.close().unwrap();
+    }
+
+    let options = ConvertOptions {
+        max_node_size: 500,
+        seed: Some(7),
+        crs: Some(CrsOverride::Epsg(4326)),
+        ..Default::default()
+    };
+    let summary = convert(&input, &output, &options).unwrap();
+    assert_eq!(summary.points_read, n as u64);
+    assert_eq!(summary.points_written, n as u64);
+    assert_eq!(summary.read_errors, 0);
+    assert_eq!(summary.rejected.total(), 0);
+    assert!(summary.node_count > 1);
+    assert!(summary.depth >= 1);
+
+    let mut r = CopcReader::from_path(&output).unwrap();
+    assert_eq!(r.header().point_format().to_u8().unwrap(), 6);
+    let read = r
+        .points(LodSelection::All, BoundsSelection::All)
+        .unwrap()
+        .count();
+    assert_eq!(read, n);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,113 @@
+#![cfg(feature = "writer")]
+
+//! CRS accessors of `CopcReader` and the WKT parsing of `Crs`.
+
+use std::io::Cursor;
+
+use copc_rs::{CopcReader, CopcWriterBuilder, Crs, CrsOverride, UnitKind};
+use las::point::Format;
+use las::{Builder, Point, Transform, Vector};
+
+fn header() -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(6).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 0.0;
+    raw.max_x = 100.0;
+    raw.min_y = 0.0;
+    raw.max_y = 100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    Builder::new(raw).unwrap().into_header().unwrap()
+}
+
+fn reader_with_crs(crs: CrsOverride) -> CopcReader<Cursor<Vec<u8>>> {
+    let mut buf = Cursor::new(Vec::<u8>::new());
+    {
+        let mut w = CopcWriterBuilder::new(header())
+            .crs(crs)
+            .build(&mut buf)
+            .unwrap();
+        let points = (0..10).map(|i| Point {
+            x: i as f64,
+            y: i as f64,
+            z: i as f64,
+            gps_time: Some(i as f64),
+            ..Default::default()
+        });
+        w.write(points, 10).unwrap();
+    }
+    buf.set_position(0);
+    CopcReader::new(buf).unwrap()
+}
+
+#[test]
+fn compound_crs_accessors() {
+    // NAD83 + NAVD88 height
+    let r = reader_with_crs(CrsOverride::Epsg(5498));
+    assert_eq!(r.crs_wkt(), Some(crs_definitions::EPSG_5498.wkt));
+    assert_eq!(r.crs_epsg(), Some(5498));
+
+    let crs = r.crs().unwrap();
+    assert_eq!(crs.horizontal_epsg(), Some(4269));
+    assert_eq!(crs.vertical_epsg(), Some(5703));
+    assert_eq!(
+        crs.vertical_datum(),
+        Some("North American Vertical Datum 1988")
+    );
+    let horizontal_unit = crs.horizontal_unit().unwrap();
+    assert_eq!(horizontal_unit.name, "degree");
+    assert_eq!(horizontal_unit.kind, UnitKind::Angular);
+    let vertical_unit = crs.vertical_unit().unwrap();
+    assert_eq!(vertical_unit.name, "metre");
+    assert_eq!(vertical_unit.factor, 1.0);
+    assert_eq!(vertical_unit.kind, UnitKind::Linear);
+}
+
+#[test]
+fn projected_crs_accessors() {
+    let r = reader_with_crs(CrsOverride::Epsg(2056));
+    let crs = r.crs().unwrap();
+    assert_eq!(crs.epsg(), Some(2056));
+    assert_eq!(crs.horizontal_epsg(), Some(2056));
+    assert_eq!(crs.vertical_epsg(), None);
+    assert_eq!(crs.vertical_datum(), None);
+    assert_eq!(crs.horizontal_unit().unwrap().name, "metre");
+    assert_eq!(crs.vertical_unit(), None);
+}
+
+#[test]
+fn wkt2_and_unidentified_crs() {
+    let crs = Crs::from_wkt(
+        r#"PROJCRS["my projection",BASEGEOGCRS["WGS 84",DATUM["World Geodetic System 1984",ELLIPSOID["WGS 84",6378137,298.257223563,LENGTHUNIT["metre",1]]]],CONVERSION["UTM zone 32N",METHOD["Transverse Mercator"]],CS[Cartesian,2],AXIS["easting (E)",east,ORDER[1],LENGTHUNIT["US survey foot",0.304800609601219]],AXIS["northing (N)",north,ORDER[2],LENGTHUNIT["US survey foot",0.304800609601219]]]"#,
+    );
+    assert_eq!(crs.epsg(), None);
+    let unit = crs.horizontal_unit().unwrap();
+    assert_eq!(unit.name, "US survey foot");
+    assert_eq!(unit.factor, 0.304800609601219);
+    assert_eq!(unit.kind, UnitKind::Linear);
+
+    // no authority, identified by its name
+    let crs = Crs::from_wkt(
+        r#"GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563]],PRIMEM["Greenwich",0],UNIT["degree",0.0174532925199433]]"#,
+    );
+    assert_eq!(crs.epsg(), Some(4326));
+}
+
+#[test]
+fn deeply_nested_wkt_is_not_parsed() {
+    let depth = 100_000;
+    let wkt = format!(
+        "{}AUTHORITY[\"EPSG\",\"2056\"]{}",
+        "PROJCS[\"a\",".repeat(depth),
+        "]".repeat(depth)
+    );
+    let crs = Crs::from_wkt(&wkt);
+    assert_eq!(crs.epsg(), None);
+    assert_eq!(crs.wkt(), wkt);
+}
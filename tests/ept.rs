@@ -0,0 +1,202 @@
+#![cfg(feature = "writer")]
+
+//! Import and export of Entwine Point Tile datasets.
+
+use copc_rs::{
+    ept_to_copc, validate_path, BoundsSelection, CopcReader, CopcWriterBuilder, CrsOverride,
+    EptOptions, Error, ExtraBytes, ExtraBytesDescriptor, ExtraBytesType, LodSelection, VoxelKey,
+};
+use las::point::Format;
+use las::{Builder, Point, Transform, Vector};
+use std::str::FromStr;
+
+fn header() -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(6).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 0.0;
+    raw.max_x = 100.0;
+    raw.min_y = 0.0;
+    raw.max_y = 100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    Builder::new(raw).unwrap().into_header().unwrap()
+}
+
+/// The points of a file sorted by gps time
+fn sorted_points<P: AsRef<std::path::Path>>(path: P) -> Vec<Point> {
+    let mut points: Vec<Point> = CopcReader::from_path(path)
+        .unwrap()
+        .points(LodSelection::All, BoundsSelection::All)
+        .unwrap()
+        .collect();
+    points.sort_by(|a, b| a.gps_time.partial_cmp(&b.gps_time).unwrap());
+    points
+}
+
+#[test]
+fn copc_to_ept_and_back() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-ept-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.copc.laz");
+    let layout = ExtraBytes::new(vec![ExtraBytesDescriptor::new(
+        "Amplitude",
+        ExtraBytesType::U16,
+    )]);
+    let points: Vec<Point> = (0..20_000)
+        .map(|i| {
+            let mut p = Point {
+                x: (i % 137) as f64 * 0.7,
+                y: (i % 101) as f64 * 0.9,
+                z: (i % 17) as f64,
+                return_number: 1,
+                number_of_returns: 1,
+                gps_time: Some(i as f64),
+                extra_bytes: vec![0; 2],
+                ..Default::default()
+            };
+            layout.set(&mut p, "Amplitude", (i % 1000) as f64);
+            p
+        })
+        .collect();
+    {
+        let mut w = CopcWriterBuilder::new(header())
+            .crs(CrsOverride::Epsg(2056))
+            .extra_attribute(layout.descriptors()[0].clone())
+            .min_node_size(50)
+            .max_node_size(500)
+            .seed(1)
+            .build_path(&input)
+            .unwrap();
+        w.write(points.clone(), points.len() as i32).unwrap();
+    }
+    let mut reader = CopcReader::from_path(&input).unwrap();
+    let entries: Vec<_> = reader
+        .hierarchy_entries()
+        .values()
+        .filter(|e| e.point_count > 0)
+        .map(|e| (e.key.clone(), e.point_count))
+        .collect();
+    let depth = entries.iter().map(|(key, _)| key.level).max().unwrap();
+    assert!(depth >= 2);
+
+    // COPC to EPT, with a hierarchy file every two levels
+    let ept = dir.join("ept");
+    let summary = reader
+        .export_ept(
+            &ept,
+            &EptOptions {
+                hierarchy_step: Some(2),
+            },
+        )
+        .unwrap();
+    assert_eq!(summary.nodes_written, entries.len());
+    assert_eq!(summary.chunks_reused, entries.len());
+    assert_eq!(summary.points_written, points.len() as u64);
+
+    let metadata = std::fs::read_to_string(ept.join("ept.json")).unwrap();
+    assert!(metadata.contains("\"dataType\": \"laszip\""));
+    assert!(metadata.contains("\"horizontal\": \"2056\""));
+    assert!(metadata.contains("\"name\": \"Amplitude\""));
+    let root_hierarchy =
+        std::fs::read_to_string(ept.join("ept-hierarchy").join("0-0-0-0.json")).unwrap();
+    assert!(root_hierarchy.contains(": -1"));
+    assert!(ept.join("ept-hierarchy").join("2-0-0-0.json").exists());
+
+    // the node files are LAZ files of their points
+    for (key, point_count) in &entries {
+        let path = ept
+            .join("ept-data")
+            .join(format!("{}-{}-{}-{}.laz", key.level, key.x, key.y, key.z));
+        let mut node = las::Reader::from_path(&path).unwrap();
+        assert_eq!(node.header().number_of_points(), *point_count as u64);
+        let node_bounds = node.header().bounds();
+        let read: Vec<Point> = node.points().map(Result::unwrap).collect();
+        assert_eq!(read.len(), *point_count as usize);
+        assert!(read.iter().all(|p| p.x >= node_bounds.min.x
+            && p.x <= node_bounds.max.x
+            && p.z >= node_bounds.min.z
+            && p.z <= node_bounds.max.z));
+    }
+
+    // EPT to COPC keeps the octree and copies the chunks
+    let output = dir.join("output.copc.laz");
+    let summary = ept_to_copc(&ept, &output).unwrap();
+    assert_eq!(summary.nodes_written, entries.len());
+    assert_eq!(summary.chunks_reused, entries.len());
+    assert_eq!(summary.points_written, points.len() as u64);
+    let report = validate_path(&output).unwrap();
+    assert!(report.is_valid(), "{:?}", report.issues);
+
+    let imported = CopcReader::from_path(&output).unwrap();
+    assert_eq!(imported.crs_epsg(), Some(2056));
+    assert_eq!(imported.copc_info().center, reader.copc_info().center);
+    assert_eq!(imported.copc_info().halfsize, reader.copc_info().halfsize);
+    assert!((imported.copc_info().spacing - reader.copc_info().spacing).abs() < 0.01);
+    assert_eq!(imported.extra_bytes(), reader.extra_bytes());
+    for (key, point_count) in &entries {
+        assert_eq!(
+            imported.hierarchy_entries().get(key).map(|e| e.point_count),
+            Some(*point_count)
+        );
+    }
+    assert_eq!(sorted_points(&output), sorted_points(&input));
+
+    // the EPSG code is used when the srs has no WKT
+    std::fs::write(
+        ept.join("ept.json"),
+        metadata.replace("\"wkt\"", "\"no_wkt\""),
+    )
+    .unwrap();
+    let epsg_only = dir.join("epsg.copc.laz");
+    ept_to_copc(&ept, &epsg_only).unwrap();
+    assert_eq!(
+        CopcReader::from_path(&epsg_only).unwrap().crs_epsg(),
+        Some(2056)
+    );
+    std::fs::write(ept.join("ept.json"), &metadata).unwrap();
+
+    // a node without points between the root and nodes with points is in the hierarchy
+    let root_file = ept.join("ept-hierarchy").join("0-0-0-0.json");
+    let root_members = std::fs::read_to_string(&root_file).unwrap();
+    let start = root_members.find("\"1-0-0-0\": ").unwrap() + "\"1-0-0-0\": ".len();
+    let end = start + root_members[start..].find([',', '}']).unwrap();
+    let emptied = format!("{}0{}", &root_members[..start], &root_members[end..]);
+    std::fs::write(&root_file, emptied).unwrap();
+    let sparse = dir.join("sparse.copc.laz");
+    ept_to_copc(&ept, &sparse).unwrap();
+    let report = validate_path(&sparse).unwrap();
+    assert!(report.is_valid(), "{:?}", report.issues);
+    let imported = CopcReader::from_path(&sparse).unwrap();
+    let entry = imported.hierarchy_entries()[&VoxelKey::from_str("1-0-0-0").unwrap()].clone();
+    assert_eq!(
+        (entry.point_count, entry.offset, entry.byte_size),
+        (0, 0, 0)
+    );
+    std::fs::write(&root_file, root_members).unwrap();
+
+    // a subtree file referencing the root file is a loop
+    let subtree = ept.join("ept-hierarchy").join("2-0-0-0.json");
+    let members = std::fs::read_to_string(&subtree).unwrap();
+    std::fs::write(&subtree, members.replacen('{', "{\"0-0-0-0\": -1, ", 1)).unwrap();
+    assert!(matches!(
+        ept_to_copc(&ept, dir.join("loop.copc.laz")),
+        Err(Error::InvalidEpt(message)) if message.contains("more than once")
+    ));
+    std::fs::write(&subtree, members).unwrap();
+
+    // only LAZ nodes are supported
+    let metadata = metadata.replace("\"laszip\"", "\"binary\"");
+    std::fs::write(ept.join("ept.json"), metadata).unwrap();
+    assert!(matches!(
+        ept_to_copc(&ept, dir.join("binary.copc.laz")),
+        Err(Error::InvalidEpt(_))
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
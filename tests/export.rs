@@ -0,0 +1,318 @@
+#![cfg(feature = "writer")]
+
+//! Export of query results to LAS/LAZ and COPC files.
+
+use copc_rs::{
+    validate_path, Bounds, BoundsSelection, ConvertOptions, CopcReader, CopcWriterBuilder,
+    CrsOverride, CsvOptions, Error, ExtraBytes, ExtraBytesDescriptor, ExtraBytesType, LodSelection,
+    PlyFormat, PlyOptions, PointAttribute,
+};
+use las::point::Format;
+use las::{Builder, Point, Reader, Transform, Vector};
+use std::path::Path;
+
+fn header() -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(6).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 0.0;
+    raw.max_x = 100.0;
+    raw.min_y = 0.0;
+    raw.max_y = 100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    Builder::new(raw).unwrap().into_header().unwrap()
+}
+
+fn clip() -> Bounds {
+    Bounds {
+        min: Vector {
+            x: 10.,
+            y: 20.,
+            z: 0.,
+        },
+        max: Vector {
+            x: 30.5,
+            y: 60.5,
+            z: 100.,
+        },
+    }
+}
+
+fn amplitude() -> ExtraBytes {
+    ExtraBytes::new(vec![ExtraBytesDescriptor::new(
+        "Amplitude",
+        ExtraBytesType::U16,
+    )])
+}
+
+/// Writes a 100 x 100 grid of points with an amplitude to `input`
+fn write_input(input: &Path) -> Vec<Point> {
+    let layout = amplitude();
+    let points: Vec<Point> = (0..10_000)
+        .map(|i| {
+            let mut p = Point {
+                x: (i % 100) as f64,
+                y: (i / 100) as f64,
+                z: (i % 13) as f64,
+                return_number: 1 + (i % 2) as u8,
+                number_of_returns: 2,
+                gps_time: Some(i as f64),
+                extra_bytes: vec![0; 2],
+                ..Default::default()
+            };
+            layout.set(&mut p, "Amplitude", (i % 1000) as f64);
+            p
+        })
+        .collect();
+    {
+        let mut w = CopcWriterBuilder::new(header())
+            .crs(CrsOverride::Epsg(2056))
+            .extra_attribute(layout.descriptors()[0].clone())
+            .min_node_size(50)
+            .max_node_size(500)
+            .seed(1)
+            .build_path(input)
+            .unwrap();
+        w.write(points.clone(), points.len() as i32).unwrap();
+    }
+    points
+}
+
+#[test]
+fn clip_to_laz_and_copc() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-export-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.copc.laz");
+    let layout = amplitude();
+    let points = write_input(&input);
+    let expected: Vec<&Point> = points
+        .iter()
+        .filter(|p| p.x >= 10. && p.x <= 30.5 && p.y >= 20. && p.y <= 60.5)
+        .collect();
+
+    // LAZ
+    let laz = dir.join("clip.laz");
+    let mut r = CopcReader::from_path(&input).unwrap();
+    let summary = r
+        .export_las(&laz, LodSelection::All, BoundsSelection::Within(clip()))
+        .unwrap();
+    assert_eq!(summary.points_written, expected.len() as u64);
+    assert_eq!(summary.bounds.min.x, 10.);
+    assert_eq!(summary.bounds.max.y, 60.);
+
+    let mut laz_reader = Reader::from_path(&laz).unwrap();
+    let header = laz_reader.header().clone();
+    assert!(header.point_format().is_compressed);
+    assert_eq!(header.number_of_points(), expected.len() as u64);
+    assert_eq!(header.bounds(), summary.bounds);
+    assert_eq!(
+        header.number_of_points_by_return(1).unwrap()
+            + header.number_of_points_by_return(2).unwrap(),
+        expected.len() as u64
+    );
+    assert_eq!(
+        header.get_wkt_crs_bytes().unwrap(),
+        crs_definitions::EPSG_2056.wkt.as_bytes()
+    );
+    assert!(header
+        .all_vlrs()
+        .all(|vlr| !vlr.user_id.eq_ignore_ascii_case("copc")));
+    let exported = ExtraBytes::from_header(&header).unwrap();
+    assert_eq!(exported.descriptors(), layout.descriptors());
+    for point in laz_reader.points() {
+        let point = point.unwrap();
+        let i = point.gps_time.unwrap() as usize;
+        assert_eq!(exported.get(&point, "Amplitude"), Some((i % 1000) as f64));
+    }
+
+    // COPC of the upper levels
+    let copc = dir.join("clip.copc.laz");
+    let summary = r
+        .export_copc(
+            &copc,
+            LodSelection::LevelMinMax(0, 2),
+            BoundsSelection::Within(clip()),
+            &ConvertOptions::default(),
+        )
+        .unwrap();
+    assert!(summary.points_written > 0);
+    assert!(summary.points_written < expected.len() as u64);
+    let report = validate_path(&copc).unwrap();
+    assert!(report.is_valid(), "{:?}", report.issues);
+    let mut copc_reader = CopcReader::from_path(&copc).unwrap();
+    assert_eq!(copc_reader.crs_epsg(), Some(2056));
+    assert_eq!(
+        copc_reader
+            .points(LodSelection::All, BoundsSelection::All)
+            .unwrap()
+            .count() as u64,
+        summary.points_written
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn clip_to_ply_and_csv() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-export-text-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.copc.laz");
+    let points = write_input(&input);
+    let expected = points
+        .iter()
+        .filter(|p| p.x >= 10. && p.x <= 30.5 && p.y >= 20. && p.y <= 60.5)
+        .count();
+    let mut r = CopcReader::from_path(&input).unwrap();
+
+    // binary PLY with normals, the points are counted before they are written
+    let ply = dir.join("clip.ply");
+    let summary = r
+        .export_ply(
+            &ply,
+            LodSelection::All,
+            BoundsSelection::Within(clip()),
+            &PlyOptions {
+                attributes: Some(vec![
+                    PointAttribute::Intensity,
+                    PointAttribute::X,
+                    PointAttribute::Y,
+                    PointAttribute::Z,
+                    PointAttribute::Classification,
+                ]),
+                normals: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(summary.points_written, expected as u64);
+    let bytes = std::fs::read(&ply).unwrap();
+    let end = b"end_header\n";
+    let data = bytes.windows(end.len()).position(|w| w == end).unwrap() + end.len();
+    let ply_header = std::str::from_utf8(&bytes[..data]).unwrap();
+    assert!(ply_header.starts_with("ply\nformat binary_little_endian 1.0\n"));
+    assert!(ply_header.contains(&format!("\nelement vertex {expected}\n")));
+    assert!(ply_header.contains(
+        "property ushort intensity\nproperty double x\nproperty double y\nproperty double z\nproperty float nx\nproperty float ny\nproperty float nz\nproperty uchar classification\n"
+    ));
+    assert_eq!(bytes.len() - data, expected * (3 * 8 + 3 * 4 + 2 + 1));
+
+    // ASCII PLY has one line per point
+    let ply = dir.join("clip-ascii.ply");
+    r.export_ply(
+        &ply,
+        LodSelection::All,
+        BoundsSelection::Within(clip()),
+        &PlyOptions {
+            format: PlyFormat::Ascii,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let text = std::fs::read_to_string(&ply).unwrap();
+    let (_, vertices) = text.split_once("end_header\n").unwrap();
+    assert_eq!(vertices.lines().count(), expected);
+
+    // without bounds the count of a level is the one of the hierarchy
+    let ply = dir.join("level.ply");
+    let summary = r
+        .export_ply(
+            &ply,
+            LodSelection::Level(1),
+            BoundsSelection::All,
+            &PlyOptions {
+                format: PlyFormat::Ascii,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(summary.points_written > 0);
+    let text = std::fs::read_to_string(&ply).unwrap();
+    let (ply_header, vertices) = text.split_once("end_header\n").unwrap();
+    assert!(ply_header.contains(&format!("\nelement vertex {}\n", summary.points_written)));
+    assert_eq!(vertices.lines().count() as u64, summary.points_written);
+
+    // CSV with an extra attribute and fixed precision
+    let csv = dir.join("clip.csv");
+    r.export_csv(
+        &csv,
+        LodSelection::All,
+        BoundsSelection::Within(clip()),
+        &CsvOptions {
+            columns: Some(
+                ["x", "y", "gps_time", "Amplitude", "red"]
+                    .map(|c| c.parse().unwrap())
+                    .to_vec(),
+            ),
+            precision: Some(2),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let text = std::fs::read_to_string(&csv).unwrap();
+    let mut lines = text.lines();
+    assert_eq!(lines.next(), Some("x,y,gps_time,Amplitude,red"));
+    let mut rows = 0;
+    for line in lines {
+        let cells: Vec<&str> = line.split(',').collect();
+        assert_eq!(cells.len(), 5);
+        assert!(cells[0].ends_with(".00"));
+        let i: usize = cells[2].parse::<f64>().unwrap() as usize;
+        assert_eq!(cells[3], format!("{}.00", i % 1000));
+        // no colors in point format 6
+        assert_eq!(cells[4], "");
+        rows += 1;
+    }
+    assert_eq!(rows, expected);
+
+    // TSV without header
+    let tsv = dir.join("clip.tsv");
+    r.export_csv(
+        &tsv,
+        LodSelection::All,
+        BoundsSelection::Within(clip()),
+        &CsvOptions {
+            delimiter: '\t',
+            header: false,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let text = std::fs::read_to_string(&tsv).unwrap();
+    assert_eq!(text.lines().count(), expected);
+    assert_eq!(
+        text.lines().next().unwrap().split('\t').count(),
+        PointAttribute::defaults(r.header().point_format()).len()
+    );
+
+    let missing = r.export_csv(
+        dir.join("missing.csv"),
+        LodSelection::All,
+        BoundsSelection::All,
+        &CsvOptions {
+            columns: Some(vec![PointAttribute::Extra("Missing".to_string())]),
+            ..Default::default()
+        },
+    );
+    assert!(matches!(missing, Err(Error::UnknownAttribute(name)) if name == "Missing"));
+
+    // normals without all coordinates
+    let no_z = r.export_ply(
+        dir.join("no-z.ply"),
+        LodSelection::All,
+        BoundsSelection::All,
+        &PlyOptions {
+            attributes: Some(vec![PointAttribute::X, PointAttribute::Y]),
+            normals: true,
+            ..Default::default()
+        },
+    );
+    assert!(matches!(no_z, Err(Error::PlyNormalsWithoutCoordinates)));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
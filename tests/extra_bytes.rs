@@ -0,0 +1,182 @@
+#![cfg(feature = "writer")]
+
+//! Extra attributes declared on the writer and read back by name.
+
+use std::io::Cursor;
+
+use copc_rs::{
+    BoundsSelection, CopcReader, CopcWriterBuilder, Error, ExtraBytes, ExtraBytesDescriptor,
+    ExtraBytesType, LodSelection, WriterConfigError,
+};
+use las::point::Format;
+use las::{Builder, Point, Transform, Vector, Vlr};
+
+const WKT: &[u8] = b"GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",SPHEROID[\"WGS 84\",6378137,298.257223563]],PRIMEM[\"Greenwich\",0],UNIT[\"degree\",0.0174532925199433]]";
+
+fn header(extra_bytes: u16) -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(6).unwrap();
+    b.point_format.extra_bytes = extra_bytes;
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 0.0;
+    raw.max_x = 100.0;
+    raw.min_y = 0.0;
+    raw.max_y = 100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    let mut b2 = Builder::new(raw).unwrap();
+    b2.vlrs.push(Vlr {
+        user_id: "LASF_Projection".into(),
+        record_id: 2112,
+        description: String::new(),
+        data: WKT.to_vec(),
+    });
+    b2.into_header().unwrap()
+}
+
+fn attributes() -> Vec<ExtraBytesDescriptor> {
+    let mut amplitude = ExtraBytesDescriptor::new("Amplitude", ExtraBytesType::U16);
+    amplitude.scale = Some(0.1);
+    let mut deviation = ExtraBytesDescriptor::new("Deviation", ExtraBytesType::I32);
+    deviation.no_data = Some(-1.0);
+    deviation.description = "pulse shape deviation".into();
+    vec![amplitude, deviation]
+}
+
+#[test]
+fn declared_attributes_round_trip() {
+    let [amplitude, deviation]: [ExtraBytesDescriptor; 2] = attributes().try_into().unwrap();
+    // the header already has one undescribed extra byte
+    let layout = ExtraBytes::new(vec![
+        ExtraBytesDescriptor::new("", ExtraBytesType::Undocumented(1)),
+        amplitude.clone(),
+        deviation.clone(),
+    ]);
+
+    let n = 200;
+    let points: Vec<Point> = (0..n)
+        .map(|i| {
+            let mut p = Point {
+                x: (i % 100) as f64,
+                y: (i / 2) as f64,
+                z: (i % 10) as f64,
+                gps_time: Some(i as f64),
+                extra_bytes: vec![9],
+                ..Default::default()
+            };
+            assert!(layout.set(&mut p, "Amplitude", i as f64 / 10.0));
+            let deviation = if i % 2 == 0 { -1.0 } else { i as f64 };
+            assert!(layout.set(&mut p, "Deviation", deviation));
+            p
+        })
+        .collect();
+
+    let mut buf = Cursor::new(Vec::<u8>::new());
+    {
+        let mut w = CopcWriterBuilder::new(header(1))
+            .extra_attribute(amplitude)
+            .extra_attribute(deviation)
+            .build(&mut buf)
+            .unwrap();
+        assert_eq!(w.header().point_format().extra_bytes, 7);
+        w.write(points, n).unwrap();
+    }
+
+    buf.set_position(0);
+    let mut r = CopcReader::new(buf).unwrap();
+    assert_eq!(r.extra_bytes(), &layout);
+    let extra_bytes = r.extra_bytes().clone();
+    assert_eq!(
+        extra_bytes.descriptors()[2].description,
+        "pulse shape deviation"
+    );
+
+    let mut read = 0;
+    for p in r.points(LodSelection::All, BoundsSelection::All).unwrap() {
+        let i = p.gps_time.unwrap();
+        assert_eq!(p.extra_bytes[0], 9);
+        assert!((extra_bytes.get(&p, "Amplitude").unwrap() - i / 10.0).abs() < 1e-9);
+        if i as i32 % 2 == 0 {
+            assert_eq!(extra_bytes.get(&p, "Deviation"), None);
+        } else {
+            assert_eq!(extra_bytes.get(&p, "Deviation"), Some(i));
+        }
+        assert_eq!(extra_bytes.get(&p, "Unknown"), None);
+        read += 1;
+    }
+    assert_eq!(read, n);
+}
+
+#[test]
+fn duplicate_attribute_names_are_rejected() {
+    let err = CopcWriterBuilder::new(header(0))
+        .extra_attribute(ExtraBytesDescriptor::new("a", ExtraBytesType::U8))
+        .extra_attribute(ExtraBytesDescriptor::new("a", ExtraBytesType::F64))
+        .build(Cursor::new(Vec::<u8>::new()))
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err,
+        Error::InvalidWriterConfig(WriterConfigError::InvalidExtraAttribute(name)) if name == "a"
+    ));
+}
+
+#[test]
+fn unsupported_descriptors_do_not_prevent_reading() {
+    // a deprecated array of two unsigned chars
+    let mut descriptor = vec![0; 192];
+    descriptor[2] = 11;
+    descriptor[4..8].copy_from_slice(b"Pair");
+
+    for (data, expected) in [
+        (
+            descriptor,
+            ExtraBytes::new(vec![ExtraBytesDescriptor::new(
+                "Pair",
+                ExtraBytesType::Undocumented(2),
+            )]),
+        ),
+        // not a multiple of the descriptor size
+        (vec![0; 100], ExtraBytes::default()),
+    ] {
+        let mut b = Builder::from(header(2));
+        b.vlrs.push(Vlr {
+            user_id: "LASF_Spec".into(),
+            record_id: 4,
+            description: String::new(),
+            data,
+        });
+        let points: Vec<Point> = (0..100)
+            .map(|i| Point {
+                x: i as f64,
+                y: i as f64,
+                z: i as f64,
+                gps_time: Some(i as f64),
+                extra_bytes: vec![1, 2],
+                ..Default::default()
+            })
+            .collect();
+        let mut buf = Cursor::new(Vec::<u8>::new());
+        {
+            let mut w = CopcWriterBuilder::new(b.into_header().unwrap())
+                .build(&mut buf)
+                .unwrap();
+            w.write(points, 100).unwrap();
+        }
+
+        buf.set_position(0);
+        let mut r = CopcReader::new(buf).unwrap();
+        assert_eq!(r.extra_bytes(), &expected);
+        let read: Vec<Point> = r
+            .points(LodSelection::All, BoundsSelection::All)
+            .unwrap()
+            .collect();
+        assert_eq!(read.len(), 100);
+        assert!(read.iter().all(|p| p.extra_bytes == [1, 2]));
+    }
+}
@@ -0,0 +1,94 @@
+#![cfg(feature = "writer")]
+
+//! Conversion of GeoTIFF CRS keys to the WKT CRS of the COPC file.
+
+use std::io::Cursor;
+
+use copc_rs::{CopcReader, CopcWriterBuilder, CrsOverride, Error};
+use las::point::Format;
+use las::{Builder, Point, Transform, Vector, Vlr};
+
+/// Header with a GeoTIFF key directory holding the `(id, value)` keys
+fn header(keys: &[(u16, u16)]) -> las::Header {
+    let mut b = Builder::from((1u8, 2u8));
+    b.point_format = Format::new(1).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 0.0;
+    raw.max_x = 100.0;
+    raw.min_y = 0.0;
+    raw.max_y = 100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    let mut b2 = Builder::new(raw).unwrap();
+    if !keys.is_empty() {
+        let mut data = vec![1, 1, 0, keys.len() as u16];
+        for (id, value) in keys {
+            data.extend([*id, 0, 1, *value]);
+        }
+        b2.vlrs.push(Vlr {
+            user_id: "LASF_Projection".into(),
+            record_id: 34735,
+            description: String::new(),
+            data: data.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        });
+    }
+    b2.into_header().unwrap()
+}
+
+fn written_wkt(builder: CopcWriterBuilder) -> copc_rs::Result<String> {
+    let mut buf = Cursor::new(Vec::<u8>::new());
+    {
+        let mut w = builder.build(&mut buf)?;
+        let points = (0..10).map(|i| Point {
+            x: i as f64,
+            y: i as f64,
+            z: i as f64,
+            gps_time: Some(i as f64),
+            ..Default::default()
+        });
+        w.write(points, 10)?;
+    }
+    buf.set_position(0);
+    let r = CopcReader::new(buf)?;
+    let wkt = r.header().get_wkt_crs_bytes().unwrap();
+    Ok(String::from_utf8(wkt.to_vec()).unwrap())
+}
+
+#[test]
+fn vertical_crs_gives_a_compound_crs() {
+    // projected WGS 84 / UTM zone 32N with NAVD88 height in US survey feet
+    let header = header(&[(1024, 1), (3072, 32632), (4096, 5703), (4099, 9003)]);
+    let wkt = written_wkt(CopcWriterBuilder::new(header)).unwrap();
+    assert!(wkt.starts_with(r#"COMPD_CS["WGS 84 / UTM zone 32N + EPSG:5703",PROJCS["#));
+    assert!(wkt.contains(r#"VERT_CS["EPSG:5703""#));
+    assert!(wkt.contains("US survey foot"));
+    assert!(wkt.ends_with(r#"AUTHORITY["EPSG","5703"]]]"#));
+}
+
+#[test]
+fn geographic_crs_is_supported() {
+    let header = header(&[(1024, 2), (2048, 4326)]);
+    let wkt = written_wkt(CopcWriterBuilder::new(header)).unwrap();
+    assert_eq!(wkt, crs_definitions::EPSG_4326.wkt);
+}
+
+#[test]
+fn fallback_crs_is_used_without_a_crs() {
+    let err = written_wkt(CopcWriterBuilder::new(header(&[]))).unwrap_err();
+    assert!(matches!(err, Error::NoCRSDefined));
+
+    let builder = CopcWriterBuilder::new(header(&[])).fallback_crs(CrsOverride::Epsg(2056));
+    let wkt = written_wkt(builder).unwrap();
+    assert_eq!(wkt, crs_definitions::EPSG_2056.wkt);
+
+    // the fallback is not used if the header has a crs
+    let builder = CopcWriterBuilder::new(header(&[(1024, 2), (2048, 4326)]))
+        .fallback_crs(CrsOverride::Epsg(2056));
+    let wkt = written_wkt(builder).unwrap();
+    assert_eq!(wkt, crs_definitions::EPSG_4326.wkt);
+}
@@ -0,0 +1,90 @@
+#![cfg(feature = "writer")]
+
+//! Points without gps time must never panic the writer: depending on the
+//! `GpsTimePolicy` they get a synthesized gps time, which is left out of the
+//! gps time range of the COPC info, or are rejected with a typed error.
+
+use std::io::Cursor;
+
+use copc_rs::{CopcReader, CopcWriterBuilder, Error, GpsTimePolicy, PointAddError};
+use las::point::Format;
+use las::{Builder, Point, Transform, Vector, Vlr};
+
+const WKT: &[u8] = b"GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",SPHEROID[\"WGS 84\",6378137,298.257223563]],PRIMEM[\"Greenwich\",0],UNIT[\"degree\",0.0174532925199433]]";
+
+fn header() -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(6).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 0.0;
+    raw.max_x = 100.0;
+    raw.min_y = 0.0;
+    raw.max_y = 100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    let mut b2 = Builder::new(raw).unwrap();
+    b2.vlrs.push(Vlr {
+        user_id: "LASF_Projection".into(),
+        record_id: 2112,
+        description: String::new(),
+        data: WKT.to_vec(),
+    });
+    b2.into_header().unwrap()
+}
+
+// every third point has no gps time
+fn points() -> Vec<Point> {
+    (0..3000)
+        .map(|i| Point {
+            x: (i % 100) as f64,
+            y: (i % 100) as f64,
+            z: (i % 100) as f64,
+            gps_time: (i % 3 != 0).then_some(1000.0 + i as f64),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn write(policy: GpsTimePolicy) -> (copc_rs::Result<()>, Cursor<Vec<u8>>) {
+    let pts = points();
+    let n = pts.len();
+    let mut buf = Cursor::new(Vec::<u8>::new());
+    let result = {
+        let mut w = CopcWriterBuilder::new(header())
+            .gps_time_policy(policy)
+            .build(&mut buf)
+            .unwrap();
+        w.write(pts, n as i32)
+    };
+    buf.set_position(0);
+    (result, buf)
+}
+
+#[test]
+fn synthesized_gps_times_are_not_in_the_copc_info_range() {
+    let (result, buf) = write(GpsTimePolicy::Constant(-5.0));
+    result.unwrap();
+
+    let r = CopcReader::new(buf).unwrap();
+    assert_eq!(r.header().number_of_points(), 3000);
+    assert_eq!(r.copc_info().gpstime_minimum, 1001.0);
+    assert_eq!(r.copc_info().gpstime_maximum, 3999.0);
+}
+
+#[test]
+fn missing_gps_time_is_a_typed_error() {
+    let (result, buf) = write(GpsTimePolicy::Error);
+    assert!(matches!(
+        result,
+        Err(Error::InvalidPoint(PointAddError::MissingGpsTime))
+    ));
+
+    // the points with gps time are still written
+    let r = CopcReader::new(buf).unwrap();
+    assert_eq!(r.header().number_of_points(), 2000);
+}
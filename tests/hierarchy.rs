@@ -0,0 +1,104 @@
+#![cfg(feature = "writer")]
+
+//! Inspection of the hierarchy.
+
+use copc_rs::{CopcReader, CopcWriterBuilder, CrsOverride, VoxelKey};
+use las::point::Format;
+use las::{Builder, Point, Transform, Vector};
+
+fn header() -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(6).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 0.0;
+    raw.max_x = 100.0;
+    raw.min_y = 0.0;
+    raw.max_y = 100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    Builder::new(raw).unwrap().into_header().unwrap()
+}
+
+#[test]
+fn nodes_levels_and_pages() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-hierarchy-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("hierarchy.copc.laz");
+    let points: Vec<Point> = (0..20_000)
+        .map(|i| Point {
+            x: (i % 137) as f64 * 0.7,
+            y: (i % 101) as f64 * 0.9,
+            z: (i % 17) as f64,
+            gps_time: Some(i as f64),
+            ..Default::default()
+        })
+        .collect();
+    {
+        let mut w = CopcWriterBuilder::new(header())
+            .crs(CrsOverride::Epsg(2056))
+            .min_node_size(50)
+            .max_node_size(500)
+            .hierarchy_page_size(9)
+            .seed(1)
+            .build_path(&path)
+            .unwrap();
+        w.write(points.clone(), points.len() as i32).unwrap();
+    }
+    let reader = CopcReader::from_path(&path).unwrap();
+
+    // the nodes are ordered by key and lie in the octree cube
+    let nodes = reader.hierarchy_nodes();
+    assert_eq!(nodes.len(), reader.num_entries());
+    assert!(nodes.windows(2).all(|w| w[0].key < w[1].key));
+    let root = &nodes[0];
+    assert_eq!(root.key.level, 0);
+    let info = reader.copc_info();
+    assert_eq!(root.bounds.min.x, info.center.x - info.halfsize);
+    assert_eq!(root.bounds.max.z, info.center.z + info.halfsize);
+    let record_length = reader.header().point_format().len() as f64;
+    for node in &nodes {
+        let expected = (node.point_count > 0)
+            .then(|| node.point_count as f64 * record_length / node.byte_size as f64);
+        assert_eq!(node.compression_ratio, expected);
+    }
+
+    // the levels add up to the whole file
+    let levels = reader.level_summaries();
+    assert_eq!(levels[0].level, 0);
+    assert_eq!(levels[0].nodes, 1);
+    assert_eq!(
+        levels.iter().map(|l| l.points).sum::<u64>(),
+        points.len() as u64
+    );
+    assert_eq!(levels.iter().map(|l| l.nodes).sum::<usize>(), nodes.len());
+    let bytes: u64 = nodes.iter().map(|n| n.byte_size).sum();
+    assert_eq!(levels.iter().map(|l| l.bytes).sum::<u64>(), bytes);
+    assert!(points.len() as f64 * record_length > bytes as f64);
+
+    // the root page and its child pages, an entry for each node and child page
+    let pages = reader.hierarchy_pages();
+    assert!(pages.len() > 1);
+    assert_eq!(pages[0].offset, info.root_hier_offset);
+    assert_eq!(pages[0].byte_size, info.root_hier_size);
+    assert_eq!(pages[0].key, VoxelKey::root());
+    let entries: u64 = pages.iter().map(|p| p.entry_count()).sum();
+    assert_eq!(entries, (nodes.len() + pages.len() - 1) as u64);
+
+    // the dumps
+    let dot = reader.hierarchy_dot();
+    assert!(dot.starts_with("digraph hierarchy {"));
+    assert!(dot.contains("subgraph cluster_1 {"));
+    assert!(dot.contains("\"0-0-0-0\" -> \"1-"));
+    assert_eq!(dot.matches(" -> ").count(), nodes.len() - 1);
+    let json = reader.hierarchy_json();
+    assert!(json.starts_with("{\"nodes\": [{\"key\": \"0-0-0-0\", \"level\": 0"));
+    assert_eq!(json.matches("\"compression_ratio\"").count(), nodes.len());
+    assert_eq!(json.matches("\"entries\"").count(), pages.len());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
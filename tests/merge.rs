@@ -0,0 +1,116 @@
+#![cfg(feature = "writer")]
+
+//! Merging of LAS files with different headers into one COPC file with `merge`.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use copc_rs::{merge, BoundsSelection, CopcReader, Error, LodSelection, MergeOptions};
+use las::point::Format;
+use las::{Builder, Color, Point, Transform, Vector, Vlr};
+
+const WKT: &[u8] = b"GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",SPHEROID[\"WGS 84\",6378137,298.257223563]],PRIMEM[\"Greenwich\",0],UNIT[\"degree\",0.0174532925199433]]";
+
+fn header(pdrf: u8, extra_bytes: u16, scale: f64, wkt: &[u8]) -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(pdrf).unwrap();
+    b.point_format.extra_bytes = extra_bytes;
+    let t = Transform { scale, offset: 0.0 };
+    b.transforms = Vector { x: t, y: t, z: t };
+    b.vlrs.push(Vlr {
+        user_id: "LASF_Projection".into(),
+        record_id: 2112,
+        description: String::new(),
+        data: wkt.to_vec(),
+    });
+    b.into_header().unwrap()
+}
+
+fn write_las(path: &Path, header: las::Header, offset: f64, n: usize) {
+    let format = *header.point_format();
+    let mut w = las::Writer::from_path(path, header).unwrap();
+    for i in 0..n {
+        w.write_point(Point {
+            x: offset + (i % 50) as f64,
+            y: offset + (i / 50) as f64,
+            z: (i % 7) as f64,
+            gps_time: format.has_gps_time.then_some(i as f64),
+            color: format.has_color.then(|| Color::new(1, 2, 3)),
+            extra_bytes: vec![7; format.extra_bytes as usize],
+            ..Default::default()
+        })
+        .unwrap();
+    }
+    w.close().unwrap();
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("copc-rs-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn merges_inputs_with_different_headers() {
+    let dir = temp_dir("merge");
+    let inputs = [dir.join("a.las"), dir.join("b.las")];
+    write_las(&inputs[0], header(1, 0, 0.01, WKT), 0.0, 1000);
+    write_las(&inputs[1], header(3, 2, 0.001, WKT), 100.0, 500);
+    let output = dir.join("merged.copc.laz");
+
+    let options = MergeOptions {
+        tag_source: true,
+        ..Default::default()
+    };
+    let summary = merge(&inputs, &output, &options).unwrap();
+    assert_eq!(summary.points_read, 1500);
+    assert_eq!(summary.points_written, 1500);
+    assert_eq!(summary.rejected.total(), 0);
+
+    let mut r = CopcReader::from_path(&output).unwrap();
+    let format = *r.header().point_format();
+    assert_eq!(format.to_u8().unwrap(), 7);
+    assert_eq!(format.extra_bytes, 2);
+    assert_eq!(r.header().transforms().x.scale, 0.001);
+
+    let points: Vec<Point> = r
+        .points(LodSelection::All, BoundsSelection::All)
+        .unwrap()
+        .collect();
+    assert_eq!(points.len(), 1500);
+    let sources: HashSet<u16> = points.iter().map(|p| p.point_source_id).collect();
+    assert_eq!(sources, HashSet::from([0, 1]));
+    for p in &points {
+        if p.point_source_id == 0 {
+            assert_eq!(p.extra_bytes, vec![0, 0]);
+        } else {
+            assert_eq!(p.extra_bytes, vec![7, 7]);
+            assert_eq!(p.color, Some(Color::new(1, 2, 3)));
+        }
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn different_crs_are_rejected() {
+    let dir = temp_dir("merge-crs");
+    let inputs = [dir.join("a.las"), dir.join("b.las")];
+    write_las(&inputs[0], header(6, 0, 0.01, WKT), 0.0, 10);
+    write_las(
+        &inputs[1],
+        header(6, 0, 0.01, b"LOCAL_CS[\"local\"]"),
+        0.0,
+        10,
+    );
+
+    let err = merge(
+        &inputs,
+        dir.join("merged.copc.laz"),
+        &MergeOptions::default(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, Error::MergeCrsMismatch(p) if p == inputs[1]));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,99 @@
+#![cfg(feature = "writer")]
+
+//! Every LAS point data record format can be written to COPC: formats without
+//! gps time get a synthesized one and waveform packets are dropped.
+
+use std::io::Cursor;
+
+use copc_rs::{BoundsSelection, CopcReader, CopcWriter, LodSelection};
+use las::point::Format;
+use las::raw::point::Waveform;
+use las::{Builder, Point, Transform, Vector, Vlr};
+
+const WKT: &[u8] = b"GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",SPHEROID[\"WGS 84\",6378137,298.257223563]],PRIMEM[\"Greenwich\",0],UNIT[\"degree\",0.0174532925199433]]";
+
+fn header(format: u8, version: (u8, u8)) -> las::Header {
+    let mut b = Builder::from(version);
+    b.point_format = Format::new(format).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 0.0;
+    raw.max_x = 100.0;
+    raw.min_y = 0.0;
+    raw.max_y = 100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    let mut b2 = Builder::new(raw).unwrap();
+    b2.vlrs.push(Vlr {
+        user_id: "LASF_Projection".into(),
+        record_id: 2112,
+        description: String::new(),
+        data: WKT.to_vec(),
+    });
+    b2.into_header().unwrap()
+}
+
+fn write_and_read(header: las::Header, pts: Vec<Point>) -> (Format, Vec<Point>) {
+    let n = pts.len();
+    let mut buf = Cursor::new(Vec::<u8>::new());
+    {
+        let mut w = CopcWriter::new(&mut buf, header, -1, -1).unwrap();
+        w.write(pts, n as i32).unwrap();
+    }
+
+    buf.set_position(0);
+    let mut r = CopcReader::new(buf).unwrap();
+    let format = *r.header().point_format();
+    let read = r
+        .points(LodSelection::All, BoundsSelection::All)
+        .unwrap()
+        .collect();
+    (format, read)
+}
+
+#[test]
+fn pdrf_2_is_converted_to_7_with_synthesized_gps_time() {
+    let pts: Vec<Point> = (0..300)
+        .map(|i| Point {
+            x: (i % 100) as f64,
+            y: (i % 100) as f64,
+            z: (i % 100) as f64,
+            color: Some(las::Color::new(1, 2, 3)),
+            ..Default::default()
+        })
+        .collect();
+
+    let (format, mut read) = write_and_read(header(2, (1, 2)), pts);
+    assert_eq!(format.to_u8().unwrap(), 7);
+    assert_eq!(read.len(), 300);
+
+    read.sort_by(|a, b| a.gps_time.partial_cmp(&b.gps_time).unwrap());
+    for (i, p) in read.iter().enumerate() {
+        assert_eq!(p.gps_time, Some(i as f64));
+        assert_eq!(p.x, (i % 100) as f64);
+        assert_eq!(p.color, Some(las::Color::new(1, 2, 3)));
+    }
+}
+
+#[test]
+fn pdrf_4_is_converted_to_6_without_waveforms() {
+    let pts: Vec<Point> = (0..300)
+        .map(|i| Point {
+            x: (i % 100) as f64,
+            y: (i % 100) as f64,
+            z: (i % 100) as f64,
+            gps_time: Some(1000.0 + i as f64),
+            waveform: Some(Waveform::default()),
+            ..Default::default()
+        })
+        .collect();
+
+    let (format, read) = write_and_read(header(4, (1, 3)), pts);
+    assert_eq!(format.to_u8().unwrap(), 6);
+    assert_eq!(read.len(), 300);
+    assert!(read.iter().all(|p| p.waveform.is_none()));
+}
@@ -0,0 +1,203 @@
+#![cfg(feature = "writer")]
+
+//! Export of the octree to a Potree 2.0 dataset.
+
+use std::io::Cursor;
+
+use byteorder::{ByteOrder, LittleEndian};
+use copc_rs::{
+    CopcReader, CopcWriterBuilder, CrsOverride, ExtraBytes, ExtraBytesDescriptor, ExtraBytesType,
+    PotreeOptions,
+};
+use las::point::Format;
+use las::{Builder, Point, Transform, Vector};
+
+fn header() -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(7).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 0.0;
+    raw.max_x = 100.0;
+    raw.min_y = 0.0;
+    raw.max_y = 100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    Builder::new(raw).unwrap().into_header().unwrap()
+}
+
+/// A node of `hierarchy.bin`
+struct Node {
+    name: String,
+    node_type: u8,
+    child_mask: u8,
+    point_count: u32,
+    offset: u64,
+    size: u64,
+}
+
+/// Reads the nodes of a chunk of `hierarchy.bin` like the Potree loader,
+/// the chunk starts with the node `name`
+fn read_chunk(hierarchy: &[u8], name: &str, offset: u64, size: u64) -> Vec<Node> {
+    let chunk = &hierarchy[offset as usize..(offset + size) as usize];
+    let mut names = vec![name.to_string()];
+    let mut nodes = vec![];
+    for (i, record) in chunk.chunks(22).enumerate() {
+        let node = Node {
+            name: names[i].clone(),
+            node_type: record[0],
+            child_mask: record[1],
+            point_count: LittleEndian::read_u32(&record[2..6]),
+            offset: LittleEndian::read_u64(&record[6..14]),
+            size: LittleEndian::read_u64(&record[14..22]),
+        };
+        if node.node_type != 2 {
+            for child in 0..8 {
+                if node.child_mask & (1 << child) != 0 {
+                    names.push(format!("{}{child}", node.name));
+                }
+            }
+        }
+        nodes.push(node);
+    }
+    assert_eq!(names.len(), nodes.len());
+    nodes
+}
+
+#[test]
+fn export_to_potree() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-potree-{}", std::process::id()));
+    let layout = ExtraBytes::new(vec![ExtraBytesDescriptor::new(
+        "Amplitude",
+        ExtraBytesType::F32,
+    )]);
+    let mut buf = Cursor::new(Vec::<u8>::new());
+    {
+        let mut w = CopcWriterBuilder::new(header())
+            .crs(CrsOverride::Epsg(2056))
+            .extra_attribute(layout.descriptors()[0].clone())
+            .min_node_size(10)
+            .max_node_size(100)
+            .seed(1)
+            .build(&mut buf)
+            .unwrap();
+        let points = (0..20_000).map(|i| {
+            let mut p = Point {
+                x: (i % 100) as f64 + 0.25,
+                y: (i / 200) as f64,
+                z: (i % 7) as f64,
+                intensity: (i % 500) as u16,
+                gps_time: Some(i as f64),
+                color: Some(las::Color::new(256, 512, 65535)),
+                extra_bytes: vec![0; 4],
+                ..Default::default()
+            };
+            layout.set(&mut p, "Amplitude", i as f64 / 2.);
+            p
+        });
+        w.write(points, 20_000).unwrap();
+    }
+    let mut reader = CopcReader::new(Cursor::new(buf.into_inner())).unwrap();
+    let summary = reader
+        .export_potree(
+            &dir,
+            &PotreeOptions {
+                hierarchy_step_size: 2,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(summary.points_written, 20_000);
+    assert!(summary.depth >= 2);
+
+    // position 12, intensity 2, returns 2, classification 1, scan angle 2, user data 1,
+    // point source id 2, gps time 8, rgb 6, amplitude 4
+    let point_size = 40;
+    let octree = std::fs::read(dir.join("octree.bin")).unwrap();
+    assert_eq!(octree.len(), 20_000 * point_size);
+
+    let metadata = std::fs::read_to_string(dir.join("metadata.json")).unwrap();
+    assert!(metadata.starts_with(r#"{"version": "2.0", "name": "copc-rs-potree-"#));
+    assert!(metadata.contains(r#""points": 20000"#));
+    assert!(metadata.contains(r#""encoding": "DEFAULT""#));
+    assert!(metadata.contains(r#""stepSize": 2"#));
+    assert!(metadata.contains(&format!(r#""depth": {}"#, summary.depth)));
+    assert!(metadata.contains(r#""scale": [0.01, 0.01, 0.01]"#));
+    assert!(metadata.contains(
+        r#"{"name": "rgb", "description": "", "size": 6, "numElements": 3, "elementSize": 2, "type": "uint16", "min": [256, 512, 65535], "max": [256, 512, 65535]}"#
+    ));
+    assert!(metadata.contains(r#""name": "Amplitude", "description": "", "size": 4, "numElements": 1, "elementSize": 4, "type": "float", "min": [0], "max": [9999.5]"#));
+    assert!(metadata.contains(r#""name": "position", "description": "", "size": 12, "numElements": 3, "elementSize": 4, "type": "int32", "min": [0.25, 0, 0], "max": [99.25, 99, 6]"#));
+    let first_chunk_size: u64 = metadata
+        .split(r#""firstChunkSize": "#)
+        .nth(1)
+        .unwrap()
+        .split(',')
+        .next()
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    // load all chunks from the root like the viewer
+    let hierarchy = std::fs::read(dir.join("hierarchy.bin")).unwrap();
+    let mut chunks = vec![("r".to_string(), 0, first_chunk_size)];
+    let mut nodes = vec![];
+    let mut proxies = 0;
+    while let Some((name, offset, size)) = chunks.pop() {
+        for (i, node) in read_chunk(&hierarchy, &name, offset, size)
+            .into_iter()
+            .enumerate()
+        {
+            if node.node_type == 2 {
+                assert!(i > 0);
+                chunks.push((node.name, node.offset, node.size));
+                proxies += 1;
+            } else {
+                assert_eq!(node.node_type == 1, node.child_mask == 0);
+                nodes.push(node);
+            }
+        }
+    }
+    assert!(proxies > 0);
+    assert_eq!(nodes.len(), summary.nodes_written);
+    assert_eq!(nodes.len(), reader.hierarchy_entries().len());
+    assert_eq!(
+        nodes.iter().map(|n| n.point_count as u64).sum::<u64>(),
+        20_000
+    );
+    assert_eq!(
+        nodes.iter().map(|n| n.size).sum::<u64>(),
+        octree.len() as u64
+    );
+
+    // the points of a node are in its cube
+    for node in &nodes {
+        assert_eq!(node.size, node.point_count as u64 * point_size as u64);
+        let (center, halfsize) = (reader.copc_info().center, reader.copc_info().halfsize);
+        let mut min = [
+            center.x - halfsize,
+            center.y - halfsize,
+            center.z - halfsize,
+        ];
+        let mut size = halfsize * 2.;
+        for child in node.name[1..].chars().map(|c| c.to_digit(10).unwrap()) {
+            size /= 2.;
+            min[0] += ((child >> 2) & 1) as f64 * size;
+            min[1] += ((child >> 1) & 1) as f64 * size;
+            min[2] += (child & 1) as f64 * size;
+        }
+        let data = &octree[node.offset as usize..(node.offset + node.size) as usize];
+        for point in data.chunks(point_size) {
+            for (axis, min) in min.iter().enumerate() {
+                let value = LittleEndian::read_i32(&point[axis * 4..]) as f64 * 0.01;
+                assert!(value >= min - 0.01 && value <= min + size + 0.01);
+            }
+        }
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
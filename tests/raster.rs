@@ -0,0 +1,377 @@
+#![cfg(feature = "writer")]
+
+//! Raster products of the points.
+
+use std::collections::HashMap;
+
+use byteorder::{ByteOrder, LittleEndian};
+use copc_rs::{
+    BoundsSelection, CopcReader, CopcWriterBuilder, CrsOverride, Error, LodSelection, RasterFormat,
+    RasterOptions, RasterProduct,
+};
+use las::point::{Classification, Format};
+use las::{Bounds, Builder, Point, Transform, Vector};
+
+fn header() -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(6).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 0.0;
+    raw.max_x = 100.0;
+    raw.min_y = 0.0;
+    raw.max_y = 100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 200.0;
+    Builder::new(raw).unwrap().into_header().unwrap()
+}
+
+/// A GeoTIFF read like a TIFF reader: the values of the tags and the samples of the strips
+struct GeoTiff {
+    width: usize,
+    height: usize,
+    tags: HashMap<u16, Vec<u8>>,
+    values: Vec<f32>,
+}
+
+impl GeoTiff {
+    fn read(bytes: &[u8]) -> Self {
+        assert_eq!(&bytes[..4], b"II*\0");
+        let ifd = LittleEndian::read_u32(&bytes[4..8]) as usize;
+        let count = LittleEndian::read_u16(&bytes[ifd..]) as usize;
+        let mut tags = HashMap::new();
+        for entry in bytes[ifd + 2..ifd + 2 + 12 * count].chunks(12) {
+            let tag = LittleEndian::read_u16(&entry[0..2]);
+            let size = match LittleEndian::read_u16(&entry[2..4]) {
+                2 => 1,
+                3 => 2,
+                4 => 4,
+                12 => 8,
+                t => panic!("unexpected type {t}"),
+            } * LittleEndian::read_u32(&entry[4..8]) as usize;
+            let value = if size <= 4 {
+                entry[8..8 + size].to_vec()
+            } else {
+                let offset = LittleEndian::read_u32(&entry[8..12]) as usize;
+                bytes[offset..offset + size].to_vec()
+            };
+            tags.insert(tag, value);
+        }
+        let long = |tag: u16| -> Vec<usize> {
+            tags[&tag]
+                .chunks(4)
+                .map(|v| LittleEndian::read_u32(v) as usize)
+                .collect()
+        };
+        let mut values = vec![];
+        for (offset, size) in long(273).into_iter().zip(long(279)) {
+            values.extend(
+                bytes[offset..offset + size]
+                    .chunks(4)
+                    .map(LittleEndian::read_f32),
+            );
+        }
+        GeoTiff {
+            width: long(256)[0],
+            height: long(257)[0],
+            values,
+            tags,
+        }
+    }
+
+    fn shorts(&self, tag: u16) -> Vec<u16> {
+        self.tags[&tag]
+            .chunks(2)
+            .map(LittleEndian::read_u16)
+            .collect()
+    }
+
+    fn doubles(&self, tag: u16) -> Vec<f64> {
+        self.tags[&tag]
+            .chunks(8)
+            .map(LittleEndian::read_f64)
+            .collect()
+    }
+
+    fn value(&self, column: usize, row: usize) -> f32 {
+        self.values[row * self.width + column]
+    }
+}
+
+/// Writes a point in the middle of every unit square of 100 x 100, the even columns are ground
+fn write_input(input: &std::path::Path) -> Vec<Point> {
+    let points: Vec<Point> = (0..10_000)
+        .map(|i| {
+            let x = (i % 100) as f64 + 0.5;
+            let y = (i / 100) as f64 + 0.5;
+            Point {
+                x,
+                y,
+                z: x + y,
+                intensity: (x as u16 / 10) * 100,
+                return_number: 1,
+                number_of_returns: 1,
+                classification: if i % 2 == 0 {
+                    Classification::Ground
+                } else {
+                    Classification::HighVegetation
+                },
+                gps_time: Some(i as f64),
+                ..Default::default()
+            }
+        })
+        .collect();
+    {
+        let mut w = CopcWriterBuilder::new(header())
+            .crs(CrsOverride::Epsg(2056))
+            .min_node_size(50)
+            .max_node_size(500)
+            .seed(1)
+            .build_path(input)
+            .unwrap();
+        w.write(points.clone(), points.len() as i32).unwrap();
+    }
+    points
+}
+
+#[test]
+fn dsm_dtm_density_and_intensity() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-raster-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.copc.laz");
+    let points = write_input(&input);
+    let mut reader = CopcReader::from_path(&input).unwrap();
+    // two columns of cells without points on the right
+    let bounds = Bounds {
+        min: Vector {
+            x: 0.,
+            y: 0.,
+            z: 0.,
+        },
+        max: Vector {
+            x: 120.,
+            y: 100.,
+            z: 200.,
+        },
+    };
+    let options = RasterOptions {
+        cell_size: 10.,
+        bounds: Some(bounds),
+        tile_size: 4,
+        lod: Some(LodSelection::All),
+        ..Default::default()
+    };
+
+    // DSM
+    let path = dir.join("dsm.tif");
+    let summary = reader.export_raster(&path, &options).unwrap();
+    assert_eq!((summary.width, summary.height), (12, 10));
+    assert_eq!(summary.points_used, points.len() as u64);
+    assert_eq!(summary.cells_with_data, 100);
+    let dsm = GeoTiff::read(&std::fs::read(&path).unwrap());
+    assert_eq!((dsm.width, dsm.height), (12, 10));
+    assert_eq!(dsm.values.len(), 120);
+    for row in 0..10 {
+        for column in 0..10 {
+            // the top right point of the cell
+            let expected = 10. * column as f32 + 9.5 + 99.5 - 10. * row as f32;
+            assert_eq!(dsm.value(column, row), expected);
+        }
+        assert_eq!(dsm.value(10, row), -9999.);
+        assert_eq!(dsm.value(11, row), -9999.);
+    }
+    assert_eq!(dsm.shorts(339), vec![3]);
+    assert_eq!(dsm.doubles(33550), vec![10., 10., 0.]);
+    assert_eq!(dsm.doubles(33922), vec![0., 0., 0., 0., 100., 0.]);
+    let keys = dsm.shorts(34735);
+    assert!(keys.chunks(4).any(|key| key == [3072, 0, 1, 2056]));
+    assert_eq!(dsm.tags[&42113], b"-9999\0");
+
+    // DTM of the ground points, in the even columns
+    let path = dir.join("dtm.tif");
+    let summary = reader
+        .export_raster(
+            &path,
+            &RasterOptions {
+                product: RasterProduct::Dtm,
+                ..options
+            },
+        )
+        .unwrap();
+    assert_eq!(summary.points_used, points.len() as u64 / 2);
+    let dtm = GeoTiff::read(&std::fs::read(&path).unwrap());
+    assert_eq!(dtm.value(3, 2), 30.5 + 70.5);
+
+    // density as a raw array with a world file
+    let path = dir.join("density.bin");
+    reader
+        .export_raster(
+            &path,
+            &RasterOptions {
+                product: RasterProduct::Density,
+                format: RasterFormat::Raw,
+                ..options
+            },
+        )
+        .unwrap();
+    let density: Vec<f32> = std::fs::read(&path)
+        .unwrap()
+        .chunks(4)
+        .map(LittleEndian::read_f32)
+        .collect();
+    assert_eq!(density.len(), 120);
+    assert_eq!(density[0], 1.);
+    assert_eq!(density[11], 0.);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("density.wld")).unwrap(),
+        "10\n0\n0\n-10\n5\n95\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("density.prj")).unwrap(),
+        reader.crs_wkt().unwrap()
+    );
+
+    let path = dir.join("intensity.tif");
+    reader
+        .export_raster(
+            &path,
+            &RasterOptions {
+                product: RasterProduct::Intensity,
+                ..options
+            },
+        )
+        .unwrap();
+    let intensity = GeoTiff::read(&std::fs::read(&path).unwrap());
+    assert_eq!(intensity.value(7, 4), 700.);
+
+    // the offsets of a GeoTIFF larger than 4 GiB do not fit in 32 bits
+    let path = dir.join("too-large.tif");
+    let too_large = reader.export_raster(
+        &path,
+        &RasterOptions {
+            cell_size: 0.001,
+            ..options
+        },
+    );
+    assert!(matches!(
+        too_large,
+        Err(Error::RasterTooLarge(120_000, 100_000))
+    ));
+    assert!(!path.exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn overview_reads_the_upper_levels() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-raster-lod-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.copc.laz");
+    let points = write_input(&input);
+    let mut reader = CopcReader::from_path(&input).unwrap();
+    let spacing = reader.copc_info().spacing;
+    assert!(spacing > 0.);
+
+    // cells as large as the spacing of the root node only read the root node
+    let cell_size = 2. * spacing;
+    let root_points = reader
+        .points(LodSelection::Level(0), BoundsSelection::All)
+        .unwrap()
+        .count() as u64;
+    assert!(root_points < points.len() as u64);
+    let summary = reader
+        .export_raster(
+            dir.join("overview.tif"),
+            &RasterOptions {
+                cell_size,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(summary.lod, LodSelection::Resolution(cell_size));
+    assert_eq!(summary.points_used, root_points);
+
+    // finer cells read more levels
+    let summary = reader
+        .export_raster(
+            dir.join("fine.tif"),
+            &RasterOptions {
+                cell_size: spacing / 2.,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let expected = reader
+        .points(LodSelection::LevelMinMax(0, 2), BoundsSelection::All)
+        .unwrap()
+        .count() as u64;
+    assert_eq!(summary.points_used, expected);
+    assert!(summary.points_used > root_points);
+
+    // the density counts all points
+    let summary = reader
+        .export_raster(
+            dir.join("density.tif"),
+            &RasterOptions {
+                product: RasterProduct::Density,
+                cell_size,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(summary.lod, LodSelection::All);
+    assert_eq!(summary.points_used, points.len() as u64);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn long_wkt_citation_is_left_out() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-raster-wkt-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.copc.laz");
+    // the longest WKT fitting in a VLR, without EPSG code
+    let prefix = "LOCAL_CS[\"";
+    let suffix = "\",UNIT[\"metre\",1]]";
+    let wkt = format!(
+        "{prefix}{}{suffix}",
+        "a".repeat(u16::MAX as usize - prefix.len() - suffix.len())
+    );
+    {
+        let mut w = CopcWriterBuilder::new(header())
+            .crs(CrsOverride::Wkt(wkt))
+            .build_path(&input)
+            .unwrap();
+        let points = (0..100).map(|i| Point {
+            x: i as f64,
+            y: i as f64,
+            z: 1.,
+            ..Default::default()
+        });
+        w.write(points, 100).unwrap();
+    }
+    let mut reader = CopcReader::from_path(&input).unwrap();
+    assert_eq!(reader.crs_epsg(), None);
+    let path = dir.join("dsm.tif");
+    reader
+        .export_raster(
+            &path,
+            &RasterOptions {
+                cell_size: 10.,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let dsm = GeoTiff::read(&std::fs::read(&path).unwrap());
+    assert!(!dsm.tags.contains_key(&34737));
+    let keys = dsm.shorts(34735);
+    // the user-defined projected CRS without citation
+    assert!(keys.chunks(4).any(|key| key == [3072, 0, 1, 32767]));
+    assert!(!keys.chunks(4).any(|key| key[0] == 1026));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
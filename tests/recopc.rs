@@ -0,0 +1,103 @@
+#![cfg(feature = "writer")]
+
+//! Re-indexing of an existing COPC file with other node sizes with `recopc`.
+
+use copc_rs::{
+    recopc, BoundsSelection, ConvertOptions, CopcReader, CopcWriterBuilder, LodSelection,
+    SamplingStrategy,
+};
+use las::point::Format;
+use las::{Builder, Point, Transform, Vector, Vlr};
+
+const WKT: &[u8] = b"GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",SPHEROID[\"WGS 84\",6378137,298.257223563]],PRIMEM[\"Greenwich\",0],UNIT[\"degree\",0.0174532925199433]]";
+
+fn header() -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(6).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 0.0;
+    raw.max_x = 100.0;
+    raw.min_y = 0.0;
+    raw.max_y = 100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    let mut b2 = Builder::new(raw).unwrap();
+    b2.vlrs.push(Vlr {
+        user_id: "LASF_Projection".into(),
+        record_id: 2112,
+        description: String::new(),
+        data: WKT.to_vec(),
+    });
+    b2.into_header().unwrap()
+}
+
+fn points(n: usize) -> Vec<Point> {
+    (0..n)
+        .map(|i| Point {
+            x: (i % 100) as f64,
+            y: ((i / 100) % 100) as f64,
+            z: ((i * 7) % 100) as f64,
+            gps_time: Some(1000.0 + i as f64),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn sorted_coords(points: impl Iterator<Item = Point>) -> Vec<(i64, i64, i64)> {
+    let mut coords: Vec<_> = points
+        .map(|p| {
+            (
+                (p.x * 100.0).round() as i64,
+                (p.y * 100.0).round() as i64,
+                (p.z * 100.0).round() as i64,
+            )
+        })
+        .collect();
+    coords.sort_unstable();
+    coords
+}
+
+#[test]
+fn recopc_changes_the_node_sizes() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-recopc-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("small-nodes.copc.laz");
+    let output = dir.join("large-nodes.copc.laz");
+
+    let pts = points(3000);
+    let n = pts.len();
+    let small_nodes = {
+        let mut w = CopcWriterBuilder::new(header())
+            .min_node_size(10)
+            .max_node_size(50)
+            .sampling(SamplingStrategy::Greedy)
+            .build_path(&input)
+            .unwrap();
+        w.write(pts.clone(), n as i32).unwrap();
+        w.hierarchy_entries().entries.len()
+    };
+
+    let options = ConvertOptions {
+        min_node_size: 100,
+        max_node_size: 1000,
+        seed: Some(3),
+        hierarchy_page_size: Some(9),
+        recompute_bounds: false,
+        ..Default::default()
+    };
+    let summary = recopc(&input, &output, &options).unwrap();
+    assert_eq!(summary.points_read, n as u64);
+    assert_eq!(summary.points_written, n as u64);
+    assert!(summary.node_count < small_nodes);
+
+    let mut r = CopcReader::from_path(&output).unwrap();
+    let read = r.points(LodSelection::All, BoundsSelection::All).unwrap();
+    assert_eq!(sorted_coords(read), sorted_coords(pts.into_iter()));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,218 @@
+#![cfg(feature = "writer")]
+
+//! In place repair and rewrite of defective COPC files.
+
+use std::path::Path;
+
+use copc_rs::{
+    repair, validate_path, BoundsSelection, CopcReader, CopcWriterBuilder, CrsOverride, Error,
+    LodSelection, RepairOptions, ValidationCheck,
+};
+use las::point::Format;
+use las::{Builder, Point, Transform, Vector};
+
+fn header() -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(6).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 0.0;
+    raw.max_x = 100.0;
+    raw.min_y = 0.0;
+    raw.max_y = 100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    Builder::new(raw).unwrap().into_header().unwrap()
+}
+
+/// Offset of the COPC info VLR data, after the header and the VLR header
+const COPC_INFO: usize = 375 + 54;
+
+fn write_copc(path: &Path) {
+    let mut w = CopcWriterBuilder::new(header())
+        .crs(CrsOverride::Epsg(2056))
+        .min_node_size(10)
+        .max_node_size(100)
+        .hierarchy_page_size(9)
+        .seed(1)
+        .build_path(path)
+        .unwrap();
+    let points = (0..2000).map(|i| Point {
+        x: (i % 100) as f64,
+        y: (i / 20) as f64,
+        z: (i % 7) as f64,
+        return_number: 1 + (i % 3) as u8,
+        number_of_returns: 3,
+        gps_time: Some(i as f64),
+        ..Default::default()
+    });
+    w.write(points, 2000).unwrap();
+}
+
+fn patch(path: &Path, offset: usize, bytes: &[u8]) {
+    let mut data = std::fs::read(path).unwrap();
+    data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    std::fs::write(path, data).unwrap();
+}
+
+fn point_count(path: &Path) -> usize {
+    let mut r = CopcReader::from_path(path).unwrap();
+    r.points(LodSelection::All, BoundsSelection::All)
+        .unwrap()
+        .count()
+}
+
+#[test]
+fn metadata_is_repaired_in_place() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-repair-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("in-place.copc.laz");
+    write_copc(&path);
+
+    // a valid file is not changed
+    let original = std::fs::read(&path).unwrap();
+    let summary = repair(&path, &RepairOptions::default()).unwrap();
+    assert_eq!(summary.fixes, vec![]);
+    assert_eq!(std::fs::read(&path).unwrap(), original);
+    let spacing = CopcReader::from_path(&path).unwrap().copc_info().spacing;
+
+    // number of point records of the LAS 1.4 header
+    patch(&path, 247, &1234u64.to_le_bytes());
+    // spacing, root hierarchy offset and gps time maximum of the COPC info
+    patch(&path, COPC_INFO + 32, &0f64.to_le_bytes());
+    patch(&path, COPC_INFO + 40, &7u64.to_le_bytes());
+    patch(&path, COPC_INFO + 64, &5f64.to_le_bytes());
+    assert!(!validate_path(&path).unwrap().is_valid());
+
+    let options = RepairOptions {
+        allow_rewrite: false,
+        ..Default::default()
+    };
+    let summary = repair(&path, &options).unwrap();
+    assert!(!summary.rewritten);
+    let fixed: Vec<ValidationCheck> = summary.fixes.iter().map(|f| f.check).collect();
+    assert!(fixed.contains(&ValidationCheck::Hierarchy));
+    assert!(fixed.contains(&ValidationCheck::PointCount));
+    assert!(fixed.contains(&ValidationCheck::GpsTime));
+    assert!(fixed.contains(&ValidationCheck::CopcInfo));
+
+    let report = validate_path(&path).unwrap();
+    assert!(report.is_valid(), "{:?}", report.issues);
+    assert_eq!(point_count(&path), 2000);
+    let r = CopcReader::from_path(&path).unwrap();
+    assert_eq!(r.header().number_of_points(), 2000);
+    assert_eq!(r.copc_info().gpstime_maximum, 1999.);
+    // the spacing of the writer
+    assert_eq!(r.copc_info().spacing, spacing);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn points_outside_their_node_need_a_rewrite() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-repair-rewrite-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("rewrite.copc.laz");
+    write_copc(&path);
+
+    // moving the octree center moves the node bounds away from their points
+    let center_x = f64::from_le_bytes(
+        std::fs::read(&path).unwrap()[COPC_INFO..COPC_INFO + 8]
+            .try_into()
+            .unwrap(),
+    );
+    patch(&path, COPC_INFO, &(center_x + 30.).to_le_bytes());
+    let report = validate_path(&path).unwrap();
+    assert!(report
+        .errors()
+        .any(|i| i.check == ValidationCheck::PointBounds));
+
+    let options = RepairOptions {
+        allow_rewrite: false,
+        ..Default::default()
+    };
+    let err = repair(&path, &options).unwrap_err();
+    assert!(matches!(err, Error::RepairNeedsRewrite(_)));
+
+    let summary = repair(&path, &RepairOptions::default()).unwrap();
+    assert!(summary.rewritten);
+    let report = validate_path(&path).unwrap();
+    assert!(report.is_valid(), "{:?}", report.issues);
+    assert_eq!(point_count(&path), 2000);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn undecodable_chunks_are_dropped() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-repair-chunk-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("chunk.copc.laz");
+    write_copc(&path);
+
+    // the first node of the root hierarchy page points after the end of the file
+    let data = std::fs::read(&path).unwrap();
+    let root_hier_offset =
+        u64::from_le_bytes(data[COPC_INFO + 40..COPC_INFO + 48].try_into().unwrap()) as usize;
+    let entry = (0..9)
+        .map(|i| root_hier_offset + 32 * i)
+        .find(|e| i32::from_le_bytes(data[e + 28..e + 32].try_into().unwrap()) > 0)
+        .unwrap();
+    let dropped = i32::from_le_bytes(data[entry + 28..entry + 32].try_into().unwrap());
+    patch(&path, entry + 16, &(2 * data.len() as u64).to_le_bytes());
+
+    let summary = repair(&path, &RepairOptions::default()).unwrap();
+    assert!(summary.rewritten);
+    assert!(summary
+        .fixes
+        .iter()
+        .any(|f| f.message.starts_with("dropped the points of the node")));
+    let report = validate_path(&path).unwrap();
+    assert!(report.is_valid(), "{:?}", report.issues);
+    assert_eq!(point_count(&path), 2000 - dropped as usize);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_crs_needs_a_fallback() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-repair-crs-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("crs.copc.laz");
+    write_copc(&path);
+
+    // the WKT VLR is renamed
+    let data = std::fs::read(&path).unwrap();
+    let user_id = data
+        .windows(15)
+        .position(|w| w == b"LASF_Projection")
+        .unwrap();
+    patch(&path, user_id, b"LASF_Xrojection");
+    let is_missing = |path: &Path| {
+        validate_path(path)
+            .unwrap()
+            .errors()
+            .any(|i| i.message.contains("WKT"))
+    };
+    assert!(is_missing(&path));
+
+    // without a CRS the file can not be rewritten
+    let summary = repair(&path, &RepairOptions::default()).unwrap();
+    assert!(!summary.rewritten);
+    assert!(is_missing(&path));
+
+    let mut options = RepairOptions::default();
+    options.convert.fallback_crs = Some(CrsOverride::Epsg(2056));
+    let summary = repair(&path, &options).unwrap();
+    assert!(summary.rewritten);
+    let report = validate_path(&path).unwrap();
+    assert!(report.is_valid(), "{:?}", report.issues);
+    assert_eq!(CopcReader::from_path(&path).unwrap().crs_epsg(), Some(2056));
+    assert_eq!(point_count(&path), 2000);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
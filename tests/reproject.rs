@@ -0,0 +1,200 @@
+#![cfg(all(feature = "writer", feature = "reproject"))]
+
+//! Reprojection of query results and of query bounds.
+
+use std::io::Cursor;
+
+use copc_rs::{
+    Bounds, BoundsSelection, Chain, CopcReader, CopcWriterBuilder, CrsOverride, Ellipsoid,
+    Geocentric, Inverse, LodSelection, PointTransform, TilesOptions, TransverseMercator, Vector,
+    WebMercator,
+};
+use las::point::Format;
+use las::{Builder, Point, Transform};
+
+fn v(x: f64, y: f64) -> Vector<f64> {
+    Vector { x, y, z: 0. }
+}
+
+#[test]
+fn transverse_mercator_matches_reference_values() {
+    // on the central meridian the northing is the scaled meridian arc
+    let p = TransverseMercator::utm(32, true).forward(v(9., 48.));
+    assert!((p.x - 500_000.).abs() < 1e-3);
+    assert!((p.y - 5_316_300.224).abs() < 1e-3);
+
+    // reference value of the PROJ test suite
+    let utm = TransverseMercator::new(Ellipsoid::GRS80, 9., 0., 0.9996, 500_000., 0.);
+    let p = utm.forward(v(12., 55.));
+    assert!((p.x - 691_875.632).abs() < 1e-3);
+    assert!((p.y - 6_098_907.825).abs() < 1e-3);
+
+    let back = utm.inverse(p);
+    assert!((back.x - 12.).abs() < 1e-9);
+    assert!((back.y - 55.).abs() < 1e-9);
+
+    let p = WebMercator.forward(v(180., 0.));
+    assert!((p.x - 20_037_508.343).abs() < 1e-3);
+    let back = WebMercator.inverse(WebMercator.forward(v(12., 55.)));
+    assert!((back.x - 12.).abs() < 1e-9);
+    assert!((back.y - 55.).abs() < 1e-9);
+}
+
+#[test]
+fn geocentric_round_trip() {
+    let p = Geocentric::WGS84.forward(v(0., 0.));
+    assert!((p.x - 6_378_137.).abs() < 1e-6 && p.y.abs() < 1e-6 && p.z.abs() < 1e-6);
+    let p = Geocentric::WGS84.forward(v(0., 90.));
+    assert!(p.x.abs() < 1e-6 && (p.z - 6_356_752.314_245).abs() < 1e-3);
+
+    let p = Vector {
+        x: 8.54,
+        y: 47.37,
+        z: 408.,
+    };
+    let back = Geocentric::WGS84.inverse(Geocentric::WGS84.forward(p));
+    assert!((back.x - p.x).abs() < 1e-9 && (back.y - p.y).abs() < 1e-9);
+    assert!((back.z - p.z).abs() < 1e-4);
+}
+
+fn header() -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(6).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 691_800.0;
+    raw.max_x = 692_000.0;
+    raw.min_y = 6_098_800.0;
+    raw.max_y = 6_099_000.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    Builder::new(raw).unwrap().into_header().unwrap()
+}
+
+#[test]
+fn transformed_query_in_the_target_crs() {
+    let points: Vec<Point> = (0..2000)
+        .map(|i| Point {
+            x: 691_800. + (i % 40) as f64 * 5.,
+            y: 6_098_800. + (i / 40) as f64 * 4.,
+            z: (i % 100) as f64,
+            gps_time: Some(i as f64),
+            ..Default::default()
+        })
+        .collect();
+
+    let mut buf = Cursor::new(Vec::<u8>::new());
+    {
+        let mut w = CopcWriterBuilder::new(header())
+            .crs(CrsOverride::Epsg(32632))
+            .min_node_size(10)
+            .max_node_size(100)
+            .seed(1)
+            .build(&mut buf)
+            .unwrap();
+        w.write(points.clone(), points.len() as i32).unwrap();
+    }
+
+    let transform = Chain(Inverse(TransverseMercator::utm(32, true)), WebMercator);
+    let center = transform.forward(v(691_900., 6_098_900.));
+    let query = Bounds {
+        min: Vector {
+            x: center.x - 60.,
+            y: center.y - 80.,
+            z: 0.,
+        },
+        max: Vector {
+            x: center.x + 60.,
+            y: center.y + 80.,
+            z: 100.,
+        },
+    };
+
+    let expected = points
+        .iter()
+        .map(|p| {
+            transform.forward(Vector {
+                x: p.x,
+                y: p.y,
+                z: p.z,
+            })
+        })
+        .filter(|p| {
+            p.x >= query.min.x && p.x <= query.max.x && p.y >= query.min.y && p.y <= query.max.y
+        })
+        .count();
+    assert!(expected > 0 && expected < points.len());
+
+    buf.set_position(0);
+    let mut r = CopcReader::new(buf).unwrap();
+    let read: Vec<Point> = r
+        .transformed_points(LodSelection::All, BoundsSelection::Within(query), transform)
+        .unwrap()
+        .collect();
+    assert_eq!(read.len(), expected);
+    assert!(read.iter().all(|p| p.x >= query.min.x
+        && p.x <= query.max.x
+        && p.y >= query.min.y
+        && p.y <= query.max.y));
+}
+
+#[test]
+fn tiles_in_ecef() {
+    let mut buf = Cursor::new(Vec::<u8>::new());
+    {
+        let mut w = CopcWriterBuilder::new(header())
+            .crs(CrsOverride::Epsg(32632))
+            .min_node_size(10)
+            .max_node_size(100)
+            .seed(1)
+            .build(&mut buf)
+            .unwrap();
+        let points = (0..2000).map(|i| Point {
+            x: 691_800. + (i % 40) as f64 * 5.,
+            y: 6_098_800. + (i / 40) as f64 * 4.,
+            z: (i % 100) as f64,
+            ..Default::default()
+        });
+        w.write(points, 2000).unwrap();
+    }
+    buf.set_position(0);
+    let mut r = CopcReader::new(buf).unwrap();
+
+    let dir = std::env::temp_dir().join(format!("copc-rs-tiles-ecef-{}", std::process::id()));
+    let transform = Chain(
+        Inverse(TransverseMercator::utm(32, true)),
+        Geocentric::WGS84,
+    );
+    let summary = r
+        .export_tiles_transformed(&dir, &TilesOptions::default(), transform)
+        .unwrap();
+    assert_eq!(summary.points_written, 2000);
+
+    // the root box is centered near the ECEF position of the center of the points
+    let center = transform.forward(Vector {
+        x: 691_900.,
+        y: 6_098_900.,
+        z: 50.,
+    });
+    let tileset = std::fs::read_to_string(dir.join("tileset.json")).unwrap();
+    let start = tileset.find(r#""box": ["#).unwrap() + 8;
+    let values: Vec<f64> = tileset[start..]
+        .split(']')
+        .next()
+        .unwrap()
+        .split(", ")
+        .map(|v| v.parse().unwrap())
+        .collect();
+    assert_eq!(values.len(), 12);
+    let distance = ((values[0] - center.x).powi(2)
+        + (values[1] - center.y).powi(2)
+        + (values[2] - center.z).powi(2))
+    .sqrt();
+    assert!(distance < r.copc_info().halfsize, "{distance}");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,238 @@
+#![cfg(feature = "writer")]
+
+//! Statistics of the point attributes.
+
+use copc_rs::{
+    recopc, BoundsSelection, ConvertOptions, CopcReader, CopcWriterBuilder, CrsOverride, Error,
+    LodSelection, PointAttribute, StatisticsOptions,
+};
+use las::point::{Classification, Format};
+use las::{Bounds, Builder, Point, Transform, Vector};
+
+fn header() -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(6).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 0.0;
+    raw.max_x = 100.0;
+    raw.min_y = 0.0;
+    raw.max_y = 100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    Builder::new(raw).unwrap().into_header().unwrap()
+}
+
+#[test]
+fn file_node_and_query_statistics() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-statistics-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("statistics.copc.laz");
+    let points: Vec<Point> = (0..10_000)
+        .map(|i| Point {
+            x: (i % 100) as f64 + 0.5,
+            y: (i / 100) as f64 + 0.5,
+            z: (i % 50) as f64,
+            intensity: (i % 1000) as u16,
+            return_number: 1 + (i % 3) as u8,
+            number_of_returns: 3,
+            classification: if i % 4 == 0 {
+                Classification::Ground
+            } else {
+                Classification::LowVegetation
+            },
+            gps_time: Some(i as f64),
+            ..Default::default()
+        })
+        .collect();
+    {
+        let mut w = CopcWriterBuilder::new(header())
+            .crs(CrsOverride::Epsg(2056))
+            .min_node_size(50)
+            .max_node_size(500)
+            .seed(1)
+            .statistics(StatisticsOptions::default())
+            .build_path(&path)
+            .unwrap();
+        w.write(points.clone(), points.len() as i32).unwrap();
+    }
+    let mut reader = CopcReader::from_path(&path).unwrap();
+    let options = StatisticsOptions::default();
+    let statistics = reader
+        .statistics(LodSelection::All, BoundsSelection::All, &options)
+        .unwrap();
+
+    // the whole file
+    let total = &statistics.total;
+    assert_eq!(total.point_count, points.len() as u64);
+    let z = total.get(&PointAttribute::Z).unwrap();
+    assert_eq!((z.min, z.max), (0., 49.));
+    assert!((z.mean().unwrap() - 24.5).abs() < 1e-9);
+    let classification = total.get(&PointAttribute::Classification).unwrap();
+    let histogram = classification.histogram.as_ref().unwrap();
+    assert_eq!(histogram.counts[2], 2_500);
+    assert_eq!(histogram.counts[3], 7_500);
+    assert_eq!(histogram.bin_range(2), 2.0..3.0);
+    let returns = total.get(&PointAttribute::ReturnNumber).unwrap();
+    assert_eq!(
+        returns.histogram.as_ref().unwrap().counts[1..4],
+        [3334, 3333, 3333]
+    );
+    let intensity = total.get(&PointAttribute::Intensity).unwrap();
+    assert_eq!((intensity.min, intensity.max), (0., 999.));
+    assert_eq!(
+        intensity
+            .histogram
+            .as_ref()
+            .unwrap()
+            .counts
+            .iter()
+            .sum::<u64>(),
+        points.len() as u64
+    );
+    assert!(total.get(&PointAttribute::GpsTime).is_none());
+
+    // the nodes add up to the whole file
+    let entries = reader.hierarchy_entries().clone();
+    assert!(statistics.nodes.len() > 1);
+    for (key, node) in &statistics.nodes {
+        assert_eq!(node.point_count, entries[key].point_count as u64);
+    }
+    let node_points: u64 = statistics.nodes.values().map(|n| n.point_count).sum();
+    assert_eq!(node_points, points.len() as u64);
+
+    // a query only counts its points
+    let bounds = Bounds {
+        min: Vector {
+            x: 0.,
+            y: 0.,
+            z: 0.,
+        },
+        max: Vector {
+            x: 50.,
+            y: 50.,
+            z: 100.,
+        },
+    };
+    let query = reader
+        .statistics(
+            LodSelection::All,
+            BoundsSelection::Within(bounds),
+            &StatisticsOptions {
+                attributes: Some(vec![PointAttribute::X, PointAttribute::GpsTime]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(query.total.point_count, 2_500);
+    let x = query.total.get(&PointAttribute::X).unwrap();
+    assert_eq!((x.min, x.max), (0.5, 49.5));
+    assert!(query
+        .total
+        .get(&PointAttribute::GpsTime)
+        .unwrap()
+        .histogram
+        .is_none());
+
+    // the statistics stored at write time
+    assert_eq!(reader.stored_statistics().unwrap(), Some(statistics));
+
+    assert!(matches!(
+        reader.statistics(
+            LodSelection::All,
+            BoundsSelection::All,
+            &StatisticsOptions {
+                attributes: Some(vec![PointAttribute::Extra("Amplitude".into())]),
+                ..Default::default()
+            },
+        ),
+        Err(Error::UnknownAttribute(_))
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_stored_statistics_are_rejected() {
+    let mut buf = std::io::Cursor::new(Vec::<u8>::new());
+    {
+        let mut w = CopcWriterBuilder::new(header())
+            .crs(CrsOverride::Epsg(2056))
+            .statistics(StatisticsOptions {
+                attributes: Some(vec![PointAttribute::Z]),
+                ..Default::default()
+            })
+            .build(&mut buf)
+            .unwrap();
+        let points = (0..100).map(|i| Point {
+            x: i as f64,
+            y: i as f64,
+            z: i as f64,
+            gps_time: Some(i as f64),
+            ..Default::default()
+        });
+        w.write(points, 100).unwrap();
+    }
+    let file = buf.into_inner();
+    // the data follows the user id, record id, length and description of the EVLR header
+    let user_id = file.windows(8).position(|w| w == b"copc-rs\0").unwrap();
+    let data = user_id + 16 + 2 + 8 + 32;
+
+    // the histogram of z with too many bins, after the attribute count, name, min and width
+    let mut bins = file.clone();
+    bins[data + 4 + 2 + 16..data + 4 + 2 + 16 + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    // no attributes and more nodes than the data holds
+    let mut nodes = file;
+    nodes[data..data + 4].copy_from_slice(&0u32.to_le_bytes());
+    nodes[data + 4..data + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+
+    for file in [bins, nodes] {
+        let reader = CopcReader::new(std::io::Cursor::new(file)).unwrap();
+        assert!(matches!(
+            reader.stored_statistics(),
+            Err(Error::InvalidStatisticsVlr(_))
+        ));
+    }
+}
+
+#[test]
+fn statistics_of_the_source_are_not_copied() {
+    let dir =
+        std::env::temp_dir().join(format!("copc-rs-statistics-recopc-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.copc.laz");
+    {
+        let mut w = CopcWriterBuilder::new(header())
+            .crs(CrsOverride::Epsg(2056))
+            .statistics(StatisticsOptions::default())
+            .build_path(&input)
+            .unwrap();
+        let points = (0..1000).map(|i| Point {
+            x: (i % 100) as f64,
+            y: (i / 10) as f64,
+            z: (i % 7) as f64,
+            gps_time: Some(i as f64),
+            ..Default::default()
+        });
+        w.write(points, 1000).unwrap();
+    }
+    let output = dir.join("output.copc.laz");
+    recopc(
+        &input,
+        &output,
+        &ConvertOptions {
+            min_node_size: 10,
+            max_node_size: 100,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let reader = CopcReader::from_path(&output).unwrap();
+    assert_eq!(reader.stored_statistics().unwrap(), None);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
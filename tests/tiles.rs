@@ -0,0 +1,158 @@
+#![cfg(feature = "writer")]
+
+//! Export of the octree to a 3D Tiles tileset.
+
+use std::io::Cursor;
+
+use byteorder::{ByteOrder, LittleEndian};
+use copc_rs::{CopcReader, CopcWriterBuilder, CrsOverride, TileFormat, TilesOptions};
+use las::point::Format;
+use las::{Builder, Color, Point, Transform, Vector};
+
+fn header() -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(7).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 1000.0;
+    raw.max_x = 1100.0;
+    raw.min_y = 2000.0;
+    raw.max_y = 2100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    Builder::new(raw).unwrap().into_header().unwrap()
+}
+
+fn reader() -> CopcReader<Cursor<Vec<u8>>> {
+    let mut buf = Cursor::new(Vec::<u8>::new());
+    {
+        let mut w = CopcWriterBuilder::new(header())
+            .crs(CrsOverride::Epsg(2056))
+            .min_node_size(10)
+            .max_node_size(100)
+            .seed(1)
+            .build(&mut buf)
+            .unwrap();
+        let points = (0..5000).map(|i| Point {
+            x: 1000. + (i % 100) as f64,
+            y: 2000. + (i / 50) as f64,
+            z: (i % 7) as f64,
+            intensity: i as u16,
+            color: Some(Color::new(0xff00, 0x8000, 0)),
+            ..Default::default()
+        });
+        w.write(points, 5000).unwrap();
+    }
+    CopcReader::new(Cursor::new(buf.into_inner())).unwrap()
+}
+
+/// The value of the first `"name": value` member in `json`
+fn member<'a>(json: &'a str, name: &str) -> &'a str {
+    let start = json.find(&format!(r#""{name}": "#)).unwrap() + name.len() + 4;
+    let end = json[start..].find([',', '}']).unwrap();
+    &json[start..start + end]
+}
+
+#[test]
+fn export_to_pnts_tiles() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-tiles-{}", std::process::id()));
+    let mut r = reader();
+    let summary = r.export_tiles(&dir, &TilesOptions::default()).unwrap();
+    assert_eq!(summary.points_written, 5000);
+    assert!(summary.depth >= 1);
+
+    let tileset = std::fs::read_to_string(dir.join("tileset.json")).unwrap();
+    assert!(tileset.starts_with(r#"{"asset": {"version": "1.0", "generator": "copc-rs "#));
+    let spacing = r.copc_info().spacing;
+    assert_eq!(
+        member(&tileset, "geometricError").parse::<f64>().unwrap(),
+        spacing * 2.
+    );
+    assert!(tileset.contains(&format!(
+        r#""geometricError": {spacing}, "refine": "ADD", "content": {{"uri": "tiles/0-0-0-0.pnts"}}"#
+    )));
+    assert!(tileset.contains(&format!(r#""geometricError": {}"#, spacing / 2.)));
+    assert_eq!(tileset.matches(r#""uri": "#).count(), summary.tiles_written);
+
+    let mut points = 0;
+    for entry in std::fs::read_dir(dir.join("tiles")).unwrap() {
+        let bytes = std::fs::read(entry.unwrap().path()).unwrap();
+        assert_eq!(&bytes[..4], b"pnts");
+        let header: Vec<usize> = bytes[4..28]
+            .chunks(4)
+            .map(|b| LittleEndian::read_u32(b) as usize)
+            .collect();
+        assert_eq!(header[0], 1);
+        assert_eq!(header[1], bytes.len());
+        // the JSON ends and the binary bodies start 8-byte aligned
+        assert_eq!((28 + header[2]) % 8, 0);
+        assert!(header[3..].iter().all(|length| length % 8 == 0));
+        let feature_json = std::str::from_utf8(&bytes[28..28 + header[2]]).unwrap();
+        let count: usize = member(feature_json, "POINTS_LENGTH").parse().unwrap();
+        assert!(feature_json.contains(&format!(r#""RGB": {{"byteOffset": {}}}"#, count * 12)));
+        let binary = &bytes[28 + header[2]..];
+        assert_eq!(&binary[count * 12..count * 12 + 3], &[0xff, 0x80, 0]);
+        let batch_json = std::str::from_utf8(&binary[header[3]..header[3] + header[4]]).unwrap();
+        assert!(batch_json
+            .contains(r#""Intensity": {"byteOffset": 0, "componentType": "UNSIGNED_SHORT""#));
+
+        // the positions relative to the center are within the tile
+        let rtc = member(feature_json, "RTC_CENTER");
+        assert!(rtc.starts_with('['));
+        for position in binary[..count * 12].chunks(12) {
+            for axis in 0..3 {
+                assert!(LittleEndian::read_f32(&position[axis * 4..]).abs() <= 50.01);
+            }
+        }
+        points += count;
+    }
+    assert_eq!(points, 5000);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn export_to_glb_tiles() {
+    let dir = std::env::temp_dir().join(format!("copc-rs-tiles-glb-{}", std::process::id()));
+    let summary = reader()
+        .export_tiles(
+            &dir,
+            &TilesOptions {
+                format: TileFormat::Glb,
+            },
+        )
+        .unwrap();
+
+    let tileset = std::fs::read_to_string(dir.join("tileset.json")).unwrap();
+    assert!(tileset.starts_with(r#"{"asset": {"version": "1.1""#));
+    assert!(tileset.contains(r#""uri": "tiles/0-0-0-0.glb""#));
+
+    let mut points = 0;
+    for entry in std::fs::read_dir(dir.join("tiles")).unwrap() {
+        let bytes = std::fs::read(entry.unwrap().path()).unwrap();
+        assert_eq!(&bytes[..4], b"glTF");
+        assert_eq!(LittleEndian::read_u32(&bytes[4..]), 2);
+        assert_eq!(LittleEndian::read_u32(&bytes[8..]) as usize, bytes.len());
+        let json_length = LittleEndian::read_u32(&bytes[12..]) as usize;
+        assert_eq!(json_length % 4, 0);
+        assert_eq!(&bytes[16..20], b"JSON");
+        let json = std::str::from_utf8(&bytes[20..20 + json_length]).unwrap();
+        assert!(json.contains(r#""attributes": {"POSITION": 0, "COLOR_0": 1}, "mode": 0"#));
+        let binary = &bytes[20 + json_length..];
+        assert_eq!(&binary[4..8], b"BIN\0");
+        let count: usize = member(json, "count").parse().unwrap();
+        assert_eq!(
+            LittleEndian::read_u32(binary) as usize,
+            count * 12 + count * 4
+        );
+        points += count;
+    }
+    assert_eq!(points as u64, summary.points_written);
+    assert_eq!(points, 5000);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
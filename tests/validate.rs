@@ -0,0 +1,122 @@
+#![cfg(feature = "writer")]
+
+//! Conformance checks of `validate`.
+
+use std::io::Cursor;
+
+use copc_rs::{
+    validate, CopcReader, CopcWriterBuilder, CrsOverride, Error, Severity, ValidationCheck,
+};
+use las::point::Format;
+use las::{Builder, Point, Transform, Vector};
+
+fn header() -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(6).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 0.0;
+    raw.max_x = 100.0;
+    raw.min_y = 0.0;
+    raw.max_y = 100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    Builder::new(raw).unwrap().into_header().unwrap()
+}
+
+fn copc_file() -> Vec<u8> {
+    let mut buf = Cursor::new(Vec::<u8>::new());
+    {
+        let mut w = CopcWriterBuilder::new(header())
+            .crs(CrsOverride::Epsg(2056))
+            .min_node_size(10)
+            .max_node_size(100)
+            .seed(1)
+            .build(&mut buf)
+            .unwrap();
+        let points = (0..2000).map(|i| Point {
+            x: (i % 100) as f64,
+            y: (i / 20) as f64,
+            z: (i % 7) as f64,
+            gps_time: Some(i as f64),
+            ..Default::default()
+        });
+        w.write(points, 2000).unwrap();
+    }
+    buf.into_inner()
+}
+
+#[test]
+fn written_file_is_valid() {
+    let report = validate(Cursor::new(copc_file())).unwrap();
+    assert!(report.is_valid(), "{:?}", report.issues);
+    assert_eq!(report.warnings().count(), 0, "{:?}", report.issues);
+    assert_eq!(report.points_checked, 2000);
+    assert!(report.nodes_checked > 1);
+}
+
+#[test]
+fn corrupted_copc_info_is_reported() {
+    let mut data = copc_file();
+    // header, vlr header, then center, halfsize and spacing precede the root hierarchy offset
+    let root_hier_offset = 375 + 54 + 5 * 8;
+    data[root_hier_offset] ^= 1;
+    // a reserved field
+    data[375 + 54 + 100] = 1;
+
+    let report = validate(Cursor::new(data)).unwrap();
+    assert!(!report.is_valid());
+    assert!(report
+        .errors()
+        .any(|i| i.check == ValidationCheck::CopcInfo && i.message.contains("root hierarchy")));
+    assert!(report
+        .warnings()
+        .any(|i| i.check == ValidationCheck::CopcInfo && i.severity == Severity::Warning));
+}
+
+#[test]
+fn hierarchy_cycle_is_reported() {
+    let mut data = copc_file();
+    // the root hierarchy offset and size of the COPC info
+    let info = 375 + 54 + 5 * 8;
+    let root_hier_offset = u64::from_le_bytes(data[info..info + 8].try_into().unwrap());
+    let root_hier_size = u64::from_le_bytes(data[info + 8..info + 16].try_into().unwrap());
+    // the first entry of the root page points back to the root page
+    let entry = root_hier_offset as usize + 16;
+    data[entry..entry + 8].copy_from_slice(&root_hier_offset.to_le_bytes());
+    data[entry + 8..entry + 12].copy_from_slice(&(root_hier_size as i32).to_le_bytes());
+    data[entry + 12..entry + 16].copy_from_slice(&(-1i32).to_le_bytes());
+
+    let report = validate(Cursor::new(data.clone())).unwrap();
+    assert!(report
+        .errors()
+        .any(|i| i.check == ValidationCheck::Hierarchy && i.message.contains("cycle")));
+    assert!(matches!(
+        CopcReader::new(Cursor::new(data.clone())),
+        Err(Error::InvalidHierarchy(_))
+    ));
+
+    // a child page with a negative size
+    data[entry + 8..entry + 12].copy_from_slice(&(-32i32).to_le_bytes());
+    assert!(matches!(
+        CopcReader::new(Cursor::new(data)),
+        Err(Error::InvalidHierarchy(_))
+    ));
+}
+
+#[test]
+fn short_copc_info_is_reported() {
+    let mut data = copc_file();
+    // the record length of the COPC info VLR, the rest of its data is read as the next VLR
+    let record_length = 375 + 2 + 16 + 2;
+    data[record_length..record_length + 2].copy_from_slice(&40u16.to_le_bytes());
+
+    let report = validate(Cursor::new(data)).unwrap();
+    assert!(report
+        .errors()
+        .any(|i| i.check == ValidationCheck::CopcInfo && i.message.contains("40 bytes")));
+}
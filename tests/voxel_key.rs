@@ -0,0 +1,132 @@
+//! Navigation, containment and encodings of voxel keys.
+
+use copc_rs::{Bounds, Error, Vector, VoxelKey};
+
+fn key(level: i32, x: i32, y: i32, z: i32) -> VoxelKey {
+    VoxelKey { level, x, y, z }
+}
+
+#[test]
+fn parent_children_and_ancestors() {
+    let k = key(3, 5, 2, 7);
+    assert_eq!(k.parent(), Some(key(2, 2, 1, 3)));
+    assert_eq!(VoxelKey::root().parent(), None);
+    assert_eq!(
+        k.ancestors().collect::<Vec<_>>(),
+        vec![key(2, 2, 1, 3), key(1, 1, 0, 1), VoxelKey::root()]
+    );
+    assert_eq!(k.ancestor(1), Some(key(1, 1, 0, 1)));
+    assert_eq!(k.ancestor(3), Some(k.clone()));
+    assert_eq!(k.ancestor(4), None);
+
+    let children = k.children();
+    assert_eq!(children.len(), 8);
+    assert_eq!(children[0], key(4, 10, 4, 14));
+    assert_eq!(children[7], key(4, 11, 5, 15));
+    assert!(children.iter().all(|c| c.parent().as_ref() == Some(&k)));
+    assert_eq!(k.child(5), key(4, 11, 4, 15));
+
+    let siblings = children[3].siblings();
+    assert_eq!(siblings.len(), 7);
+    assert!(!siblings.contains(&children[3]));
+    assert!(VoxelKey::root().siblings().is_empty());
+
+    assert!(k.contains(&k));
+    assert!(k.ancestor(1).unwrap().contains(&k));
+    assert!(k.contains(&children[2].child(6)));
+    assert!(!k.contains(&k.parent().unwrap()));
+    assert!(!k.contains(&key(3, 5, 2, 6)));
+}
+
+#[test]
+fn neighbours() {
+    assert!(VoxelKey::root().face_neighbours().is_empty());
+    // a corner has three face and three edge neighbours
+    let corner = key(2, 0, 0, 0);
+    assert_eq!(
+        corner.face_neighbours(),
+        vec![key(2, 1, 0, 0), key(2, 0, 1, 0), key(2, 0, 0, 1)]
+    );
+    assert_eq!(corner.edge_neighbours().len(), 3);
+    assert!(corner.edge_neighbours().contains(&key(2, 1, 1, 0)));
+    // an inner key has all of them
+    let inner = key(2, 1, 2, 1);
+    assert_eq!(inner.face_neighbours().len(), 6);
+    assert_eq!(inner.edge_neighbours().len(), 12);
+    assert!(inner
+        .edge_neighbours()
+        .iter()
+        .all(|n| [n.x - 1, n.y - 2, n.z - 1]
+            .iter()
+            .filter(|d| **d != 0)
+            .count()
+            == 2));
+    assert_eq!(inner.neighbour(2, 1, -1), Some(key(2, 3, 3, 0)));
+    assert_eq!(inner.neighbour(0, 2, 0), None);
+}
+
+#[test]
+fn key_of_a_point() {
+    let root_bounds = Bounds {
+        min: Vector {
+            x: 0.,
+            y: 0.,
+            z: 0.,
+        },
+        max: Vector {
+            x: 8.,
+            y: 8.,
+            z: 8.,
+        },
+    };
+    let point = Vector {
+        x: 5.5,
+        y: 0.2,
+        z: 8.,
+    };
+    let k = VoxelKey::from_point(&point, 3, &root_bounds).unwrap();
+    assert_eq!(k, key(3, 5, 0, 7));
+    let bounds = k.bounds(&root_bounds);
+    assert_eq!((bounds.min.x, bounds.max.x), (5., 6.));
+    let deep = key(40, 1, 0, 0).bounds(&root_bounds);
+    assert!(deep.min.x > 0. && deep.max.x > deep.min.x);
+    assert_eq!(
+        VoxelKey::from_point(&point, 0, &root_bounds),
+        Some(VoxelKey::root())
+    );
+    let outside = Vector {
+        x: -0.1,
+        y: 1.,
+        z: 1.,
+    };
+    assert_eq!(VoxelKey::from_point(&outside, 3, &root_bounds), None);
+}
+
+#[test]
+fn morton_codes_and_strings() {
+    let k = key(3, 5, 2, 7);
+    // x 101, y 010, z 111 interleaved as zyx triplets: 101 110 101
+    assert_eq!(k.morton_code(), 0b101_110_101);
+    assert_eq!(
+        VoxelKey::from_morton_code(3, k.morton_code()),
+        Some(k.clone())
+    );
+    assert_eq!(k.child(6).morton_code(), (k.morton_code() << 3) | 6);
+    let deep = key(21, (1 << 21) - 1, 12345, 1 << 20);
+    assert_eq!(
+        VoxelKey::from_morton_code(21, deep.morton_code()),
+        Some(deep)
+    );
+    assert_eq!(VoxelKey::from_morton_code(2, k.morton_code()), None);
+    assert_eq!(VoxelKey::from_morton_code(22, 0), None);
+    assert_eq!(VoxelKey::from_morton_code(-1, 0), None);
+
+    assert_eq!(key(3, 1, 2, 0).to_string(), "3-1-2-0");
+    assert_eq!("3-1-2-0".parse::<VoxelKey>().unwrap(), key(3, 1, 2, 0));
+    for invalid in ["3-1-2", "3-1-2-0-4", "a-1-2-0", "", "31-0-0-0", "40-1-2-0"] {
+        assert!(matches!(
+            invalid.parse::<VoxelKey>(),
+            Err(Error::InvalidVoxelKey(_))
+        ));
+    }
+}
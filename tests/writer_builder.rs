@@ -0,0 +1,137 @@
+#![cfg(feature = "writer")]
+
+//! Writer configuration through `CopcWriterBuilder`: typed validation errors,
+//! depth limited octrees and hierarchies split over several pages.
+
+use std::io::Cursor;
+
+use copc_rs::{
+    BoundsSelection, CopcReader, CopcWriterBuilder, Error, LodSelection, SamplingStrategy,
+    WriterConfigError,
+};
+use las::point::Format;
+use las::{Builder, Point, Transform, Vector, Vlr};
+
+const WKT: &[u8] = b"GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",SPHEROID[\"WGS 84\",6378137,298.257223563]],PRIMEM[\"Greenwich\",0],UNIT[\"degree\",0.0174532925199433]]";
+
+fn header() -> las::Header {
+    let mut b = Builder::from((1u8, 4u8));
+    b.point_format = Format::new(6).unwrap();
+    let t = Transform {
+        scale: 0.01,
+        offset: 0.0,
+    };
+    b.transforms = Vector { x: t, y: t, z: t };
+    let mut raw = b.into_header().unwrap().into_raw().unwrap();
+    raw.min_x = 0.0;
+    raw.max_x = 100.0;
+    raw.min_y = 0.0;
+    raw.max_y = 100.0;
+    raw.min_z = 0.0;
+    raw.max_z = 100.0;
+    let mut b2 = Builder::new(raw).unwrap();
+    b2.vlrs.push(Vlr {
+        user_id: "LASF_Projection".into(),
+        record_id: 2112,
+        description: String::new(),
+        data: WKT.to_vec(),
+    });
+    b2.into_header().unwrap()
+}
+
+fn points(n: usize) -> Vec<Point> {
+    (0..n)
+        .map(|i| Point {
+            x: (i % 100) as f64,
+            y: ((i / 100) % 100) as f64,
+            z: ((i * 7) % 100) as f64,
+            gps_time: Some(1000.0 + i as f64),
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[test]
+fn invalid_options_are_reported_as_typed_errors() {
+    let err = CopcWriterBuilder::new(header())
+        .min_node_size(100)
+        .max_node_size(100)
+        .build(Cursor::new(Vec::<u8>::new()))
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err,
+        Error::InvalidWriterConfig(WriterConfigError::InvalidMaxNodeSize { min: 100, max: 100 })
+    ));
+
+    let err = CopcWriterBuilder::new(header())
+        .hierarchy_page_size(4)
+        .build(Cursor::new(Vec::<u8>::new()))
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err,
+        Error::InvalidWriterConfig(WriterConfigError::InvalidHierarchyPageSize(4))
+    ));
+}
+
+#[test]
+fn max_depth_limits_the_octree() {
+    let pts = points(5000);
+    let n = pts.len();
+
+    let mut buf = Cursor::new(Vec::<u8>::new());
+    {
+        let mut w = CopcWriterBuilder::new(header())
+            .min_node_size(10)
+            .max_node_size(100)
+            .max_depth(1)
+            .sampling(SamplingStrategy::Greedy)
+            .build(&mut buf)
+            .unwrap();
+        w.write(pts, n as i32).unwrap();
+        assert!(w
+            .hierarchy_entries()
+            .entries
+            .iter()
+            .all(|e| e.key.level <= 1));
+    }
+
+    buf.set_position(0);
+    let mut r = CopcReader::new(buf).unwrap();
+    let read = r
+        .points(LodSelection::All, BoundsSelection::All)
+        .unwrap()
+        .count();
+    assert_eq!(read, n, "every written point must round-trip");
+}
+
+#[test]
+fn paged_hierarchy_round_trips() {
+    let pts = points(5000);
+    let n = pts.len();
+
+    let mut buf = Cursor::new(Vec::<u8>::new());
+    let written_entries = {
+        let mut w = CopcWriterBuilder::new(header())
+            .min_node_size(10)
+            .max_node_size(50)
+            .hierarchy_page_size(9)
+            .sampling(SamplingStrategy::Greedy)
+            .build(&mut buf)
+            .unwrap();
+        w.write(pts, n as i32).unwrap();
+        assert_eq!(w.copc_info().root_hier_size % 32, 0);
+        assert!(w.copc_info().root_hier_size <= 9 * 32);
+        w.hierarchy_entries().entries.len()
+    };
+
+    buf.set_position(0);
+    let mut r = CopcReader::new(buf).unwrap();
+    assert!(r.num_entries() >= written_entries);
+    let read = r
+        .points(LodSelection::All, BoundsSelection::All)
+        .unwrap()
+        .count();
+    assert_eq!(read, n, "every written point must round-trip");
+}